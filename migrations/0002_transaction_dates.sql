@@ -0,0 +1,12 @@
+ALTER TABLE expenses ADD COLUMN IF NOT EXISTS occurred_at TIMESTAMPTZ;
+UPDATE expenses SET occurred_at = now() WHERE occurred_at IS NULL;
+ALTER TABLE expenses ALTER COLUMN occurred_at SET DEFAULT now();
+ALTER TABLE expenses ALTER COLUMN occurred_at SET NOT NULL;
+
+ALTER TABLE income ADD COLUMN IF NOT EXISTS occurred_at TIMESTAMPTZ;
+UPDATE income SET occurred_at = now() WHERE occurred_at IS NULL;
+ALTER TABLE income ALTER COLUMN occurred_at SET DEFAULT now();
+ALTER TABLE income ALTER COLUMN occurred_at SET NOT NULL;
+
+CREATE INDEX IF NOT EXISTS expenses_user_occurred_at ON expenses (user_id, occurred_at);
+CREATE INDEX IF NOT EXISTS income_user_occurred_at ON income (user_id, occurred_at);
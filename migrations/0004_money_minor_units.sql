@@ -0,0 +1,5 @@
+-- Amounts were stored as whole units, from now on they are kopecks/cents.
+UPDATE accounts SET balance = balance * 100;
+UPDATE expenses SET amount = amount * 100;
+UPDATE income SET amount = amount * 100;
+UPDATE transfers SET amount = amount * 100;
@@ -0,0 +1,14 @@
+ALTER TABLE accounts ADD COLUMN IF NOT EXISTS currency TEXT NOT NULL DEFAULT 'RUB';
+
+CREATE TABLE IF NOT EXISTS exchange_rates (
+    user_id BIGINT NOT NULL,
+    from_currency TEXT NOT NULL,
+    to_currency TEXT NOT NULL,
+    rate DOUBLE PRECISION NOT NULL,
+    PRIMARY KEY (user_id, from_currency, to_currency)
+);
+
+CREATE TABLE IF NOT EXISTS user_settings (
+    user_id BIGINT PRIMARY KEY,
+    base_currency TEXT NOT NULL DEFAULT 'RUB'
+);
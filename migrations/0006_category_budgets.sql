@@ -0,0 +1,2 @@
+ALTER TABLE categories ADD COLUMN IF NOT EXISTS budget_limit BIGINT;
+ALTER TABLE categories ADD COLUMN IF NOT EXISTS budget_period TEXT;
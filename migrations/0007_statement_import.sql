@@ -0,0 +1,13 @@
+ALTER TABLE expenses ADD COLUMN IF NOT EXISTS import_ref TEXT;
+ALTER TABLE income ADD COLUMN IF NOT EXISTS import_ref TEXT;
+
+CREATE INDEX IF NOT EXISTS expenses_user_import_ref ON expenses (user_id, import_ref);
+CREATE INDEX IF NOT EXISTS income_user_import_ref ON income (user_id, import_ref);
+
+CREATE TABLE IF NOT EXISTS import_mappings (
+    user_id BIGINT PRIMARY KEY,
+    date_column BIGINT NOT NULL,
+    amount_column BIGINT NOT NULL,
+    category_column BIGINT NOT NULL,
+    description_column BIGINT
+);
@@ -0,0 +1,26 @@
+ALTER TABLE expenses ADD COLUMN IF NOT EXISTS fingerprint TEXT;
+ALTER TABLE expenses ADD COLUMN IF NOT EXISTS created_at TIMESTAMPTZ;
+UPDATE expenses
+SET fingerprint = account_id || '|' || amount || '|' || to_char(occurred_at, 'YYYY-MM-DD') || '|' || COALESCE(import_ref, ''),
+    created_at = occurred_at
+WHERE fingerprint IS NULL;
+ALTER TABLE expenses ALTER COLUMN fingerprint SET NOT NULL;
+ALTER TABLE expenses ALTER COLUMN created_at SET DEFAULT now();
+ALTER TABLE expenses ALTER COLUMN created_at SET NOT NULL;
+
+ALTER TABLE income ADD COLUMN IF NOT EXISTS fingerprint TEXT;
+ALTER TABLE income ADD COLUMN IF NOT EXISTS created_at TIMESTAMPTZ;
+UPDATE income
+SET fingerprint = account_id || '|' || amount || '|' || to_char(occurred_at, 'YYYY-MM-DD') || '|' || COALESCE(import_ref, ''),
+    created_at = occurred_at
+WHERE fingerprint IS NULL;
+ALTER TABLE income ALTER COLUMN fingerprint SET NOT NULL;
+ALTER TABLE income ALTER COLUMN created_at SET DEFAULT now();
+ALTER TABLE income ALTER COLUMN created_at SET NOT NULL;
+
+CREATE INDEX IF NOT EXISTS expenses_user_fingerprint ON expenses (user_id, fingerprint);
+CREATE INDEX IF NOT EXISTS income_user_fingerprint ON income (user_id, fingerprint);
+
+-- An imported row is booked once, however many statements it appears in.
+CREATE UNIQUE INDEX IF NOT EXISTS expenses_imported_fingerprint ON expenses (user_id, fingerprint) WHERE import_ref IS NOT NULL;
+CREATE UNIQUE INDEX IF NOT EXISTS income_imported_fingerprint ON income (user_id, fingerprint) WHERE import_ref IS NOT NULL;
@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS payee_rules (
+    id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT NOT NULL,
+    pattern TEXT NOT NULL,
+    category_id BIGINT NOT NULL REFERENCES categories(id) ON DELETE CASCADE,
+    UNIQUE (user_id, pattern)
+);
@@ -0,0 +1 @@
+ALTER TABLE user_settings ADD COLUMN IF NOT EXISTS default_account_id BIGINT REFERENCES accounts(id) ON DELETE SET NULL;
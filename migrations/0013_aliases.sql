@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS aliases (
+    id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT NOT NULL,
+    alias TEXT NOT NULL,
+    category_id BIGINT REFERENCES categories(id) ON DELETE CASCADE,
+    account_id BIGINT REFERENCES accounts(id) ON DELETE CASCADE,
+    UNIQUE (user_id, alias)
+);
@@ -0,0 +1,21 @@
+CREATE TABLE IF NOT EXISTS expense_notes (
+    expense_id BIGINT PRIMARY KEY REFERENCES expenses(id) ON DELETE CASCADE,
+    note TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS income_notes (
+    income_id BIGINT PRIMARY KEY REFERENCES income(id) ON DELETE CASCADE,
+    note TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS expense_tags (
+    expense_id BIGINT NOT NULL REFERENCES expenses(id) ON DELETE CASCADE,
+    tag TEXT NOT NULL,
+    PRIMARY KEY (expense_id, tag)
+);
+
+CREATE TABLE IF NOT EXISTS income_tags (
+    income_id BIGINT NOT NULL REFERENCES income(id) ON DELETE CASCADE,
+    tag TEXT NOT NULL,
+    PRIMARY KEY (income_id, tag)
+);
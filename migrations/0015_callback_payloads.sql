@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS callback_payloads (
+    id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT NOT NULL,
+    payload TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS callback_payloads_user_id ON callback_payloads (user_id);
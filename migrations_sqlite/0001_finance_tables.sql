@@ -0,0 +1,62 @@
+-- The schema of the Postgres migrations up to 0006 in one step.
+CREATE TABLE IF NOT EXISTS accounts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    balance INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    currency TEXT NOT NULL DEFAULT 'RUB'
+);
+
+CREATE TABLE IF NOT EXISTS categories (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    description TEXT NOT NULL,
+    user_id INTEGER NOT NULL,
+    budget_limit INTEGER,
+    budget_period TEXT
+);
+
+-- Timestamps are RFC 3339 text in UTC.
+CREATE TABLE IF NOT EXISTS expenses (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    account_id INTEGER NOT NULL REFERENCES accounts(id),
+    category_id INTEGER NOT NULL REFERENCES categories(id),
+    amount INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    occurred_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%S+00:00', 'now'))
+);
+
+CREATE TABLE IF NOT EXISTS income (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    account_id INTEGER NOT NULL REFERENCES accounts(id),
+    category_id INTEGER NOT NULL REFERENCES categories(id),
+    amount INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    occurred_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%S+00:00', 'now'))
+);
+
+CREATE TABLE IF NOT EXISTS transfers (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    from_account_id INTEGER NOT NULL REFERENCES accounts(id),
+    to_account_id INTEGER NOT NULL REFERENCES accounts(id),
+    amount INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    occurred_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%S+00:00', 'now'))
+);
+
+CREATE INDEX IF NOT EXISTS expenses_user_occurred_at ON expenses (user_id, occurred_at);
+CREATE INDEX IF NOT EXISTS income_user_occurred_at ON income (user_id, occurred_at);
+CREATE INDEX IF NOT EXISTS transfers_user_occurred_at ON transfers (user_id, occurred_at);
+
+CREATE TABLE IF NOT EXISTS exchange_rates (
+    user_id INTEGER NOT NULL,
+    from_currency TEXT NOT NULL,
+    to_currency TEXT NOT NULL,
+    rate REAL NOT NULL,
+    PRIMARY KEY (user_id, from_currency, to_currency)
+);
+
+CREATE TABLE IF NOT EXISTS user_settings (
+    user_id INTEGER PRIMARY KEY,
+    base_currency TEXT NOT NULL DEFAULT 'RUB'
+);
@@ -0,0 +1,13 @@
+ALTER TABLE expenses ADD COLUMN import_ref TEXT;
+ALTER TABLE income ADD COLUMN import_ref TEXT;
+
+CREATE INDEX IF NOT EXISTS expenses_user_import_ref ON expenses (user_id, import_ref);
+CREATE INDEX IF NOT EXISTS income_user_import_ref ON income (user_id, import_ref);
+
+CREATE TABLE IF NOT EXISTS import_mappings (
+    user_id INTEGER PRIMARY KEY,
+    date_column INTEGER NOT NULL,
+    amount_column INTEGER NOT NULL,
+    category_column INTEGER NOT NULL,
+    description_column INTEGER
+);
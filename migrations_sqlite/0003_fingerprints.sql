@@ -0,0 +1,19 @@
+-- Timestamps are RFC 3339 text in UTC, so the first ten characters are the day.
+ALTER TABLE expenses ADD COLUMN fingerprint TEXT NOT NULL DEFAULT '';
+ALTER TABLE expenses ADD COLUMN created_at TEXT;
+UPDATE expenses
+SET fingerprint = account_id || '|' || amount || '|' || substr(occurred_at, 1, 10) || '|' || COALESCE(import_ref, ''),
+    created_at = occurred_at;
+
+ALTER TABLE income ADD COLUMN fingerprint TEXT NOT NULL DEFAULT '';
+ALTER TABLE income ADD COLUMN created_at TEXT;
+UPDATE income
+SET fingerprint = account_id || '|' || amount || '|' || substr(occurred_at, 1, 10) || '|' || COALESCE(import_ref, ''),
+    created_at = occurred_at;
+
+CREATE INDEX IF NOT EXISTS expenses_user_fingerprint ON expenses (user_id, fingerprint);
+CREATE INDEX IF NOT EXISTS income_user_fingerprint ON income (user_id, fingerprint);
+
+-- An imported row is booked once, however many statements it appears in.
+CREATE UNIQUE INDEX IF NOT EXISTS expenses_imported_fingerprint ON expenses (user_id, fingerprint) WHERE import_ref IS NOT NULL;
+CREATE UNIQUE INDEX IF NOT EXISTS income_imported_fingerprint ON income (user_id, fingerprint) WHERE import_ref IS NOT NULL;
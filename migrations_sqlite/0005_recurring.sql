@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS recurring (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    income BOOLEAN NOT NULL,
+    amount INTEGER NOT NULL,
+    category_id INTEGER NOT NULL REFERENCES categories(id) ON DELETE CASCADE,
+    account_id INTEGER NOT NULL REFERENCES accounts(id) ON DELETE CASCADE,
+    schedule TEXT NOT NULL,
+    next_run DATE NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS recurring_next_run ON recurring (next_run);
@@ -0,0 +1 @@
+ALTER TABLE user_settings ADD COLUMN default_account_id INTEGER REFERENCES accounts(id) ON DELETE SET NULL;
@@ -0,0 +1,4 @@
+CREATE TABLE IF NOT EXISTS dialogues (
+    user_id INTEGER PRIMARY KEY,
+    state TEXT NOT NULL
+);
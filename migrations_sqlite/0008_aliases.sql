@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS aliases (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    alias TEXT NOT NULL,
+    category_id INTEGER REFERENCES categories(id) ON DELETE CASCADE,
+    account_id INTEGER REFERENCES accounts(id) ON DELETE CASCADE,
+    UNIQUE (user_id, alias)
+);
@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS callback_payloads (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    payload TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS callback_payloads_user_id ON callback_payloads (user_id);
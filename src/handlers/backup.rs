@@ -0,0 +1,256 @@
+use super::logic::*;
+use super::{error_message, send_reply, Reply};
+use chrono::Local;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError};
+use teloxide::{
+    net::Download,
+    prelude::*,
+    types::{InlineKeyboardButton, InlineKeyboardMarkup},
+};
+
+pub const CONFIRM_RESTORE: &str = "restore:yes";
+pub const CANCEL_RESTORE: &str = "restore:no";
+
+pub const RESTORE_HELP: &str =
+    "Отправьте файл резервной копии, сделанный командой /backup. Перед восстановлением бот покажет, что в нём";
+
+/// Uploaded backup of every user waiting for the confirmation.
+#[derive(Clone, Default)]
+pub struct RestoreDrafts(Arc<Mutex<HashMap<i64, Backup>>>);
+
+impl RestoreDrafts {
+    fn insert(&self, user_id: i64, backup: Backup) {
+        let mut drafts = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        drafts.insert(user_id, backup);
+    }
+
+    fn take(&self, user_id: i64) -> Option<Backup> {
+        let mut drafts = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        drafts.remove(&user_id)
+    }
+}
+
+/// Backups are told from statements by the extension `/backup` gives them.
+pub fn is_backup_document(document: &teloxide::types::Document) -> bool {
+    document
+        .file_name
+        .as_deref()
+        .is_some_and(|name| name.to_lowercase().ends_with(".json"))
+}
+
+pub async fn backup_handler(store: &dyn FinanceStore, user_id: i64) -> Reply {
+    match store.backup(user_id).await {
+        Ok(backup) => Reply::document(
+            format!(
+                "Резервная копия: {}. Чтобы восстановить данные, отправьте этот файл боту",
+                backup.summary()
+            ),
+            &format!("finance-backup-{}.json", Local::now().format("%Y-%m-%d")),
+            backup.to_json(),
+        ),
+        Err(e) => error_message(e).into(),
+    }
+}
+
+/// Reads an uploaded backup and asks to confirm replacing the user's data.
+pub fn preview_restore(drafts: &RestoreDrafts, user_id: i64, bytes: &[u8]) -> Reply {
+    let backup = match Backup::from_json(bytes) {
+        Ok(backup) => backup,
+        Err(e) => return error_message(e.into()).into(),
+    };
+    let text = format!(
+        "Резервная копия от {}: {}.\nВосстановление заменит все ваши текущие данные",
+        backup.created_at.format("%Y-%m-%d %H:%M"),
+        backup.summary()
+    );
+    drafts.insert(user_id, backup);
+    let keyboard = InlineKeyboardMarkup::new([[
+        InlineKeyboardButton::callback("✅ Восстановить", CONFIRM_RESTORE),
+        InlineKeyboardButton::callback("❌ Отмена", CANCEL_RESTORE),
+    ]]);
+    Reply {
+        keyboard: Some(keyboard),
+        ..text.into()
+    }
+}
+
+pub async fn confirm_restore(
+    store: &dyn FinanceStore,
+    drafts: &RestoreDrafts,
+    user_id: i64,
+) -> Reply {
+    let Some(backup) = drafts.take(user_id) else {
+        return "Нет резервной копии для восстановления, отправьте файл ещё раз".into();
+    };
+    match store.restore(user_id, backup).await {
+        Ok(()) => "Данные восстановлены".into(),
+        Err(e) => error_message(e).into(),
+    }
+}
+
+pub fn cancel_restore(drafts: &RestoreDrafts, user_id: i64) -> Reply {
+    drafts.take(user_id);
+    "Восстановление отменено".into()
+}
+
+/// Downloads a backup sent as a document and asks to confirm the restore.
+pub async fn restore_document_handler(
+    bot: Bot,
+    msg: Message,
+    drafts: RestoreDrafts,
+) -> ResponseResult<()> {
+    let Some(document) = msg.document() else {
+        return Ok(());
+    };
+    let file = bot.get_file(&document.file.id).await?;
+    let mut bytes = vec![];
+    bot.download_file(&file.path, &mut bytes).await?;
+
+    let reply = preview_restore(&drafts, msg.chat.id.0, &bytes);
+    send_reply(&bot, msg.chat.id, reply).await
+}
+
+/// Handles the buttons under a backup preview.
+pub async fn restore_callback_handler(
+    bot: Bot,
+    q: CallbackQuery,
+    store: Store,
+    drafts: RestoreDrafts,
+) -> ResponseResult<()> {
+    if let Some(message) = &q.message {
+        let user_id = message.chat.id.0;
+        let reply = match q.data.as_deref() {
+            Some(CONFIRM_RESTORE) => confirm_restore(&*store, &drafts, user_id).await,
+            _ => cancel_restore(&drafts, user_id),
+        };
+        bot.edit_message_text(message.chat.id, message.id, reply.text)
+            .await?;
+    }
+    bot.answer_callback_query(q.id).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::logic::store::tests::{setup_user, test_user_id};
+
+    #[tokio::test]
+    async fn backup_moves_data_to_another_instance() {
+        let source = MemoryStore::default();
+        let user_id = setup_user(&source, 100000).await;
+        source
+            .add_expense(user_id, Money(19990), "food".into(), "card".into(), None)
+            .await
+            .unwrap();
+        source
+            .set_payee_rule(user_id, "кофейня".into(), "food".into())
+            .await
+            .unwrap();
+        let note = Note::new("с коллегами".into(), ["work".into()]);
+        source
+            .add_entry(
+                user_id,
+                false,
+                NewEntry {
+                    amount: Money(5000),
+                    category: "food".into(),
+                    account: "card".into(),
+                    date: None,
+                    import_ref: None,
+                    note: note.clone(),
+                },
+            )
+            .await
+            .unwrap();
+        let next_run = Local::now().date_naive() + chrono::Duration::days(1);
+        source
+            .add_recurring(
+                user_id,
+                NewRecurring {
+                    income: false,
+                    amount: Money(1000),
+                    category: "food".into(),
+                    account: "card".into(),
+                    schedule: Schedule::Monthly(5),
+                    next_run,
+                },
+            )
+            .await
+            .unwrap();
+        source
+            .set_default_account(user_id, "card".into())
+            .await
+            .unwrap();
+        source
+            .set_alias(user_id, "кофе".into(), "food".into())
+            .await
+            .unwrap();
+
+        let reply = backup_handler(&source, user_id).await;
+        let document = reply.document.unwrap();
+        assert!(document.file_name.starts_with("finance-backup-"));
+        assert!(reply
+            .text
+            .contains("аккаунтов: 1, категорий: 1, расходов: 2"));
+
+        let target = MemoryStore::default();
+        let drafts = RestoreDrafts::default();
+        let new_user_id = test_user_id();
+        let preview = preview_restore(&drafts, new_user_id, &document.bytes);
+        assert!(preview.keyboard.is_some());
+        assert!(
+            preview
+                .text
+                .ends_with("Восстановление заменит все ваши текущие данные"),
+            "{}",
+            preview.text
+        );
+        assert_eq!(
+            confirm_restore(&target, &drafts, new_user_id).await.text,
+            "Данные восстановлены"
+        );
+        let accounts = target.get_accounts(new_user_id).await.unwrap();
+        assert_eq!(accounts[0].balance, Money(100000 - 19990 - 5000));
+        let rules = target.get_payee_rules(new_user_id).await.unwrap();
+        assert_eq!(rules[0].category, "food");
+        let expenses = target.get_expense(new_user_id, Period::All).await.unwrap();
+        assert!(expenses.iter().any(|expense| expense.note == note));
+        let recurring = target.get_recurring(new_user_id).await.unwrap();
+        assert_eq!(
+            (
+                recurring[0].amount,
+                recurring[0].schedule,
+                recurring[0].next_run
+            ),
+            (Money(1000), Schedule::Monthly(5), next_run)
+        );
+        assert_eq!(
+            target.get_default_account(new_user_id).await.unwrap(),
+            Some("card".to_string())
+        );
+        let aliases = target.get_aliases(new_user_id).await.unwrap();
+        assert_eq!(aliases[0].target, AliasTarget::Category("food".into()));
+        assert_eq!(
+            confirm_restore(&target, &drafts, new_user_id).await.text,
+            "Нет резервной копии для восстановления, отправьте файл ещё раз"
+        );
+    }
+
+    #[test]
+    fn rejects_files_that_are_not_backups() {
+        let drafts = RestoreDrafts::default();
+        let reply = preview_restore(&drafts, 1, b"date;amount\n");
+        assert!(reply.keyboard.is_none());
+        assert!(
+            reply
+                .text
+                .starts_with("Неверные данные: файл не похож на резервную копию"),
+            "{}",
+            reply.text
+        );
+        assert_eq!(cancel_restore(&drafts, 1).text, "Восстановление отменено");
+    }
+}
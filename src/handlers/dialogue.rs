@@ -0,0 +1,572 @@
+use super::logic::*;
+use super::names::named_rows;
+use super::{add_expense_handler, error_message, send_reply, Reply};
+use futures::future::BoxFuture;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::sync::Arc;
+use teloxide::{
+    dispatching::dialogue::{Dialogue, ErasedStorage, Storage},
+    prelude::*,
+    types::{InlineKeyboardButton, InlineKeyboardMarkup},
+};
+
+pub const CANCEL_ADD_EXPENSE: &str = "addexp:no";
+const CONFIRM_ADD_EXPENSE: &str = "addexp:yes";
+
+/// Buttons per row of the category and account keyboards.
+const BUTTONS_PER_ROW: usize = 2;
+
+/// Keeps dialogue states in the finance store, so a dialogue survives a
+/// restart of the bot.
+pub struct DialogueStore(pub Store);
+
+impl DialogueStore {
+    /// Storage of the `/addexpense` dialogue, as the dispatcher takes it.
+    pub fn erased(store: Store) -> Arc<ErasedStorage<AddExpenseState>> {
+        Arc::new(DialogueStore(store)).erase()
+    }
+}
+
+impl<D> Storage<D> for DialogueStore
+where
+    D: Serialize + DeserializeOwned + Send + 'static,
+{
+    type Error = FinanceError;
+
+    fn remove_dialogue(
+        self: Arc<Self>,
+        chat_id: ChatId,
+    ) -> BoxFuture<'static, Result<(), Self::Error>>
+    where
+        D: Send + 'static,
+    {
+        Box::pin(async move { self.0.del_dialogue(chat_id.0).await })
+    }
+
+    fn update_dialogue(
+        self: Arc<Self>,
+        chat_id: ChatId,
+        dialogue: D,
+    ) -> BoxFuture<'static, Result<(), Self::Error>>
+    where
+        D: Send + 'static,
+    {
+        Box::pin(async move {
+            let state = serde_json::to_string(&dialogue)
+                .map_err(|e| FinanceError::Validation(e.to_string()))?;
+            self.0.set_dialogue(chat_id.0, state).await
+        })
+    }
+
+    fn get_dialogue(
+        self: Arc<Self>,
+        chat_id: ChatId,
+    ) -> BoxFuture<'static, Result<Option<D>, Self::Error>> {
+        Box::pin(async move {
+            let Some(state) = self.0.get_dialogue(chat_id.0).await? else {
+                return Ok(None);
+            };
+            // A state saved by an older version of the bot starts over.
+            match serde_json::from_str(&state) {
+                Ok(dialogue) => Ok(Some(dialogue)),
+                Err(e) => {
+                    log::warn!("Dropping unreadable dialogue of {chat_id}: {e}");
+                    Ok(None)
+                }
+            }
+        })
+    }
+}
+
+/// Step of `/addexpense` sent without all of its arguments.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AddExpenseState {
+    #[default]
+    Idle,
+    Amount,
+    Category {
+        amount: Money,
+    },
+    Account {
+        amount: Money,
+        category: String,
+    },
+    Confirm {
+        amount: Money,
+        category: String,
+        account: String,
+    },
+}
+
+pub type AddExpenseDialogue = Dialogue<AddExpenseState, ErasedStorage<AddExpenseState>>;
+
+/// What the user answered a step with.
+#[derive(Clone, Copy, Debug)]
+pub enum DialogueInput<'a> {
+    Text(&'a str),
+    /// Callback data of a button.
+    Button(&'a str),
+}
+
+fn keyboard(mut rows: Vec<Vec<InlineKeyboardButton>>) -> Option<InlineKeyboardMarkup> {
+    rows.push(vec![InlineKeyboardButton::callback(
+        "Отмена",
+        CANCEL_ADD_EXPENSE,
+    )]);
+    Some(InlineKeyboardMarkup::new(rows))
+}
+
+fn name_buttons<'a>(
+    names: impl Iterator<Item = (&'a str, i64)>,
+    prefix: &str,
+) -> Vec<Vec<InlineKeyboardButton>> {
+    let buttons: Vec<_> = names
+        .map(|(name, id)| InlineKeyboardButton::callback(name, format!("{prefix}{id}")))
+        .collect();
+    buttons
+        .chunks(BUTTONS_PER_ROW)
+        .map(|row| row.to_vec())
+        .collect()
+}
+
+/// Question of `state` with the buttons answering it.
+async fn prompt(store: &dyn FinanceStore, user_id: i64, state: &AddExpenseState) -> Reply {
+    let (text, rows) = match state {
+        AddExpenseState::Idle => return "Расход не записан".into(),
+        AddExpenseState::Amount => ("Введите сумму расхода".to_string(), vec![]),
+        AddExpenseState::Category { amount } => {
+            let categories = match store.get_categories(user_id).await {
+                Ok(categories) => categories,
+                Err(e) => return error_message(e).into(),
+            };
+            let names = categories
+                .iter()
+                .filter_map(|cat| Some((cat.name.as_str(), cat.id?)));
+            (
+                format!("Расход {amount}. Выберите категорию"),
+                name_buttons(names, "addexp:cat:"),
+            )
+        }
+        AddExpenseState::Account { amount, category } => {
+            let accounts = match store.get_accounts(user_id).await {
+                Ok(accounts) => accounts,
+                Err(e) => return error_message(e).into(),
+            };
+            let names = accounts
+                .iter()
+                .filter_map(|acc| Some((acc.name.as_str(), acc.id?)));
+            (
+                format!("Расход {amount} {category}. Выберите аккаунт"),
+                name_buttons(names, "addexp:acc:"),
+            )
+        }
+        AddExpenseState::Confirm {
+            amount,
+            category,
+            account,
+        } => (
+            format!("Записать расход {amount} {category} с {account}?"),
+            vec![vec![InlineKeyboardButton::callback(
+                "Записать",
+                CONFIRM_ADD_EXPENSE,
+            )]],
+        ),
+    };
+    Reply {
+        keyboard: keyboard(rows),
+        ..text.into()
+    }
+}
+
+/// Name of the category picked by `input`, by button or by typing it.
+async fn category_of(
+    store: &dyn FinanceStore,
+    user_id: i64,
+    input: DialogueInput<'_>,
+) -> Result<Option<String>, FinanceError> {
+    let categories = store.get_categories(user_id).await?;
+    let category = match input {
+        DialogueInput::Button(data) => {
+            let id = data
+                .strip_prefix("addexp:cat:")
+                .and_then(|id| id.parse().ok());
+            categories
+                .into_iter()
+                .find(|cat| id.is_some() && cat.id == id)
+        }
+        DialogueInput::Text(text) => {
+            let (rows, _) = named_rows(store, user_id).await?;
+            let id = resolve_name(&rows, text);
+            categories
+                .into_iter()
+                .find(|cat| id.is_some() && cat.id == id)
+        }
+    };
+    Ok(category.map(|cat| cat.name))
+}
+
+/// Name of the account picked by `input`, by button or by typing it.
+async fn account_of(
+    store: &dyn FinanceStore,
+    user_id: i64,
+    input: DialogueInput<'_>,
+) -> Result<Option<String>, FinanceError> {
+    let accounts = store.get_accounts(user_id).await?;
+    let account = match input {
+        DialogueInput::Button(data) => {
+            let id = data
+                .strip_prefix("addexp:acc:")
+                .and_then(|id| id.parse().ok());
+            accounts
+                .into_iter()
+                .find(|acc| id.is_some() && acc.id == id)
+        }
+        DialogueInput::Text(text) => {
+            let (_, rows) = named_rows(store, user_id).await?;
+            let id = resolve_name(&rows, text);
+            accounts
+                .into_iter()
+                .find(|acc| id.is_some() && acc.id == id)
+        }
+    };
+    Ok(account.map(|acc| acc.name))
+}
+
+/// Starts the dialogue for `/addexpense <args>`, skipping the steps answered
+/// by valid arguments.
+pub async fn add_expense_start(
+    store: &dyn FinanceStore,
+    user_id: i64,
+    args: &str,
+) -> (AddExpenseState, Vec<Reply>) {
+    // Unbalanced quotes leave everything to ask.
+    let mut args = split_args(args).unwrap_or_default().into_iter();
+    let amount = args
+        .next()
+        .and_then(|amount| amount.parse::<Money>().ok())
+        .filter(|amount| *amount > Money::ZERO);
+    let mut state = match amount {
+        Some(amount) => AddExpenseState::Category { amount },
+        None => AddExpenseState::Amount,
+    };
+    let names = if amount.is_some() { 2 } else { 0 };
+    for arg in args.by_ref().take(names) {
+        match add_expense_step(store, user_id, state.clone(), DialogueInput::Text(&arg)).await {
+            (next, _) if next != state && next != AddExpenseState::Idle => state = next,
+            _ => break,
+        }
+    }
+    // The command parser rejected a mistyped date, which isn't to be dropped.
+    if let Some(Err(e)) = args.next().as_deref().map(parse_date_arg) {
+        return (AddExpenseState::Idle, vec![error_message(e.into()).into()]);
+    }
+    let reply = prompt(store, user_id, &state).await;
+    (state, vec![reply])
+}
+
+/// Moves the dialogue on by the user's answer, `Idle` once the expense is
+/// recorded or cancelled.
+pub async fn add_expense_step(
+    store: &dyn FinanceStore,
+    user_id: i64,
+    state: AddExpenseState,
+    input: DialogueInput<'_>,
+) -> (AddExpenseState, Vec<Reply>) {
+    if let DialogueInput::Button(CANCEL_ADD_EXPENSE) = input {
+        return (AddExpenseState::Idle, vec!["Расход не записан".into()]);
+    }
+
+    let next = match (&state, input) {
+        (AddExpenseState::Amount, DialogueInput::Text(text)) => {
+            match text.trim().parse::<Money>() {
+                Ok(amount) if amount > Money::ZERO => {
+                    Ok(Some(AddExpenseState::Category { amount }))
+                }
+                _ => Ok(None),
+            }
+        }
+        (AddExpenseState::Category { amount }, input) => {
+            category_of(store, user_id, input).await.map(|category| {
+                category.map(|category| AddExpenseState::Account {
+                    amount: *amount,
+                    category,
+                })
+            })
+        }
+        (AddExpenseState::Account { amount, category }, input) => {
+            account_of(store, user_id, input).await.map(|account| {
+                account.map(|account| AddExpenseState::Confirm {
+                    amount: *amount,
+                    category: category.clone(),
+                    account,
+                })
+            })
+        }
+        (
+            AddExpenseState::Confirm {
+                amount,
+                category,
+                account,
+            },
+            DialogueInput::Button(CONFIRM_ADD_EXPENSE),
+        ) => {
+            let entry = NewEntry {
+                amount: *amount,
+                category: category.clone(),
+                account: account.clone(),
+                date: None,
+                import_ref: None,
+                note: Note::default(),
+            };
+            return (
+                AddExpenseState::Idle,
+                add_expense_handler(store, user_id, entry).await,
+            );
+        }
+        _ => Ok(None),
+    };
+
+    match next {
+        Ok(Some(next)) => {
+            let reply = prompt(store, user_id, &next).await;
+            (next, vec![reply])
+        }
+        // The answer doesn't fit the step, which is asked again.
+        Ok(None) => {
+            let reply = prompt(store, user_id, &state).await;
+            (state, vec![reply])
+        }
+        Err(e) => (state, vec![error_message(e).into()]),
+    }
+}
+
+async fn save_state(dialogue: &AddExpenseDialogue, state: AddExpenseState) {
+    let saved = if state == AddExpenseState::Idle {
+        dialogue.exit().await
+    } else {
+        dialogue.update(state).await
+    };
+    if let Err(e) = saved {
+        log::error!("Failed to save dialogue of {}: {e}", dialogue.chat_id());
+    }
+}
+
+/// Whether `text` is `/addexpense` that the command parser rejected, which
+/// the dialogue completes.
+pub fn is_add_expense(text: &str) -> bool {
+    let command = text.split_whitespace().next().unwrap_or_default();
+    let command = command.split('@').next().unwrap_or_default();
+    command.eq_ignore_ascii_case("/addexpense")
+}
+
+pub async fn add_expense_dialogue_start(
+    bot: Bot,
+    msg: Message,
+    store: Store,
+    dialogue: AddExpenseDialogue,
+) -> ResponseResult<()> {
+    let text = msg.text().unwrap_or_default();
+    let args = text
+        .split_once(char::is_whitespace)
+        .map_or("", |(_, args)| args);
+    let (state, replies) = add_expense_start(&*store, msg.chat.id.0, args).await;
+    save_state(&dialogue, state).await;
+    for reply in replies {
+        send_reply(&bot, msg.chat.id, reply).await?;
+    }
+
+    Ok(())
+}
+
+/// Takes a typed answer to the current step.
+pub async fn add_expense_dialogue_message(
+    bot: Bot,
+    msg: Message,
+    store: Store,
+    dialogue: AddExpenseDialogue,
+    state: AddExpenseState,
+) -> ResponseResult<()> {
+    let input = DialogueInput::Text(msg.text().unwrap_or_default());
+    let (state, replies) = add_expense_step(&*store, msg.chat.id.0, state, input).await;
+    save_state(&dialogue, state).await;
+    for reply in replies {
+        send_reply(&bot, msg.chat.id, reply).await?;
+    }
+
+    Ok(())
+}
+
+/// Takes a button answer, replacing the question with the next one.
+pub async fn add_expense_dialogue_callback(
+    bot: Bot,
+    q: CallbackQuery,
+    store: Store,
+    dialogue: AddExpenseDialogue,
+    state: AddExpenseState,
+) -> ResponseResult<()> {
+    if let (Some(data), Some(message)) = (q.data.as_deref(), &q.message) {
+        let input = DialogueInput::Button(data);
+        let (state, replies) = add_expense_step(&*store, message.chat.id.0, state, input).await;
+        save_state(&dialogue, state).await;
+
+        let mut replies = replies.into_iter();
+        if let Some(first) = replies.next() {
+            let mut edit = bot.edit_message_text(message.chat.id, message.id, first.text);
+            if let Some(keyboard) = first.keyboard {
+                edit = edit.reply_markup(keyboard);
+            }
+            edit.await?;
+        }
+        for reply in replies {
+            send_reply(&bot, message.chat.id, reply).await?;
+        }
+    }
+    bot.answer_callback_query(q.id).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::logic::store::tests::{balance, setup_user};
+
+    fn buttons(reply: &Reply) -> Vec<String> {
+        let keyboard = reply.keyboard.as_ref().expect("buttons");
+        keyboard
+            .inline_keyboard
+            .iter()
+            .flatten()
+            .map(|button| match &button.kind {
+                teloxide::types::InlineKeyboardButtonKind::CallbackData(data) => data.clone(),
+                kind => panic!("unexpected button {kind:?}"),
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn bare_add_expense_asks_step_by_step() {
+        let store = MemoryStore::default();
+        let user_id = setup_user(&store, 100000).await;
+        let category_id = store.get_categories(user_id).await.unwrap()[0].id.unwrap();
+        let account_id = store.get_accounts(user_id).await.unwrap()[0].id.unwrap();
+
+        let (state, replies) = add_expense_start(&store, user_id, "").await;
+        assert_eq!(state, AddExpenseState::Amount);
+        assert_eq!(replies[0].text, "Введите сумму расхода");
+
+        let (state, replies) =
+            add_expense_step(&store, user_id, state, DialogueInput::Text("сто")).await;
+        assert_eq!(state, AddExpenseState::Amount);
+        assert_eq!(replies[0].text, "Введите сумму расхода");
+
+        let (state, replies) =
+            add_expense_step(&store, user_id, state, DialogueInput::Text("199,90")).await;
+        assert_eq!(replies[0].text, "Расход 199.90. Выберите категорию");
+        assert_eq!(
+            buttons(&replies[0]),
+            [
+                format!("addexp:cat:{category_id}"),
+                CANCEL_ADD_EXPENSE.to_string()
+            ]
+        );
+
+        let data = format!("addexp:cat:{category_id}");
+        let (state, replies) =
+            add_expense_step(&store, user_id, state, DialogueInput::Button(&data)).await;
+        assert_eq!(replies[0].text, "Расход 199.90 food. Выберите аккаунт");
+
+        let data = format!("addexp:acc:{account_id}");
+        let (state, replies) =
+            add_expense_step(&store, user_id, state, DialogueInput::Button(&data)).await;
+        assert_eq!(replies[0].text, "Записать расход 199.90 food с card?");
+        assert_eq!(balance(&store, user_id).await, 100000);
+
+        let input = DialogueInput::Button(CONFIRM_ADD_EXPENSE);
+        let (state, replies) = add_expense_step(&store, user_id, state, input).await;
+        assert_eq!(state, AddExpenseState::Idle);
+        assert_eq!(replies[0].text, "Расход успешно добавлен");
+        assert_eq!(balance(&store, user_id).await, 100000 - 19990);
+
+        // The same expense again asks before recording it, as /addexpense does.
+        let (state, _) = add_expense_start(&store, user_id, "199,90 food card").await;
+        let input = DialogueInput::Button(CONFIRM_ADD_EXPENSE);
+        let (state, replies) = add_expense_step(&store, user_id, state, input).await;
+        assert_eq!(state, AddExpenseState::Idle);
+        assert!(
+            replies[0].text.ends_with("Записать ещё раз?"),
+            "{}",
+            replies[0].text
+        );
+        assert!(replies[0].keyboard.is_some());
+        assert_eq!(balance(&store, user_id).await, 100000 - 19990);
+    }
+
+    #[tokio::test]
+    async fn valid_arguments_skip_their_steps() {
+        let store = MemoryStore::default();
+        let user_id = setup_user(&store, 100000).await;
+
+        let (state, _) = add_expense_start(&store, user_id, "200 food").await;
+        assert_eq!(
+            state,
+            AddExpenseState::Account {
+                amount: Money(20000),
+                category: "food".to_string(),
+            }
+        );
+        let (state, _) = add_expense_start(&store, user_id, "200 fod card").await;
+        assert_eq!(
+            state,
+            AddExpenseState::Category {
+                amount: Money(20000)
+            }
+        );
+
+        let input = DialogueInput::Button(CANCEL_ADD_EXPENSE);
+        let (state, replies) = add_expense_step(&store, user_id, state, input).await;
+        assert_eq!(state, AddExpenseState::Idle);
+        assert_eq!(replies[0].text, "Расход не записан");
+
+        // A mistyped date is reported rather than dropped.
+        let (state, replies) = add_expense_start(&store, user_id, "200 food card 2026-13-01").await;
+        assert_eq!(state, AddExpenseState::Idle);
+        assert!(
+            replies[0]
+                .text
+                .starts_with("Неверные данные: неверная дата"),
+            "{}",
+            replies[0].text
+        );
+    }
+
+    #[tokio::test]
+    async fn dialogue_state_survives_in_the_store() {
+        let store: Store = Arc::new(MemoryStore::default());
+        let storage = Arc::new(DialogueStore(store.clone()));
+        let state = AddExpenseState::Confirm {
+            amount: Money(100),
+            category: "food".to_string(),
+            account: "card".to_string(),
+        };
+
+        storage
+            .clone()
+            .update_dialogue(ChatId(1), state.clone())
+            .await
+            .unwrap();
+        let stored: Option<AddExpenseState> =
+            storage.clone().get_dialogue(ChatId(1)).await.unwrap();
+        assert_eq!(stored, Some(state));
+
+        store.set_dialogue(1, "garbage".to_string()).await.unwrap();
+        let stored: Option<AddExpenseState> =
+            storage.clone().get_dialogue(ChatId(1)).await.unwrap();
+        assert_eq!(stored, None);
+
+        Storage::<AddExpenseState>::remove_dialogue(storage.clone(), ChatId(1))
+            .await
+            .unwrap();
+        assert_eq!(store.get_dialogue(1).await.unwrap(), None);
+    }
+}
@@ -0,0 +1,129 @@
+use super::logic::*;
+use super::{book_expense, error_message, send_reply, Reply};
+use teloxide::{
+    prelude::*,
+    types::{InlineKeyboardButton, InlineKeyboardMarkup},
+};
+
+/// Button under the duplicate prompt. The expense waits in the store under
+/// the id, as its note wouldn't fit the 64 bytes of callback data.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DuplicateAnswer {
+    Confirm(i64),
+    Cancel(i64),
+}
+
+impl DuplicateAnswer {
+    pub fn to_callback_data(self) -> String {
+        match self {
+            DuplicateAnswer::Confirm(id) => format!("dup:{id}"),
+            DuplicateAnswer::Cancel(id) => format!("dup:no:{id}"),
+        }
+    }
+
+    pub fn from_callback_data(data: &str) -> Option<Self> {
+        let data = data.strip_prefix("dup:")?;
+        match data.strip_prefix("no:") {
+            Some(id) => Some(DuplicateAnswer::Cancel(id.parse().ok()?)),
+            None => Some(DuplicateAnswer::Confirm(data.parse().ok()?)),
+        }
+    }
+}
+
+/// Asks whether to record `entry` again, an identical one having been
+/// recorded within the last minutes.
+pub async fn duplicate_prompt(
+    store: &dyn FinanceStore,
+    user_id: i64,
+    entry: NewEntry,
+) -> Vec<Reply> {
+    let payload = serde_json::to_string(&entry).expect("entry serializes");
+    let id = match store.save_callback_payload(user_id, payload).await {
+        Ok(id) => id,
+        Err(e) => return vec![error_message(e).into()],
+    };
+
+    let keyboard = InlineKeyboardMarkup::new([[
+        InlineKeyboardButton::callback(
+            "Записать ещё раз",
+            DuplicateAnswer::Confirm(id).to_callback_data(),
+        ),
+        InlineKeyboardButton::callback("Отмена", DuplicateAnswer::Cancel(id).to_callback_data()),
+    ]]);
+    let text = format!(
+        "Расход {} {} с {} уже записан за последние {DUPLICATE_WINDOW_MINUTES} минут. Записать ещё раз?",
+        entry.amount, entry.category, entry.account
+    );
+    vec![Reply {
+        keyboard: Some(keyboard),
+        ..text.into()
+    }]
+}
+
+/// Answers a button under the duplicate prompt. Each prompt books its expense
+/// once, however often the button is pressed.
+pub async fn duplicate_answer(store: &dyn FinanceStore, user_id: i64, data: &str) -> Vec<Reply> {
+    let not_recorded = || vec!["Расход не записан".into()];
+    let (id, confirmed) = match DuplicateAnswer::from_callback_data(data) {
+        Some(DuplicateAnswer::Confirm(id)) => (id, true),
+        Some(DuplicateAnswer::Cancel(id)) => (id, false),
+        None => return not_recorded(),
+    };
+
+    let payload = match store.take_callback_payload(user_id, id).await {
+        Ok(Some(payload)) if confirmed => payload,
+        Ok(_) => return not_recorded(),
+        Err(e) => return vec![error_message(e).into()],
+    };
+    match serde_json::from_str::<NewEntry>(&payload) {
+        Ok(entry) => book_expense(store, user_id, entry).await,
+        Err(e) => {
+            log::warn!("Dropping unreadable pending expense of {user_id}: {e}");
+            not_recorded()
+        }
+    }
+}
+
+/// Handles the buttons under a duplicate prompt by replacing the prompt with
+/// the outcome.
+pub async fn duplicate_callback_handler(
+    bot: Bot,
+    q: CallbackQuery,
+    store: Store,
+) -> ResponseResult<()> {
+    if let (Some(data), Some(message)) = (q.data.as_deref(), &q.message) {
+        let mut replies = duplicate_answer(&*store, message.chat.id.0, data)
+            .await
+            .into_iter();
+        if let Some(first) = replies.next() {
+            bot.edit_message_text(message.chat.id, message.id, first.text)
+                .await?;
+        }
+        for reply in replies {
+            send_reply(&bot, message.chat.id, reply).await?;
+        }
+    }
+    bot.answer_callback_query(q.id).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn callback_data_round_trips() {
+        for answer in [DuplicateAnswer::Confirm(12), DuplicateAnswer::Cancel(12)] {
+            let data = answer.to_callback_data();
+            assert_eq!(DuplicateAnswer::from_callback_data(&data), Some(answer));
+        }
+        assert_eq!(DuplicateAnswer::Confirm(12).to_callback_data(), "dup:12");
+        // Buttons sent before the expense was kept in the store.
+        assert_eq!(
+            DuplicateAnswer::from_callback_data("dup:19990:12:3:2026-09-15"),
+            None
+        );
+        assert_eq!(DuplicateAnswer::from_callback_data("dup:no"), None);
+    }
+}
@@ -0,0 +1,530 @@
+use super::logic::*;
+use super::names::named_rows;
+use super::{error_message, send_reply, Reply};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError};
+use teloxide::{
+    net::Download,
+    prelude::*,
+    types::{InlineKeyboardButton, InlineKeyboardMarkup},
+};
+
+/// Statement rows shown in the preview, the rest are only counted.
+pub const PREVIEW_ROWS: usize = 10;
+
+pub const CONFIRM_IMPORT: &str = "import:yes";
+pub const CANCEL_IMPORT: &str = "import:no";
+
+/// Statement waiting for the user to confirm its preview.
+pub struct ImportDraft {
+    pub account: String,
+    /// Rows with the user's categories filled in, ready to be booked.
+    pub rows: Vec<StatementRow>,
+}
+
+/// Last previewed statement of every user. A new upload replaces the draft.
+#[derive(Clone, Default)]
+pub struct ImportDrafts(Arc<Mutex<HashMap<i64, ImportDraft>>>);
+
+impl ImportDrafts {
+    fn insert(&self, user_id: i64, draft: ImportDraft) {
+        let mut drafts = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        drafts.insert(user_id, draft);
+    }
+
+    fn take(&self, user_id: i64) -> Option<ImportDraft> {
+        let mut drafts = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        drafts.remove(&user_id)
+    }
+}
+
+pub const IMPORT_HELP: &str = "Отправьте выписку в CSV, OFX или QIF с подписью: имя аккаунта и, при желании, категория для операций, категории которых у вас нет.\nНапример: \"tinkoff black\" прочее\nКатегории по получателям задаются через /payeerule";
+
+/// Parses an uploaded statement and answers with its preview and the
+/// confirm buttons. `caption` is `<account> [category]`, either may be
+/// quoted.
+pub async fn preview_import(
+    store: &dyn FinanceStore,
+    drafts: &ImportDrafts,
+    user_id: i64,
+    caption: Option<&str>,
+    bytes: &[u8],
+) -> Reply {
+    // Unbalanced quotes get the help as no caption does.
+    let mut args = split_args(caption.unwrap_or_default())
+        .unwrap_or_default()
+        .into_iter();
+    let Some(account) = args.next() else {
+        return IMPORT_HELP.into();
+    };
+    let fallback = args.next();
+
+    match draft_import(store, user_id, &account, fallback.as_deref(), bytes).await {
+        Ok((text, Some(draft))) => {
+            drafts.insert(user_id, draft);
+            let keyboard = InlineKeyboardMarkup::new([[
+                InlineKeyboardButton::callback("✅ Импортировать", CONFIRM_IMPORT),
+                InlineKeyboardButton::callback("❌ Отмена", CANCEL_IMPORT),
+            ]]);
+            Reply {
+                keyboard: Some(keyboard),
+                ..text.into()
+            }
+        }
+        Ok((text, None)) => text.into(),
+        Err(e) => error_message(e).into(),
+    }
+}
+
+async fn draft_import(
+    store: &dyn FinanceStore,
+    user_id: i64,
+    account: &str,
+    fallback: Option<&str>,
+    bytes: &[u8],
+) -> Result<(String, Option<ImportDraft>), FinanceError> {
+    // Names are matched as in the commands, in any case or by an alias, and
+    // the draft keeps the row's own name.
+    let (categories, accounts) = named_rows(store, user_id).await?;
+    let own_name = |rows: &[NamedRow], name: &str| {
+        let id = resolve_name(rows, name)?;
+        let row = rows.iter().find(|row| row.id == id && !row.alias)?;
+        Some((id, row.name.clone()))
+    };
+    let Some((account_id, account)) = own_name(&accounts, account) else {
+        return Err(FinanceError::UnknownAccount(account.to_string()));
+    };
+    let known = |name: &str| resolve_name(&categories, name).is_some();
+    let fallback = match fallback {
+        Some(name) => match own_name(&categories, name) {
+            Some((_, name)) => Some(name),
+            None => return Err(FinanceError::UnknownCategory(name.to_string())),
+        },
+        None => None,
+    };
+
+    let mapping = store.get_import_mapping(user_id).await?;
+    let statement = read_statement(bytes, mapping)?;
+    let imported = store.get_import_fingerprints(user_id).await?;
+    let rules = store.get_payee_rules(user_id).await?;
+
+    let mut rows = vec![];
+    let (mut already_imported, mut by_rules, mut uncategorized) = (0, 0, 0);
+    for mut row in statement.rows {
+        let entry = NewEntry {
+            amount: row.amount,
+            category: String::new(),
+            account: account.clone(),
+            date: Some(row.date),
+            import_ref: Some(row.import_ref.clone()),
+            note: Note::default(),
+        };
+        if imported.contains(&fingerprint(account_id, &entry)) {
+            already_imported += 1;
+            continue;
+        }
+        if let Some(category) = match_payee(&rules, &row.description) {
+            row.category = category.to_string();
+            by_rules += 1;
+        } else if !known(&row.category) {
+            match &fallback {
+                Some(fallback) => row.category = fallback.clone(),
+                None => {
+                    uncategorized += 1;
+                    continue;
+                }
+            }
+        }
+        rows.push(row);
+    }
+
+    let total = |kind| -> Money {
+        rows.iter()
+            .filter(|row| row.kind == kind)
+            .map(|row| row.amount)
+            .sum()
+    };
+    let source = match statement.bank {
+        Some(bank) => format!("Выписка {bank}"),
+        None => "Выписка по вашей схеме колонок".to_string(),
+    };
+    let mut text = format!(
+        "{source}, аккаунт {account}\nНовых операций: {} (расходы {}, доходы {})\n",
+        rows.len(),
+        total(EntryKind::Expense),
+        total(EntryKind::Income)
+    );
+    if already_imported > 0 {
+        text += &format!("Уже импортированы: {already_imported}\n");
+    }
+    if by_rules > 0 {
+        text += &format!("Категории по правилам получателей: {by_rules}\n");
+    }
+    if uncategorized > 0 {
+        text += &format!(
+            "Без подходящей категории, будут пропущены: {uncategorized}. Укажите категорию для них в подписи к файлу\n"
+        );
+    }
+    if rows.is_empty() {
+        text += "Импортировать нечего";
+        return Ok((text, None));
+    }
+
+    text += "\n";
+    for row in rows.iter().take(PREVIEW_ROWS) {
+        let sign = if row.kind == EntryKind::Income {
+            "+"
+        } else {
+            "-"
+        };
+        text += &format!(
+            "{} {sign}{} {} {}\n",
+            row.date, row.amount, row.category, row.description
+        );
+    }
+    if rows.len() > PREVIEW_ROWS {
+        text += &format!("и ещё {}\n", rows.len() - PREVIEW_ROWS);
+    }
+
+    let draft = ImportDraft { account, rows };
+    Ok((text, Some(draft)))
+}
+
+/// Books the previewed statement of the user.
+pub async fn confirm_import(
+    store: &dyn FinanceStore,
+    drafts: &ImportDrafts,
+    user_id: i64,
+) -> Reply {
+    let Some(draft) = drafts.take(user_id) else {
+        return "Нет выписки для импорта, отправьте файл ещё раз".into();
+    };
+    match store.import_rows(user_id, draft.account, draft.rows).await {
+        Ok(added) => format!("Импортировано операций: {added}").into(),
+        Err(e) => error_message(e).into(),
+    }
+}
+
+pub fn cancel_import(drafts: &ImportDrafts, user_id: i64) -> Reply {
+    drafts.take(user_id);
+    "Импорт отменён".into()
+}
+
+pub async fn import_mapping_handler(
+    store: &dyn FinanceStore,
+    user_id: i64,
+    columns: String,
+) -> Reply {
+    if columns.trim().is_empty() {
+        return match store.get_import_mapping(user_id).await {
+            Ok(Some(mapping)) => format!("Схема колонок: {mapping}").into(),
+            Ok(None) => "Схема колонок не задана".into(),
+            Err(e) => error_message(e).into(),
+        };
+    }
+
+    let result = match columns.parse::<ColumnMapping>() {
+        Ok(mapping) => store.set_import_mapping(user_id, mapping).await,
+        Err(e) => Err(e.into()),
+    };
+    match result {
+        Ok(()) => "Схема колонок сохранена".into(),
+        Err(e) => error_message(e).into(),
+    }
+}
+
+/// Lists the payee rules or sets one from `<payee> <category>`, the payee
+/// taking every argument but the last.
+pub async fn payee_rule_handler(store: &dyn FinanceStore, user_id: i64, args: String) -> Reply {
+    let args = args.trim();
+    if args.is_empty() {
+        return match store.get_payee_rules(user_id).await {
+            Ok(rules) if rules.is_empty() => "Правил получателей нет".into(),
+            Ok(rules) => {
+                let mut text = "Правила получателей:\n".to_string();
+                for rule in rules {
+                    text += &format!("{} → {}\n", rule.pattern, rule.category);
+                }
+                text.into()
+            }
+            Err(e) => error_message(e).into(),
+        };
+    }
+
+    let mut args = split_args(args).unwrap_or_default();
+    let category = args.pop().unwrap_or_default();
+    if args.is_empty() || category.is_empty() {
+        return "Укажите получателя и категорию, например: /payeerule yandex taxi такси".into();
+    }
+    match store
+        .set_payee_rule(user_id, args.join(" "), category)
+        .await
+    {
+        Ok(()) => "Правило сохранено".into(),
+        Err(e) => error_message(e).into(),
+    }
+}
+
+pub async fn del_payee_rule_handler(
+    store: &dyn FinanceStore,
+    user_id: i64,
+    pattern: String,
+) -> Reply {
+    match store
+        .del_payee_rule(user_id, Args::new(&pattern).rest())
+        .await
+    {
+        Ok(()) => "Правило удалено".into(),
+        Err(FinanceError::NotFound) => "Правило не найдено".into(),
+        Err(e) => error_message(e).into(),
+    }
+}
+
+/// Downloads a statement sent as a document and answers with its preview.
+pub async fn document_handler(
+    bot: Bot,
+    msg: Message,
+    store: Store,
+    drafts: ImportDrafts,
+) -> ResponseResult<()> {
+    let Some(document) = msg.document() else {
+        return Ok(());
+    };
+    let file = bot.get_file(&document.file.id).await?;
+    let mut bytes = vec![];
+    bot.download_file(&file.path, &mut bytes).await?;
+
+    let reply = preview_import(&*store, &drafts, msg.chat.id.0, msg.caption(), &bytes).await;
+    send_reply(&bot, msg.chat.id, reply).await
+}
+
+/// Handles the buttons under a statement preview.
+pub async fn import_callback_handler(
+    bot: Bot,
+    q: CallbackQuery,
+    store: Store,
+    drafts: ImportDrafts,
+) -> ResponseResult<()> {
+    if let Some(message) = &q.message {
+        let user_id = message.chat.id.0;
+        let reply = match q.data.as_deref() {
+            Some(CONFIRM_IMPORT) => confirm_import(&*store, &drafts, user_id).await,
+            _ => cancel_import(&drafts, user_id),
+        };
+        bot.edit_message_text(message.chat.id, message.id, reply.text)
+            .await?;
+    }
+    bot.answer_callback_query(q.id).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::logic::store::tests::test_user_id;
+
+    const STATEMENT: &str = "Дата операции;Категория;Описание;Сумма в валюте счёта\n\
+        01.09.2026;food;Пятёрочка;350,00\n\
+        02.09.2026;Перевод;От Ивана;+1000,00\n";
+
+    async fn setup(store: &MemoryStore) -> i64 {
+        let user_id = test_user_id();
+        for (name, description) in [("food", "еда"), ("other", "прочее")] {
+            store
+                .add_category(Categories {
+                    id: None,
+                    name: name.to_string(),
+                    user_id,
+                    description: description.to_string(),
+                })
+                .await
+                .unwrap();
+        }
+        store
+            .add_account(Accounts {
+                id: None,
+                name: "sber".to_string(),
+                balance: Money(100000),
+                user_id,
+                currency: DEFAULT_CURRENCY.to_string(),
+            })
+            .await
+            .unwrap();
+        user_id
+    }
+
+    #[tokio::test]
+    async fn previews_confirms_and_skips_imported_rows() {
+        let store = MemoryStore::default();
+        let drafts = ImportDrafts::default();
+        let user_id = setup(&store).await;
+        let bytes = STATEMENT.as_bytes();
+
+        let preview = preview_import(&store, &drafts, user_id, Some("sber"), bytes).await;
+        assert!(preview.keyboard.is_some());
+        assert!(
+            preview.text.starts_with(
+                "Выписка Сбербанк, аккаунт sber\nНовых операций: 1 (расходы 350.00, доходы 0.00)\n\
+                 Без подходящей категории, будут пропущены: 1."
+            ),
+            "{}",
+            preview.text
+        );
+
+        let preview = preview_import(&store, &drafts, user_id, Some("sber other"), bytes).await;
+        assert!(
+            preview.text.contains("2026-09-02 +1000.00 other От Ивана"),
+            "{}",
+            preview.text
+        );
+        assert_eq!(
+            confirm_import(&store, &drafts, user_id).await.text,
+            "Импортировано операций: 2"
+        );
+        let balance = store.get_accounts(user_id).await.unwrap()[0].balance;
+        assert_eq!(balance, Money(100000 - 35000 + 100000));
+
+        let preview = preview_import(&store, &drafts, user_id, Some("sber other"), bytes).await;
+        assert!(preview.keyboard.is_none());
+        assert!(
+            preview.text.contains("Уже импортированы: 2"),
+            "{}",
+            preview.text
+        );
+        assert_eq!(
+            confirm_import(&store, &drafts, user_id).await.text,
+            "Нет выписки для импорта, отправьте файл ещё раз"
+        );
+    }
+
+    #[tokio::test]
+    async fn statement_names_match_in_any_case_and_by_alias() {
+        let store = MemoryStore::default();
+        let drafts = ImportDrafts::default();
+        let user_id = setup(&store).await;
+        store
+            .set_alias(user_id, "прочее".into(), "other".into())
+            .await
+            .unwrap();
+        let statement = STATEMENT.replace(";food;", ";FOOD;");
+
+        let preview = preview_import(
+            &store,
+            &drafts,
+            user_id,
+            Some("SBER Прочее"),
+            statement.as_bytes(),
+        )
+        .await;
+        assert!(
+            preview.text.starts_with(
+                "Выписка Сбербанк, аккаунт sber\nНовых операций: 2 (расходы 350.00, доходы 1000.00)"
+            ),
+            "{}",
+            preview.text
+        );
+        assert_eq!(
+            confirm_import(&store, &drafts, user_id).await.text,
+            "Импортировано операций: 2"
+        );
+        let incomes = store.get_income(user_id, Period::All).await.unwrap();
+        assert_eq!(incomes[0].category, "other");
+    }
+
+    #[tokio::test]
+    async fn caption_account_may_be_quoted() {
+        let store = MemoryStore::default();
+        let drafts = ImportDrafts::default();
+        let user_id = setup(&store).await;
+        store
+            .add_account(Accounts {
+                id: None,
+                name: "Tinkoff Black".to_string(),
+                balance: Money::ZERO,
+                user_id,
+                currency: DEFAULT_CURRENCY.to_string(),
+            })
+            .await
+            .unwrap();
+
+        let caption = Some("«tinkoff black» other");
+        let preview = preview_import(&store, &drafts, user_id, caption, STATEMENT.as_bytes()).await;
+        assert!(
+            preview.text.starts_with(
+                "Выписка Сбербанк, аккаунт Tinkoff Black\nНовых операций: 2 (расходы 350.00, доходы 1000.00)"
+            ),
+            "{}",
+            preview.text
+        );
+    }
+
+    #[tokio::test]
+    async fn payee_rules_categorize_ofx_rows() {
+        let store = MemoryStore::default();
+        let drafts = ImportDrafts::default();
+        let user_id = setup(&store).await;
+        let ofx = "<OFX><BANKTRANLIST>\
+            <STMTTRN><DTPOSTED>20260901<TRNAMT>-350.00<FITID>1<NAME>PYATEROCHKA 123</STMTTRN>\
+            <STMTTRN><DTPOSTED>20260902<TRNAMT>-90.00<FITID>2<NAME>Metro</STMTTRN>\
+            </BANKTRANLIST></OFX>";
+
+        let reply = payee_rule_handler(&store, user_id, "Pyaterochka food".to_string()).await;
+        assert_eq!(reply.text, "Правило сохранено");
+        let reply = payee_rule_handler(&store, user_id, "metro bus".to_string()).await;
+        assert!(reply.text.starts_with("Категория \"bus\" не найдена"));
+        let reply = payee_rule_handler(&store, user_id, String::new()).await;
+        assert_eq!(reply.text, "Правила получателей:\npyaterochka → food\n");
+
+        let preview = preview_import(&store, &drafts, user_id, Some("sber"), ofx.as_bytes()).await;
+        assert!(
+            preview.text.starts_with(
+                "Выписка OFX, аккаунт sber\nНовых операций: 1 (расходы 350.00, доходы 0.00)\n\
+                 Категории по правилам получателей: 1\n\
+                 Без подходящей категории, будут пропущены: 1."
+            ),
+            "{}",
+            preview.text
+        );
+        // Nothing is booked before the confirmation.
+        assert_eq!(
+            store.get_accounts(user_id).await.unwrap()[0].balance,
+            Money(100000)
+        );
+        assert_eq!(
+            confirm_import(&store, &drafts, user_id).await.text,
+            "Импортировано операций: 1"
+        );
+        let expenses = store.get_expense(user_id, Period::All).await.unwrap();
+        assert_eq!(expenses[0].category, "food");
+
+        let reply = del_payee_rule_handler(&store, user_id, "PYATEROCHKA".to_string()).await;
+        assert_eq!(reply.text, "Правило удалено");
+        let reply = del_payee_rule_handler(&store, user_id, "pyaterochka".to_string()).await;
+        assert_eq!(reply.text, "Правило не найдено");
+    }
+
+    #[tokio::test]
+    async fn rejects_unknown_account_and_layout() {
+        let store = MemoryStore::default();
+        let drafts = ImportDrafts::default();
+        let user_id = setup(&store).await;
+
+        let reply = preview_import(&store, &drafts, user_id, None, b"").await;
+        assert_eq!(reply.text, IMPORT_HELP);
+        let reply = preview_import(&store, &drafts, user_id, Some("bank"), b"").await;
+        assert!(reply.text.starts_with("Аккаунт \"bank\" не найден"));
+        let reply = preview_import(&store, &drafts, user_id, Some("\"sber"), b"").await;
+        assert_eq!(reply.text, IMPORT_HELP);
+
+        let csv = b"when,sum,what\n2026-09-01,-10,food\n";
+        let reply = preview_import(&store, &drafts, user_id, Some("sber"), csv).await;
+        assert!(reply.text.contains("/importmapping"), "{}", reply.text);
+
+        let reply = import_mapping_handler(&store, user_id, "1 2 3".to_string()).await;
+        assert_eq!(reply.text, "Схема колонок сохранена");
+        let reply = preview_import(&store, &drafts, user_id, Some("sber"), csv).await;
+        assert!(reply.text.contains("Новых операций: 1"), "{}", reply.text);
+        assert_eq!(cancel_import(&drafts, user_id).text, "Импорт отменён");
+    }
+}
@@ -0,0 +1,89 @@
+use teloxide::utils::command::ParseError;
+
+/// Opening quotes and the quotes closing them. Phones replace the straight
+/// quote with typographic ones as it is typed.
+const QUOTES: [(char, char); 4] = [('"', '"'), ('“', '”'), ('„', '“'), ('«', '»')];
+
+/// Command arguments separated by whitespace, where a quoted one may
+/// contain spaces: `/addaccount "сбер вклад" 1000`.
+#[derive(Clone)]
+pub struct Args<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Args<'a> {
+    pub fn new(s: &'a str) -> Self {
+        Args { rest: s }
+    }
+
+    /// Next argument without its quotes, `None` after the last one.
+    pub fn next_arg(&mut self) -> Result<Option<String>, ParseError> {
+        let s = self.rest.trim_start();
+        let Some(first) = s.chars().next() else {
+            self.rest = s;
+            return Ok(None);
+        };
+        let Some(&(_, close)) = QUOTES.iter().find(|(open, _)| *open == first) else {
+            let end = s.find(char::is_whitespace).unwrap_or(s.len());
+            self.rest = &s[end..];
+            return Ok(Some(s[..end].to_string()));
+        };
+
+        let quoted = &s[first.len_utf8()..];
+        let Some(end) = quoted.find(close) else {
+            return Err(ParseError::IncorrectFormat(
+                format!("не закрыта кавычка в {s}").into(),
+            ));
+        };
+        self.rest = &quoted[end + close.len_utf8()..];
+        Ok(Some(quoted[..end].to_string()))
+    }
+
+    /// Text after the arguments taken, as typed, for a description. A single
+    /// quoted argument loses its quotes.
+    pub fn rest(self) -> String {
+        let mut args = self.clone();
+        match args.next_arg() {
+            Ok(Some(arg)) if args.rest.trim().is_empty() => arg,
+            _ => self.rest.trim().to_string(),
+        }
+    }
+}
+
+/// All the arguments of `s`.
+pub fn split_args(s: &str) -> Result<Vec<String>, ParseError> {
+    let mut args = Args::new(s);
+    let mut all = Vec::new();
+    while let Some(arg) = args.next_arg()? {
+        all.push(arg);
+    }
+    Ok(all)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quoted_arguments_keep_spaces() {
+        assert_eq!(
+            split_args(r#" 200  "кафе у дома" «сбер вклад» “наличные” x"#).unwrap(),
+            ["200", "кафе у дома", "сбер вклад", "наличные", "x"]
+        );
+        assert_eq!(split_args(r#"a "" b"#).unwrap(), ["a", "", "b"]);
+        assert!(split_args("").unwrap().is_empty());
+        assert!(split_args(r#"200 "кафе у дома"#).is_err());
+    }
+
+    #[test]
+    fn rest_is_free_text() {
+        let mut args = Args::new(r#""продукты из магазина"  еда и "химия" "#);
+        assert_eq!(
+            args.next_arg().unwrap().as_deref(),
+            Some("продукты из магазина")
+        );
+        assert_eq!(args.rest(), r#"еда и "химия""#);
+        assert_eq!(Args::new(r#" "yandex taxi" "#).rest(), "yandex taxi");
+        assert_eq!(Args::new("yandex taxi").rest(), "yandex taxi");
+    }
+}
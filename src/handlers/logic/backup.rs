@@ -0,0 +1,372 @@
+use super::{BudgetPeriod, ColumnMapping, ExchangeRates, Money, NewEntry, Note, Schedule};
+use chrono::{DateTime, Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+
+/// Version of the backup format, raised when older bots can't read it. 2
+/// added the recurring operations, the default account, the aliases and the
+/// notes of the entries, the backups of 1 are read without them.
+pub const BACKUP_VERSION: u32 = 2;
+
+/// Everything of one user, written by `/backup` and read by `/restore`. Rows
+/// refer to each other by their ids on the instance the backup was made on.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Backup {
+    pub version: u32,
+    pub created_at: DateTime<Local>,
+    pub accounts: Vec<BackupAccount>,
+    pub categories: Vec<BackupCategory>,
+    pub expenses: Vec<BackupEntry>,
+    pub income: Vec<BackupEntry>,
+    pub transfers: Vec<BackupTransfer>,
+    /// Missing from the backups made before `/addrecurring`.
+    #[serde(default)]
+    pub recurring: Vec<BackupRecurring>,
+    pub settings: BackupSettings,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BackupAccount {
+    pub id: i64,
+    pub name: String,
+    pub balance: Money,
+    pub currency: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BackupCategory {
+    pub id: i64,
+    pub name: String,
+    pub description: String,
+    pub budget: Option<BackupBudget>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BackupBudget {
+    pub limit: Money,
+    pub period: BudgetPeriod,
+}
+
+/// Expense or income.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BackupEntry {
+    pub account_id: i64,
+    pub category_id: i64,
+    pub amount: Money,
+    pub occurred_at: DateTime<Local>,
+    #[serde(default)]
+    pub import_ref: Option<String>,
+    #[serde(default)]
+    pub note: Note,
+}
+
+impl BackupEntry {
+    /// Entry to take the fingerprint of once the account has its new id.
+    pub fn new_entry(&self) -> NewEntry {
+        NewEntry {
+            amount: self.amount,
+            category: String::new(),
+            account: String::new(),
+            date: Some(self.occurred_at.date_naive()),
+            import_ref: self.import_ref.clone(),
+            note: Note::default(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BackupTransfer {
+    pub from_account_id: i64,
+    pub to_account_id: i64,
+    pub amount: Money,
+    pub occurred_at: DateTime<Local>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BackupRecurring {
+    pub income: bool,
+    pub account_id: i64,
+    pub category_id: i64,
+    pub amount: Money,
+    pub schedule: Schedule,
+    pub next_run: NaiveDate,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BackupSettings {
+    pub base_currency: String,
+    pub rates: Vec<ExchangeRates>,
+    pub import_mapping: Option<ColumnMapping>,
+    pub payee_rules: Vec<BackupPayeeRule>,
+    #[serde(default)]
+    pub default_account_id: Option<i64>,
+    #[serde(default)]
+    pub aliases: Vec<BackupAlias>,
+}
+
+/// Alias of either a category or an account.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BackupAlias {
+    pub alias: String,
+    pub category_id: Option<i64>,
+    pub account_id: Option<i64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BackupPayeeRule {
+    pub pattern: String,
+    pub category_id: i64,
+}
+
+#[derive(Debug)]
+pub struct BackupError(String);
+
+impl fmt::Display for BackupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for BackupError {}
+
+impl Backup {
+    pub fn to_json(&self) -> Vec<u8> {
+        serde_json::to_vec_pretty(self).expect("backup serializes")
+    }
+
+    /// Reads a backup, checking its version first so that a backup of a newer
+    /// bot is reported as such rather than as a broken file.
+    pub fn from_json(bytes: &[u8]) -> Result<Backup, BackupError> {
+        #[derive(Deserialize)]
+        struct Header {
+            version: u32,
+        }
+
+        let broken =
+            |e: serde_json::Error| BackupError(format!("файл не похож на резервную копию: {e}"));
+        let header: Header = serde_json::from_slice(bytes).map_err(broken)?;
+        if header.version > BACKUP_VERSION {
+            return Err(BackupError(format!(
+                "копия версии {} сделана более новым ботом, поддерживается версия {BACKUP_VERSION}",
+                header.version
+            )));
+        }
+        let backup: Backup = serde_json::from_slice(bytes).map_err(broken)?;
+        backup.validate()?;
+        Ok(backup)
+    }
+
+    /// Checks that every row refers to an account or category of the backup,
+    /// so a restore fails before writing anything.
+    pub fn validate(&self) -> Result<(), BackupError> {
+        let mut accounts = HashSet::new();
+        for acc in &self.accounts {
+            if !accounts.insert(acc.id) {
+                return Err(BackupError(format!("аккаунт {} повторяется", acc.id)));
+            }
+        }
+        let mut categories = HashSet::new();
+        for cat in &self.categories {
+            if !categories.insert(cat.id) {
+                return Err(BackupError(format!("категория {} повторяется", cat.id)));
+            }
+        }
+
+        let account = |id: i64| {
+            accounts
+                .contains(&id)
+                .then_some(())
+                .ok_or_else(|| BackupError(format!("нет аккаунта {id}")))
+        };
+        let category = |id: i64| {
+            categories
+                .contains(&id)
+                .then_some(())
+                .ok_or_else(|| BackupError(format!("нет категории {id}")))
+        };
+        let positive = |amount: Money| {
+            (amount > Money::ZERO)
+                .then_some(())
+                .ok_or_else(|| BackupError(format!("сумма {amount} не больше нуля")))
+        };
+        for entry in self.expenses.iter().chain(&self.income) {
+            account(entry.account_id)?;
+            category(entry.category_id)?;
+            positive(entry.amount)?;
+        }
+        for transfer in &self.transfers {
+            account(transfer.from_account_id)?;
+            account(transfer.to_account_id)?;
+            positive(transfer.amount)?;
+        }
+        for recurring in &self.recurring {
+            account(recurring.account_id)?;
+            category(recurring.category_id)?;
+            positive(recurring.amount)?;
+        }
+        for rule in &self.settings.payee_rules {
+            category(rule.category_id)?;
+        }
+        if let Some(id) = self.settings.default_account_id {
+            account(id)?;
+        }
+        for alias in &self.settings.aliases {
+            match (alias.category_id, alias.account_id) {
+                (Some(id), None) => category(id)?,
+                (None, Some(id)) => account(id)?,
+                _ => {
+                    return Err(BackupError(format!(
+                        "псевдоним {} должен относиться к категории или к аккаунту",
+                        alias.alias
+                    )))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// What the backup holds, for the confirmation of `/restore`.
+    pub fn summary(&self) -> String {
+        format!(
+            "аккаунтов: {}, категорий: {}, расходов: {}, доходов: {}, переводов: {}, регулярных операций: {}",
+            self.accounts.len(),
+            self.categories.len(),
+            self.expenses.len(),
+            self.income.len(),
+            self.transfers.len(),
+            self.recurring.len()
+        )
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    pub(crate) fn sample_backup() -> Backup {
+        let at = Local.with_ymd_and_hms(2026, 9, 15, 12, 30, 0).unwrap();
+        Backup {
+            version: BACKUP_VERSION,
+            created_at: at,
+            accounts: vec![
+                BackupAccount {
+                    id: 7,
+                    name: "card".to_string(),
+                    balance: Money(80000),
+                    currency: "RUB".to_string(),
+                },
+                BackupAccount {
+                    id: 9,
+                    name: "cash".to_string(),
+                    balance: Money(1000),
+                    currency: "USD".to_string(),
+                },
+            ],
+            categories: vec![BackupCategory {
+                id: 3,
+                name: "food".to_string(),
+                description: "еда".to_string(),
+                budget: Some(BackupBudget {
+                    limit: Money(500000),
+                    period: BudgetPeriod::Week,
+                }),
+            }],
+            expenses: vec![BackupEntry {
+                account_id: 7,
+                category_id: 3,
+                amount: Money(19990),
+                occurred_at: at,
+                import_ref: Some("15.09.2026|-199,90|Кофейня".to_string()),
+                note: Note::new("с коллегами".to_string(), ["work".to_string()]),
+            }],
+            income: vec![BackupEntry {
+                account_id: 9,
+                category_id: 3,
+                amount: Money(5000),
+                occurred_at: at,
+                import_ref: None,
+                note: Note::default(),
+            }],
+            transfers: vec![BackupTransfer {
+                from_account_id: 7,
+                to_account_id: 9,
+                amount: Money(1000),
+                occurred_at: at,
+            }],
+            recurring: vec![BackupRecurring {
+                income: false,
+                account_id: 7,
+                category_id: 3,
+                amount: Money(3000000),
+                schedule: Schedule::Monthly(5),
+                next_run: at.date_naive(),
+            }],
+            settings: BackupSettings {
+                base_currency: "USD".to_string(),
+                rates: vec![ExchangeRates {
+                    from: "USD".to_string(),
+                    to: "RUB".to_string(),
+                    rate: 92.5,
+                }],
+                import_mapping: Some(ColumnMapping {
+                    date: 0,
+                    amount: 4,
+                    category: 9,
+                    description: None,
+                }),
+                payee_rules: vec![BackupPayeeRule {
+                    pattern: "кофейня".to_string(),
+                    category_id: 3,
+                }],
+                default_account_id: Some(7),
+                aliases: vec![BackupAlias {
+                    alias: "кофе".to_string(),
+                    category_id: Some(3),
+                    account_id: None,
+                }],
+            },
+        }
+    }
+
+    #[test]
+    fn round_trips_through_json() {
+        let backup = sample_backup();
+        let json = backup.to_json();
+        assert!(String::from_utf8_lossy(&json).contains("\"version\": 2"));
+        assert_eq!(Backup::from_json(&json).unwrap(), backup);
+    }
+
+    #[test]
+    fn rejects_newer_and_inconsistent_backups() {
+        let err = Backup::from_json(br#"{"version": 3, "accounts": []}"#).unwrap_err();
+        assert!(err.to_string().starts_with("копия версии 3"), "{err}");
+        assert!(Backup::from_json(b"date,amount").is_err());
+
+        let mut backup = sample_backup();
+        backup.income[0].account_id = 8;
+        let err = Backup::from_json(&backup.to_json()).unwrap_err();
+        assert_eq!(err.to_string(), "нет аккаунта 8");
+    }
+
+    #[test]
+    fn reads_backups_of_the_first_version() {
+        let mut json: serde_json::Value =
+            serde_json::from_slice(&sample_backup().to_json()).unwrap();
+        json["version"] = 1.into();
+        json.as_object_mut().unwrap().remove("recurring");
+        let settings = json["settings"].as_object_mut().unwrap();
+        settings.remove("default_account_id");
+        settings.remove("aliases");
+        for entry in json["expenses"].as_array_mut().unwrap() {
+            entry.as_object_mut().unwrap().remove("note");
+        }
+        let backup = Backup::from_json(json.to_string().as_bytes()).unwrap();
+        assert_eq!(backup.version, 1);
+        assert!(backup.recurring.is_empty());
+        assert_eq!(backup.settings.default_account_id, None);
+        assert!(backup.settings.aliases.is_empty());
+        assert_eq!(backup.expenses[0].note, Note::default());
+    }
+}
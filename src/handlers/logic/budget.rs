@@ -0,0 +1,147 @@
+use super::{totals, ExchangeRates, Money, Period};
+use std::fmt;
+use std::str::FromStr;
+
+/// Share of the budget at which the user gets a warning, in percent.
+pub const BUDGET_THRESHOLDS: [i64; 2] = [100, 80];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BudgetPeriod {
+    Week,
+    #[default]
+    Month,
+}
+
+impl BudgetPeriod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BudgetPeriod::Week => "week",
+            BudgetPeriod::Month => "month",
+        }
+    }
+
+    pub fn period(&self) -> Period {
+        match self {
+            BudgetPeriod::Week => Period::Week,
+            BudgetPeriod::Month => Period::Month,
+        }
+    }
+}
+
+impl FromStr for BudgetPeriod {
+    type Err = BudgetPeriodParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "week" | "неделя" => Ok(BudgetPeriod::Week),
+            "month" | "месяц" => Ok(BudgetPeriod::Month),
+            _ => Err(BudgetPeriodParseError(s.to_string())),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct BudgetPeriodParseError(String);
+
+impl fmt::Display for BudgetPeriodParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "неизвестный период бюджета \"{}\", используйте month или week",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for BudgetPeriodParseError {}
+
+/// A budget with the amounts spent in the currencies of the accounts.
+pub struct BudgetSums {
+    pub category: String,
+    pub limit: Money,
+    pub period: BudgetPeriod,
+    /// Currency and sum.
+    pub spent: Vec<(String, Money)>,
+}
+
+pub struct Budgets {
+    pub category: String,
+    /// In the base currency.
+    pub limit: Money,
+    pub period: BudgetPeriod,
+    /// In the base currency, leaving out currencies without a rate to it.
+    pub spent: Money,
+}
+
+impl Budgets {
+    pub fn new(sums: BudgetSums, base: &str, rates: &[ExchangeRates]) -> Self {
+        Budgets {
+            category: sums.category,
+            limit: sums.limit,
+            period: sums.period,
+            spent: totals(sums.spent, base, rates).converted,
+        }
+    }
+
+    pub fn percent(&self) -> i64 {
+        percent(self.spent, self.limit)
+    }
+
+    /// The highest threshold passed by spending `amount`, which is already
+    /// included in `spent`.
+    pub fn crossed_threshold(&self, amount: Money) -> Option<i64> {
+        let before = percent(self.spent - amount, self.limit);
+        let after = self.percent();
+        BUDGET_THRESHOLDS
+            .into_iter()
+            .find(|&threshold| before < threshold && after >= threshold)
+    }
+}
+
+fn percent(spent: Money, limit: Money) -> i64 {
+    if limit.0 <= 0 {
+        return 0;
+    }
+    (spent.0 as i128 * 100 / limit.0 as i128) as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn budget(limit: i64, spent: i64) -> Budgets {
+        Budgets {
+            category: "кафе".to_string(),
+            limit: Money(limit),
+            period: BudgetPeriod::Month,
+            spent: Money(spent),
+        }
+    }
+
+    #[test]
+    fn parses_periods() {
+        assert_eq!("week".parse::<BudgetPeriod>().unwrap(), BudgetPeriod::Week);
+        assert_eq!(
+            "Месяц".parse::<BudgetPeriod>().unwrap(),
+            BudgetPeriod::Month
+        );
+        assert!("year".parse::<BudgetPeriod>().is_err());
+    }
+
+    #[test]
+    fn reports_only_newly_crossed_thresholds() {
+        assert_eq!(budget(1000, 500).crossed_threshold(Money(100)), None);
+        assert_eq!(budget(1000, 800).crossed_threshold(Money(100)), Some(80));
+        assert_eq!(budget(1000, 850).crossed_threshold(Money(10)), None);
+        assert_eq!(budget(1000, 1000).crossed_threshold(Money(150)), Some(100));
+        assert_eq!(budget(1000, 1200).crossed_threshold(Money(900)), Some(100));
+        assert_eq!(budget(1000, 1200).crossed_threshold(Money(100)), None);
+    }
+
+    #[test]
+    fn computes_percent() {
+        assert_eq!(budget(1000, 250).percent(), 25);
+        assert_eq!(budget(0, 250).percent(), 0);
+    }
+}
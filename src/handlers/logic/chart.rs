@@ -0,0 +1,407 @@
+use super::{convert, EntryKind, ExchangeRates, LedgerEntry, Money, Period};
+use chrono::NaiveDate;
+use plotters::prelude::*;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+/// Chart drawn by `/chart`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChartKind {
+    /// Expenses of the period by category.
+    Pie,
+    /// Balance and expenses of the period by day.
+    Line,
+}
+
+impl FromStr for ChartKind {
+    type Err = ChartKindError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "pie" => Ok(ChartKind::Pie),
+            "line" => Ok(ChartKind::Line),
+            _ => Err(ChartKindError(s.to_string())),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ChartKindError(String);
+
+impl fmt::Display for ChartKindError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "неизвестный график \"{}\", доступны pie и line", self.0)
+    }
+}
+
+impl std::error::Error for ChartKindError {}
+
+/// Failure to draw a chart, not the user's fault.
+#[derive(Debug)]
+pub struct ChartError(String);
+
+impl fmt::Display for ChartError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for ChartError {}
+
+impl<E: std::error::Error + Send + Sync> From<DrawingAreaErrorKind<E>> for ChartError {
+    fn from(e: DrawingAreaErrorKind<E>) -> Self {
+        ChartError(e.to_string())
+    }
+}
+
+/// Slices of the pie, the smallest categories past them are drawn as one.
+pub const PIE_SLICES: usize = 8;
+
+const PALETTE: [RGBColor; PIE_SLICES] = [
+    RGBColor(66, 133, 244),
+    RGBColor(219, 68, 55),
+    RGBColor(244, 180, 0),
+    RGBColor(15, 157, 88),
+    RGBColor(171, 71, 188),
+    RGBColor(0, 172, 193),
+    RGBColor(255, 112, 67),
+    RGBColor(158, 158, 158),
+];
+
+const PIE_SIZE: (u32, u32) = (900, 600);
+const LINE_SIZE: (u32, u32) = (1000, 700);
+
+/// Used when `CHART_FONT` is not set, DejaVu comes with most distributions
+/// and has Cyrillic.
+const DEFAULT_FONT: &str = "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf";
+
+/// Expenses among `entries` by category in the base currency, largest first.
+/// Entries without a rate to the base currency are left out.
+pub fn spending_by_category(
+    entries: &[LedgerEntry],
+    base: &str,
+    rates: &[ExchangeRates],
+) -> Vec<(String, Money)> {
+    let mut sums: HashMap<&str, Money> = HashMap::new();
+    for entry in entries.iter().filter(|e| e.kind == EntryKind::Expense) {
+        if let Some(amount) = convert(entry.amount, &entry.currency, base, rates) {
+            *sums.entry(&entry.category).or_insert(Money::ZERO) += amount;
+        }
+    }
+
+    let mut sums: Vec<(String, Money)> = sums
+        .into_iter()
+        .map(|(category, sum)| (category.to_string(), sum))
+        .collect();
+    sums.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    if sums.len() > PIE_SLICES {
+        let rest = sums.drain(PIE_SLICES - 1..).map(|(_, sum)| sum).sum();
+        sums.push(("прочее".to_string(), rest));
+    }
+    sums
+}
+
+/// Point of the line chart, in the base currency.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChartDay {
+    pub date: NaiveDate,
+    /// Total of all accounts at the end of the day.
+    pub balance: Money,
+    pub spent: Money,
+}
+
+/// Days of `period` up to `today`. `entries` is the whole ledger and
+/// `balance` the current total, earlier balances are counted back from it.
+pub fn daily_series(
+    entries: &[LedgerEntry],
+    balance: Money,
+    period: Period,
+    today: NaiveDate,
+    base: &str,
+    rates: &[ExchangeRates],
+) -> Vec<ChartDay> {
+    let mut net: BTreeMap<NaiveDate, Money> = BTreeMap::new();
+    let mut spent: HashMap<NaiveDate, Money> = HashMap::new();
+    for entry in entries {
+        let Some(amount) = convert(entry.amount, &entry.currency, base, rates) else {
+            continue;
+        };
+        let date = entry.occurred_at.date_naive();
+        match entry.kind {
+            EntryKind::Expense => {
+                *net.entry(date).or_insert(Money::ZERO) += -amount;
+                *spent.entry(date).or_insert(Money::ZERO) += amount;
+            }
+            EntryKind::Income => *net.entry(date).or_insert(Money::ZERO) += amount,
+            // Moves money between accounts, the total stays.
+            EntryKind::Transfer => {}
+        }
+    }
+
+    let (from, to) = match period.bounds(today) {
+        Some((from, to)) => (from, to.pred_opt().unwrap_or(to).min(today)),
+        None => (
+            net.keys().next().copied().unwrap_or(today).min(today),
+            today,
+        ),
+    };
+    let mut balance = balance
+        - net
+            .range(to.succ_opt().unwrap_or(to)..)
+            .map(|(_, sum)| *sum)
+            .sum();
+
+    let days: Vec<NaiveDate> = from.iter_days().take_while(|date| *date <= to).collect();
+    let mut series: Vec<ChartDay> = days
+        .into_iter()
+        .rev()
+        .map(|date| {
+            let day = ChartDay {
+                date,
+                balance,
+                spent: spent.get(&date).copied().unwrap_or(Money::ZERO),
+            };
+            balance = balance - net.get(&date).copied().unwrap_or(Money::ZERO);
+            day
+        })
+        .collect();
+    series.reverse();
+    series
+}
+
+/// Registers the font of the charts as "sans-serif" once, read from the
+/// `CHART_FONT` path or the DejaVu one.
+fn load_font() -> Result<(), ChartError> {
+    static FONT: OnceLock<Result<(), String>> = OnceLock::new();
+    FONT.get_or_init(|| {
+        let path = std::env::var("CHART_FONT").unwrap_or_else(|_| DEFAULT_FONT.to_string());
+        let bytes = std::fs::read(&path).map_err(|e| format!("font {path}: {e}"))?;
+        // plotters keeps the font for the life of the process.
+        let bytes: &'static [u8] = Box::leak(bytes.into_boxed_slice());
+        plotters::style::register_font("sans-serif", FontStyle::Normal, bytes)
+            .map_err(|_| format!("font {path} is not a TrueType font"))
+    })
+    .clone()
+    .map_err(ChartError)
+}
+
+fn encode_png(pixels: &[u8], (width, height): (u32, u32)) -> Result<Vec<u8>, ChartError> {
+    let broken = |e: png::EncodingError| ChartError(e.to_string());
+    let mut bytes = vec![];
+    let mut encoder = png::Encoder::new(&mut bytes, width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(broken)?;
+    writer.write_image_data(pixels).map_err(broken)?;
+    writer.finish().map_err(broken)?;
+    Ok(bytes)
+}
+
+fn as_units(amount: Money) -> f64 {
+    amount.0 as f64 / 100.0
+}
+
+/// Draws the slices of `spending_by_category` as a PNG.
+pub fn render_pie(title: &str, slices: &[(String, Money)]) -> Result<Vec<u8>, ChartError> {
+    load_font()?;
+    let mut pixels = vec![0; (PIE_SIZE.0 * PIE_SIZE.1 * 3) as usize];
+    {
+        let root = BitMapBackend::with_buffer(&mut pixels, PIE_SIZE).into_drawing_area();
+        root.fill(&WHITE)?;
+        let root = root.titled(title, ("sans-serif", 28))?;
+
+        let (width, height) = root.dim_in_pixel();
+        let center = (width as i32 / 2, height as i32 / 2);
+        let radius = f64::from(width.min(height)) * 0.35;
+        let sizes: Vec<f64> = slices.iter().map(|(_, sum)| as_units(*sum)).collect();
+        let labels: Vec<String> = slices
+            .iter()
+            .map(|(category, sum)| format!("{category} {sum}"))
+            .collect();
+        let mut pie = Pie::new(&center, &radius, &sizes, &PALETTE[..slices.len()], &labels);
+        pie.start_angle(-90.0);
+        pie.label_style(("sans-serif", 18).into_font().color(&BLACK));
+        pie.percentages(("sans-serif", 16).into_font().color(&WHITE));
+        root.draw(&pie)?;
+        root.present()?;
+    }
+    encode_png(&pixels, PIE_SIZE)
+}
+
+/// Draws the balance above the daily expenses as a PNG.
+pub fn render_line(title: &str, days: &[ChartDay]) -> Result<Vec<u8>, ChartError> {
+    load_font()?;
+    let mut pixels = vec![0; (LINE_SIZE.0 * LINE_SIZE.1 * 3) as usize];
+    {
+        let root = BitMapBackend::with_buffer(&mut pixels, LINE_SIZE).into_drawing_area();
+        root.fill(&WHITE)?;
+        let root = root.titled(title, ("sans-serif", 28))?;
+
+        let (_, height) = root.dim_in_pixel();
+        let (upper, lower) = root.split_vertically(height / 2);
+        let balance: Vec<f64> = days.iter().map(|day| as_units(day.balance)).collect();
+        let spent: Vec<f64> = days.iter().map(|day| as_units(day.spent)).collect();
+        draw_series(&upper, "Баланс", &balance, BLUE, days)?;
+        draw_series(&lower, "Расходы за день", &spent, RED, days)?;
+        root.present()?;
+    }
+    encode_png(&pixels, LINE_SIZE)
+}
+
+fn draw_series(
+    area: &DrawingArea<BitMapBackend, plotters::coord::Shift>,
+    caption: &str,
+    values: &[f64],
+    color: RGBColor,
+    days: &[ChartDay],
+) -> Result<(), ChartError> {
+    let (low, high) = values.iter().fold((0.0f64, 0.0f64), |(low, high), v| {
+        (low.min(*v), high.max(*v))
+    });
+    let high = if high > low { high * 1.05 } else { low + 1.0 };
+    let last = (values.len() as i32 - 1).max(1);
+
+    let mut chart = ChartBuilder::on(area)
+        .caption(caption, ("sans-serif", 20))
+        .margin(10)
+        .x_label_area_size(30)
+        .y_label_area_size(90)
+        .build_cartesian_2d(0..last, low..high)?;
+    let date_label = |index: &i32| {
+        days.get(*index as usize)
+            .map(|day| day.date.format("%d.%m").to_string())
+            .unwrap_or_default()
+    };
+    chart
+        .configure_mesh()
+        .x_labels(10)
+        .x_label_formatter(&date_label)
+        .y_label_formatter(&|v| format!("{v:.0}"))
+        .label_style(("sans-serif", 14))
+        .draw()?;
+    chart.draw_series(LineSeries::new(
+        values.iter().enumerate().map(|(i, v)| (i as i32, *v)),
+        color.stroke_width(2),
+    ))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Local, TimeZone};
+
+    const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+    fn entry(day: u32, kind: EntryKind, category: &str, amount: i64) -> LedgerEntry {
+        LedgerEntry {
+            occurred_at: Local.with_ymd_and_hms(2026, 9, day, 12, 0, 0).unwrap(),
+            kind,
+            account: "card".to_string(),
+            category: category.to_string(),
+            amount: Money(amount),
+            currency: "RUB".to_string(),
+            note: String::new(),
+        }
+    }
+
+    #[test]
+    fn parses_chart_kind() {
+        assert_eq!("Pie".parse::<ChartKind>().unwrap(), ChartKind::Pie);
+        assert_eq!("line".parse::<ChartKind>().unwrap(), ChartKind::Line);
+        let err = "bar".parse::<ChartKind>().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "неизвестный график \"bar\", доступны pie и line"
+        );
+    }
+
+    #[test]
+    fn sums_expenses_by_category() {
+        let mut entries = vec![
+            entry(1, EntryKind::Expense, "кафе", 20000),
+            entry(2, EntryKind::Expense, "такси", 50000),
+            entry(3, EntryKind::Expense, "кафе", 40000),
+            entry(3, EntryKind::Income, "зарплата", 1000000),
+        ];
+        let slices = spending_by_category(&entries, "RUB", &[]);
+        assert_eq!(
+            slices,
+            vec![
+                ("кафе".to_string(), Money(60000)),
+                ("такси".to_string(), Money(50000)),
+            ]
+        );
+
+        for i in 0..PIE_SLICES as i64 {
+            entries.push(entry(4, EntryKind::Expense, &format!("c{i}"), 100 + i));
+        }
+        let slices = spending_by_category(&entries, "RUB", &[]);
+        assert_eq!(slices.len(), PIE_SLICES);
+        assert_eq!(
+            slices.last().unwrap(),
+            &("прочее".to_string(), Money(100 + 101 + 102))
+        );
+    }
+
+    #[test]
+    fn counts_balance_back_from_today() {
+        let entries = vec![
+            entry(1, EntryKind::Income, "зарплата", 100000),
+            entry(2, EntryKind::Expense, "кафе", 20000),
+            entry(2, EntryKind::Transfer, "", 5000),
+            entry(4, EntryKind::Expense, "такси", 10000),
+            entry(6, EntryKind::Expense, "кафе", 30000),
+        ];
+        let today = NaiveDate::from_ymd_opt(2026, 9, 5).unwrap();
+        let period = Period::Range {
+            from: NaiveDate::from_ymd_opt(2026, 9, 2).unwrap(),
+            to: NaiveDate::from_ymd_opt(2026, 9, 30).unwrap(),
+        };
+        // The expense of the 6th is already in the balance.
+        let days = daily_series(&entries, Money(40000), period, today, "RUB", &[]);
+        let points: Vec<(u32, i64, i64)> = days
+            .iter()
+            .map(|day| {
+                use chrono::Datelike;
+                (day.date.day(), day.balance.0, day.spent.0)
+            })
+            .collect();
+        assert_eq!(
+            points,
+            vec![
+                (2, 80000, 20000),
+                (3, 80000, 0),
+                (4, 70000, 10000),
+                (5, 70000, 0),
+            ]
+        );
+
+        let all = daily_series(&entries, Money(40000), Period::All, today, "RUB", &[]);
+        assert_eq!(all[0].date, NaiveDate::from_ymd_opt(2026, 9, 1).unwrap());
+        assert_eq!(all[0].balance, Money(100000));
+    }
+
+    #[test]
+    fn renders_png() {
+        let slices = vec![
+            ("кафе".to_string(), Money(60000)),
+            ("такси".to_string(), Money(50000)),
+        ];
+        let png = render_pie("Расходы", &slices).unwrap();
+        assert!(png.starts_with(PNG_SIGNATURE));
+
+        let days = daily_series(
+            &[entry(1, EntryKind::Expense, "кафе", 20000)],
+            Money(80000),
+            Period::All,
+            NaiveDate::from_ymd_opt(2026, 9, 3).unwrap(),
+            "RUB",
+            &[],
+        );
+        let png = render_line("Баланс", &days).unwrap();
+        assert!(png.starts_with(PNG_SIGNATURE));
+    }
+}
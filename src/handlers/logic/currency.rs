@@ -0,0 +1,187 @@
+use super::Money;
+use std::collections::BTreeMap;
+use std::fmt;
+
+pub const DEFAULT_CURRENCY: &str = "RUB";
+
+/// Normalizes a three-letter currency code such as `usd` to `USD`.
+pub fn parse_currency(s: &str) -> Result<String, CurrencyParseError> {
+    let code = s.trim().to_uppercase();
+    if code.len() == 3 && code.bytes().all(|b| b.is_ascii_uppercase()) {
+        Ok(code)
+    } else {
+        Err(CurrencyParseError(s.to_string()))
+    }
+}
+
+/// Parses a rate written as `92.5` or `92,5`.
+pub fn parse_rate(s: &str) -> Result<f64, CurrencyParseError> {
+    match s.trim().replace(',', ".").parse::<f64>() {
+        Ok(rate) if rate.is_finite() && rate > 0.0 => Ok(rate),
+        _ => Err(CurrencyParseError(s.to_string())),
+    }
+}
+
+#[derive(Debug)]
+pub struct CurrencyParseError(String);
+
+impl fmt::Display for CurrencyParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "неверная валюта или курс \"{}\", пример: USD RUB 92.5",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for CurrencyParseError {}
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ExchangeRates {
+    pub from: String,
+    pub to: String,
+    pub rate: f64,
+}
+
+/// Parses `FROM,TO,RATE` records, one per line or separated by spaces.
+/// `;` is accepted as a separator too and a `from,to,rate` header is skipped.
+pub fn parse_rates_csv(csv: &str) -> Result<Vec<ExchangeRates>, CurrencyParseError> {
+    let mut rates = vec![];
+    for record in csv.split_whitespace() {
+        let fields: Vec<&str> = record.split([',', ';']).collect();
+        if fields[0].eq_ignore_ascii_case("from") {
+            continue;
+        }
+        // `USD,RUB,92,5` is a rate with a decimal comma.
+        let rate = match fields.len() {
+            3 => fields[2].to_string(),
+            4 => format!("{}.{}", fields[2], fields[3]),
+            _ => return Err(CurrencyParseError(record.to_string())),
+        };
+        rates.push(ExchangeRates {
+            from: parse_currency(fields[0])?,
+            to: parse_currency(fields[1])?,
+            rate: parse_rate(&rate)?,
+        });
+    }
+    Ok(rates)
+}
+
+/// Converts `amount` between currencies using a direct rate, an inverse one or
+/// a single hop through a third currency.
+pub fn convert(amount: Money, from: &str, to: &str, rates: &[ExchangeRates]) -> Option<Money> {
+    let rate = find_rate(from, to, rates).or_else(|| {
+        rates.iter().find_map(|r| {
+            let first = find_rate(from, &r.to, rates)?;
+            let second = find_rate(&r.to, to, rates)?;
+            Some(first * second)
+        })
+    })?;
+    Some(Money((amount.0 as f64 * rate).round() as i64))
+}
+
+fn find_rate(from: &str, to: &str, rates: &[ExchangeRates]) -> Option<f64> {
+    if from == to {
+        return Some(1.0);
+    }
+    rates.iter().find_map(|r| {
+        if r.from == from && r.to == to {
+            Some(r.rate)
+        } else if r.from == to && r.to == from {
+            Some(1.0 / r.rate)
+        } else {
+            None
+        }
+    })
+}
+
+pub struct Totals {
+    pub by_currency: BTreeMap<String, Money>,
+    pub converted: Money,
+    /// Currencies left out of `converted` because no rate to the base is known.
+    pub missing_rates: Vec<String>,
+}
+
+pub fn totals(
+    balances: impl IntoIterator<Item = (String, Money)>,
+    base: &str,
+    rates: &[ExchangeRates],
+) -> Totals {
+    let mut by_currency = BTreeMap::new();
+    for (currency, balance) in balances {
+        *by_currency.entry(currency).or_insert(Money::ZERO) += balance;
+    }
+
+    let mut converted = Money::ZERO;
+    let mut missing_rates = vec![];
+    for (currency, sum) in &by_currency {
+        match convert(*sum, currency, base, rates) {
+            Some(sum) => converted += sum,
+            None => missing_rates.push(currency.clone()),
+        }
+    }
+
+    Totals {
+        by_currency,
+        converted,
+        missing_rates,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rate(from: &str, to: &str, rate: f64) -> ExchangeRates {
+        ExchangeRates {
+            from: from.to_string(),
+            to: to.to_string(),
+            rate,
+        }
+    }
+
+    #[test]
+    fn parses_codes_and_rates() {
+        assert_eq!(parse_currency("usd").unwrap(), "USD");
+        assert!(parse_currency("US").is_err());
+        assert!(parse_currency("рубль").is_err());
+        assert_eq!(parse_rate("92,5").unwrap(), 92.5);
+        assert!(parse_rate("0").is_err());
+        assert!(parse_rate("abc").is_err());
+    }
+
+    #[test]
+    fn parses_csv() {
+        let rates = parse_rates_csv("from,to,rate\nUSD,RUB,92.5\neur;rub;100,25").unwrap();
+        assert_eq!(rates.len(), 2);
+        assert_eq!((rates[0].from.as_str(), rates[0].rate), ("USD", 92.5));
+        assert_eq!((rates[1].to.as_str(), rates[1].rate), ("RUB", 100.25));
+        assert!(parse_rates_csv("USD,RUB").is_err());
+    }
+
+    #[test]
+    fn converts_directly_inversely_and_through_a_hop() {
+        let rates = [rate("USD", "RUB", 90.0), rate("EUR", "RUB", 100.0)];
+        assert_eq!(convert(Money(100), "USD", "RUB", &rates), Some(Money(9000)));
+        assert_eq!(convert(Money(9000), "RUB", "USD", &rates), Some(Money(100)));
+        assert_eq!(convert(Money(900), "USD", "EUR", &rates), Some(Money(810)));
+        assert_eq!(convert(Money(100), "GBP", "RUB", &rates), None);
+    }
+
+    #[test]
+    fn totals_group_by_currency() {
+        let rates = [rate("USD", "RUB", 90.0)];
+        let balances = [
+            ("RUB".to_string(), Money(1000)),
+            ("USD".to_string(), Money(200)),
+            ("RUB".to_string(), Money(500)),
+            ("GBP".to_string(), Money(100)),
+        ];
+        let totals = totals(balances, "RUB", &rates);
+        assert_eq!(totals.by_currency["RUB"], Money(1500));
+        assert_eq!(totals.by_currency["USD"], Money(200));
+        assert_eq!(totals.converted, Money(1500 + 18000));
+        assert_eq!(totals.missing_rates, ["GBP"]);
+    }
+}
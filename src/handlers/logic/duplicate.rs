@@ -0,0 +1,49 @@
+use super::NewEntry;
+use chrono::Local;
+
+/// Minutes during which typing the same entry again asks for confirmation.
+pub const DUPLICATE_WINDOW_MINUTES: i64 = 5;
+
+/// Identifies an expense or income by account, amount, day and the bank's
+/// reference of an imported row. Imported rows with equal fingerprints are
+/// booked once.
+pub fn fingerprint(account_id: i64, entry: &NewEntry) -> String {
+    let date = entry.date.unwrap_or_else(|| Local::now().date_naive());
+    format!(
+        "{account_id}|{amount}|{date}|{external_ref}",
+        amount = entry.amount.0,
+        external_ref = entry.import_ref.as_deref().unwrap_or_default()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::logic::{Money, Note};
+    use chrono::NaiveDate;
+
+    #[test]
+    fn fingerprint_ignores_category() {
+        let entry = |category: &str, import_ref: Option<&str>| NewEntry {
+            amount: Money(19990),
+            category: category.to_string(),
+            account: "card".to_string(),
+            date: NaiveDate::from_ymd_opt(2026, 9, 15),
+            import_ref: import_ref.map(str::to_string),
+            note: Note::default(),
+        };
+        assert_eq!(fingerprint(7, &entry("кафе", None)), "7|19990|2026-09-15|");
+        assert_eq!(
+            fingerprint(7, &entry("кафе", None)),
+            fingerprint(7, &entry("еда", None))
+        );
+        assert_eq!(
+            fingerprint(7, &entry("кафе", Some("ref"))),
+            "7|19990|2026-09-15|ref"
+        );
+        assert_ne!(
+            fingerprint(7, &entry("кафе", None)),
+            fingerprint(8, &entry("кафе", None))
+        );
+    }
+}
@@ -0,0 +1,78 @@
+use super::{
+    BackupError, BudgetPeriodParseError, CurrencyParseError, DateParseError, MoneyParseError,
+    PeriodParseError, ScheduleParseError, StatementError,
+};
+use std::fmt;
+
+/// Error returned by every function in `handlers::logic`.
+#[derive(Debug)]
+pub enum FinanceError {
+    /// No account with this name belongs to the user.
+    UnknownAccount(String),
+    /// No category with this name belongs to the user.
+    UnknownCategory(String),
+    /// No row with this id exists.
+    NotFound,
+    /// The row exists but belongs to another user.
+    NotOwner,
+    /// The input was rejected before touching the database.
+    Validation(String),
+    Database(sqlx::Error),
+}
+
+impl fmt::Display for FinanceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FinanceError::UnknownAccount(name) => write!(f, "unknown account \"{name}\""),
+            FinanceError::UnknownCategory(name) => write!(f, "unknown category \"{name}\""),
+            FinanceError::NotFound => write!(f, "row not found"),
+            FinanceError::NotOwner => write!(f, "row belongs to another user"),
+            FinanceError::Validation(message) => write!(f, "{message}"),
+            FinanceError::Database(e) => write!(f, "database error: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for FinanceError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FinanceError::Database(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<sqlx::Error> for FinanceError {
+    fn from(e: sqlx::Error) -> Self {
+        FinanceError::Database(e)
+    }
+}
+
+impl From<sqlx::migrate::MigrateError> for FinanceError {
+    fn from(e: sqlx::migrate::MigrateError) -> Self {
+        FinanceError::Database(sqlx::Error::Migrate(Box::new(e)))
+    }
+}
+
+macro_rules! validation_from {
+    ($($error:ty),*) => {
+        $(
+            impl From<$error> for FinanceError {
+                fn from(e: $error) -> Self {
+                    FinanceError::Validation(e.to_string())
+                }
+            }
+        )*
+    };
+}
+
+validation_from!(
+    BackupError,
+    BudgetPeriodParseError,
+    CurrencyParseError,
+    DateParseError,
+    MoneyParseError,
+    PeriodParseError,
+    ScheduleParseError,
+    StatementError
+);
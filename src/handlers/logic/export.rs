@@ -0,0 +1,416 @@
+use super::{Accounts, FinanceError, Money, Period};
+use chrono::{DateTime, Local, NaiveDate};
+use futures::{Stream, TryStreamExt};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::str::FromStr;
+
+/// File format of `/export`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    /// Journal of hledger and ledger-cli.
+    Ledger,
+    Beancount,
+}
+
+impl ExportFormat {
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "finance.csv",
+            ExportFormat::Ledger => "finance.journal",
+            ExportFormat::Beancount => "finance.beancount",
+        }
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = ExportFormatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "csv" => Ok(ExportFormat::Csv),
+            "ledger" | "hledger" => Ok(ExportFormat::Ledger),
+            "beancount" => Ok(ExportFormat::Beancount),
+            _ => Err(ExportFormatError(s.to_string())),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ExportFormatError(String);
+
+impl fmt::Display for ExportFormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "неизвестный формат \"{}\", доступны csv, ledger и beancount",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for ExportFormatError {}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EntryKind {
+    Expense,
+    Income,
+    Transfer,
+}
+
+impl EntryKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EntryKind::Expense => "expense",
+            EntryKind::Income => "income",
+            EntryKind::Transfer => "transfer",
+        }
+    }
+
+    /// Reads the `kind` column written by the ledger queries.
+    pub(crate) fn from_column(kind: &str) -> Self {
+        match kind {
+            "income" => EntryKind::Income,
+            "transfer" => EntryKind::Transfer,
+            _ => EntryKind::Expense,
+        }
+    }
+}
+
+/// One row of the exported ledger. For a transfer `account` is the source
+/// account and `category` the destination one.
+pub struct LedgerEntry {
+    pub occurred_at: DateTime<Local>,
+    pub kind: EntryKind,
+    pub account: String,
+    pub category: String,
+    pub amount: Money,
+    /// Currency of `account`.
+    pub currency: String,
+    pub note: String,
+}
+
+/// Writes the entries as CSV with a header, reading them one at a time.
+pub async fn write_csv(
+    entries: impl Stream<Item = Result<LedgerEntry, FinanceError>>,
+) -> Result<Vec<u8>, FinanceError> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    // Writing into a Vec can't fail, so the csv errors are not carried around.
+    writer
+        .write_record(["date", "type", "account", "category", "amount", "note"])
+        .expect("write to Vec");
+
+    futures::pin_mut!(entries);
+    while let Some(entry) = entries.try_next().await? {
+        writer
+            .write_record([
+                entry.occurred_at.format("%Y-%m-%d %H:%M").to_string(),
+                entry.kind.as_str().to_string(),
+                entry.account,
+                entry.category,
+                entry.amount.to_string(),
+                entry.note,
+            ])
+            .expect("write to Vec");
+    }
+
+    Ok(writer.into_inner().expect("flush to Vec"))
+}
+
+const OPENING_BALANCES: &str = "Equity:Opening-Balances";
+
+/// Name of a bot account or category under `root` of the journal. Beancount
+/// wants every part capitalized and made of letters, digits and dashes.
+fn journal_account(format: ExportFormat, root: &str, name: &str) -> String {
+    let name: String = name
+        .trim()
+        .chars()
+        .map(|c| match format {
+            ExportFormat::Beancount if !c.is_alphanumeric() => '-',
+            _ if c == ':' || c.is_whitespace() => '-',
+            _ => c,
+        })
+        .collect();
+    let name = match format {
+        ExportFormat::Beancount => {
+            let mut chars = name.chars();
+            match chars.next() {
+                Some(first) if first.is_alphanumeric() => {
+                    first.to_uppercase().chain(chars).collect()
+                }
+                _ => format!("X{name}"),
+            }
+        }
+        _ => name,
+    };
+    format!("{root}:{name}")
+}
+
+/// Appends a transaction whose last posting is left for the journal to
+/// balance.
+fn write_transaction(
+    out: &mut String,
+    format: ExportFormat,
+    date: NaiveDate,
+    description: &str,
+    postings: &[(String, Money, &str)],
+    balancing: &str,
+) {
+    let description = description.replace(['\r', '\n'], " ");
+    match format {
+        ExportFormat::Beancount => {
+            let narration = description.replace('\\', "\\\\").replace('"', "\\\"");
+            *out += &format!("{date} * \"{narration}\"\n");
+        }
+        _ => *out += &format!("{date} {description}\n"),
+    }
+    for (account, amount, currency) in postings {
+        *out += &format!("    {account}  {amount} {currency}\n");
+    }
+    *out += &format!("    {balancing}\n\n");
+}
+
+/// Writes the period as a double-entry journal for hledger or beancount:
+/// opening balances of `accounts` at the start of the period, then its
+/// operations. `entries` is the whole ledger, as the opening balances are
+/// counted back from the current ones.
+pub async fn write_journal(
+    format: ExportFormat,
+    accounts: &[Accounts],
+    period: Period,
+    entries: impl Stream<Item = Result<LedgerEntry, FinanceError>>,
+) -> Result<Vec<u8>, FinanceError> {
+    let asset = |name: &str| journal_account(format, "Assets", name);
+    let (from, to) = period.timestamps();
+    let mut opening: HashMap<String, Money> = accounts
+        .iter()
+        .map(|acc| (acc.name.clone(), acc.balance))
+        .collect();
+    let mut used = BTreeSet::new();
+    let mut first_date = None;
+    let mut body = String::new();
+
+    futures::pin_mut!(entries);
+    while let Some(entry) = entries.try_next().await? {
+        if from.is_some_and(|from| entry.occurred_at < from) {
+            continue;
+        }
+        // Undoing every later operation leaves the balance at the start.
+        let mut undo = |account: &str, amount: Money| {
+            *opening.entry(account.to_string()).or_default() += amount;
+        };
+        match entry.kind {
+            EntryKind::Expense => undo(&entry.account, entry.amount),
+            EntryKind::Income => undo(&entry.account, -entry.amount),
+            EntryKind::Transfer => {
+                undo(&entry.account, entry.amount);
+                undo(&entry.category, -entry.amount);
+            }
+        }
+        if to.is_some_and(|to| entry.occurred_at >= to) {
+            continue;
+        }
+
+        let (target, source, description) = match entry.kind {
+            EntryKind::Expense => (
+                journal_account(format, "Expenses", &entry.category),
+                asset(&entry.account),
+                entry.category.clone(),
+            ),
+            EntryKind::Income => (
+                asset(&entry.account),
+                journal_account(format, "Income", &entry.category),
+                entry.category.clone(),
+            ),
+            EntryKind::Transfer => (
+                asset(&entry.category),
+                asset(&entry.account),
+                format!("{} -> {}", entry.account, entry.category),
+            ),
+        };
+        let description = if entry.note.is_empty() {
+            description
+        } else {
+            format!("{description} | {}", entry.note)
+        };
+        let date = entry.occurred_at.date_naive();
+        first_date.get_or_insert(date);
+        write_transaction(
+            &mut body,
+            format,
+            date,
+            &description,
+            &[(target.clone(), entry.amount, &entry.currency)],
+            &source,
+        );
+        used.insert(target);
+        used.insert(source);
+    }
+
+    let opening_date = from
+        .map(|from| from.date_naive())
+        .or(first_date)
+        .unwrap_or_else(|| Local::now().date_naive());
+    let mut names: Vec<String> = accounts.iter().map(|acc| asset(&acc.name)).collect();
+    names.extend(
+        used.into_iter()
+            .filter(|name| !names.contains(name))
+            .collect::<Vec<_>>(),
+    );
+    names.push(OPENING_BALANCES.to_string());
+
+    let mut out = format!("; Операции за период {period}\n\n");
+    for name in &names {
+        out += &match format {
+            ExportFormat::Beancount => format!("{opening_date} open {name}\n"),
+            _ => format!("account {name}\n"),
+        };
+    }
+    out += "\n";
+    let balances: Vec<(String, Money, &str)> = accounts
+        .iter()
+        .map(|acc| {
+            let balance = opening.get(&acc.name).copied().unwrap_or_default();
+            (asset(&acc.name), balance, acc.currency.as_str())
+        })
+        .filter(|(_, balance, _)| *balance != Money::ZERO)
+        .collect();
+    if !balances.is_empty() {
+        write_transaction(
+            &mut out,
+            format,
+            opening_date,
+            "Начальные остатки",
+            &balances,
+            OPENING_BALANCES,
+        );
+    }
+    out += &body;
+
+    Ok(out.into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn parses_format() {
+        assert_eq!("CSV".parse::<ExportFormat>().unwrap(), ExportFormat::Csv);
+        assert_eq!(
+            "beancount".parse::<ExportFormat>().unwrap(),
+            ExportFormat::Beancount
+        );
+        assert!("xls".parse::<ExportFormat>().is_err());
+    }
+
+    #[tokio::test]
+    async fn writes_csv_with_quoting() {
+        let entry = LedgerEntry {
+            occurred_at: Local.with_ymd_and_hms(2026, 9, 15, 12, 30, 0).unwrap(),
+            kind: EntryKind::Expense,
+            account: "card".to_string(),
+            category: "кафе, бар".to_string(),
+            amount: Money(19990),
+            currency: "RUB".to_string(),
+            note: String::new(),
+        };
+        let csv = write_csv(futures::stream::iter([Ok(entry)])).await.unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "date,type,account,category,amount,note\n\
+             2026-09-15 12:30,expense,card,\"кафе, бар\",199.90,\n"
+        );
+    }
+
+    fn journal_entries() -> Vec<Result<LedgerEntry, FinanceError>> {
+        let entry = |day, kind, account: &str, category: &str, amount| {
+            Ok(LedgerEntry {
+                occurred_at: Local.with_ymd_and_hms(2026, 9, day, 12, 0, 0).unwrap(),
+                kind,
+                account: account.to_string(),
+                category: category.to_string(),
+                amount: Money(amount),
+                currency: "RUB".to_string(),
+                note: String::new(),
+            })
+        };
+        vec![
+            entry(1, EntryKind::Income, "tinkoff", "зарплата", 5000000),
+            entry(15, EntryKind::Expense, "tinkoff", "кафе", 19990),
+            entry(20, EntryKind::Transfer, "tinkoff", "sber", 100000),
+        ]
+    }
+
+    fn journal_accounts() -> Vec<Accounts> {
+        let account = |name: &str, balance| Accounts {
+            id: None,
+            name: name.to_string(),
+            balance: Money(balance),
+            user_id: 1,
+            currency: "RUB".to_string(),
+        };
+        // Balances after all the entries.
+        vec![
+            account("tinkoff", 100000 + 5000000 - 19990 - 100000),
+            account("sber", 100000),
+        ]
+    }
+
+    #[tokio::test]
+    async fn writes_ledger_journal_with_opening_balances() {
+        let journal = write_journal(
+            ExportFormat::Ledger,
+            &journal_accounts(),
+            Period::All,
+            futures::stream::iter(journal_entries()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(journal).unwrap(),
+            "; Операции за период all\n\n\
+             account Assets:tinkoff\n\
+             account Assets:sber\n\
+             account Expenses:кафе\n\
+             account Income:зарплата\n\
+             account Equity:Opening-Balances\n\n\
+             2026-09-01 Начальные остатки\n    Assets:tinkoff  1000.00 RUB\n    Equity:Opening-Balances\n\n\
+             2026-09-01 зарплата\n    Assets:tinkoff  50000.00 RUB\n    Income:зарплата\n\n\
+             2026-09-15 кафе\n    Expenses:кафе  199.90 RUB\n    Assets:tinkoff\n\n\
+             2026-09-20 tinkoff -> sber\n    Assets:sber  1000.00 RUB\n    Assets:tinkoff\n\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn writes_beancount_from_the_period_start() {
+        let period = Period::Range {
+            from: NaiveDate::from_ymd_opt(2026, 9, 10).unwrap(),
+            to: NaiveDate::from_ymd_opt(2026, 9, 15).unwrap(),
+        };
+        let journal = write_journal(
+            ExportFormat::Beancount,
+            &journal_accounts(),
+            period,
+            futures::stream::iter(journal_entries()),
+        )
+        .await
+        .unwrap();
+        let journal = String::from_utf8(journal).unwrap();
+        assert!(
+            journal.contains(
+                "2026-09-10 open Assets:Tinkoff\n\
+                 2026-09-10 open Assets:Sber\n\
+                 2026-09-10 open Expenses:Кафе\n\
+                 2026-09-10 open Equity:Opening-Balances\n\n\
+                 2026-09-10 * \"Начальные остатки\"\n    Assets:Tinkoff  51000.00 RUB\n    Equity:Opening-Balances\n\n\
+                 2026-09-15 * \"кафе\"\n    Expenses:Кафе  199.90 RUB\n    Assets:Tinkoff\n\n"
+            ),
+            "{journal}"
+        );
+        // The transfer on the 20th is past the period.
+        assert!(!journal.contains("Sber  "), "{journal}");
+    }
+}
@@ -0,0 +1,1365 @@
+use super::*;
+use async_trait::async_trait;
+use chrono::{DateTime, Local};
+use futures::stream::{self, BoxStream};
+use futures::StreamExt;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// Store keeping everything in memory, for tests and trying the bot out
+/// without a database.
+#[derive(Default)]
+pub struct MemoryStore {
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    last_id: i64,
+    accounts: Vec<Accounts>,
+    categories: Vec<CategoryRow>,
+    expenses: Vec<Entry>,
+    income: Vec<Entry>,
+    transfers: Vec<TransferRow>,
+    rates: BTreeMap<(i64, String, String), f64>,
+    base_currencies: HashMap<i64, String>,
+    import_mappings: HashMap<i64, ColumnMapping>,
+    payee_rules: Vec<PayeeRuleRow>,
+    recurring: Vec<RecurringRow>,
+    default_accounts: HashMap<i64, i64>,
+    dialogues: HashMap<i64, String>,
+    aliases: Vec<AliasRow>,
+    callback_payloads: Vec<CallbackPayloadRow>,
+}
+
+struct CallbackPayloadRow {
+    id: i64,
+    user_id: i64,
+    payload: String,
+}
+
+struct PayeeRuleRow {
+    user_id: i64,
+    pattern: String,
+    category_id: i64,
+}
+
+struct AliasRow {
+    user_id: i64,
+    alias: String,
+    category_id: Option<i64>,
+    account_id: Option<i64>,
+}
+
+struct RecurringRow {
+    id: i64,
+    user_id: i64,
+    income: bool,
+    amount: Money,
+    category_id: i64,
+    account_id: i64,
+    schedule: Schedule,
+    next_run: NaiveDate,
+}
+
+struct CategoryRow {
+    category: Categories,
+    budget: Option<(Money, BudgetPeriod)>,
+}
+
+/// Expense or income.
+struct Entry {
+    id: i64,
+    user_id: i64,
+    account_id: i64,
+    category_id: i64,
+    amount: Money,
+    occurred_at: DateTime<Local>,
+    import_ref: Option<String>,
+    fingerprint: String,
+    created_at: DateTime<Local>,
+    note: Note,
+}
+
+struct TransferRow {
+    id: i64,
+    user_id: i64,
+    from_account_id: i64,
+    to_account_id: i64,
+    amount: Money,
+    occurred_at: DateTime<Local>,
+}
+
+impl MemoryStore {
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Index of the row with `id`, telling a missing row from another user's one.
+fn position<T>(
+    rows: &[T],
+    user_id: i64,
+    id: i64,
+    key: impl Fn(&T) -> (i64, i64),
+) -> Result<usize, FinanceError> {
+    let index = rows
+        .iter()
+        .position(|row| key(row).0 == id)
+        .ok_or(FinanceError::NotFound)?;
+    if key(&rows[index]).1 != user_id {
+        return Err(FinanceError::NotOwner);
+    }
+    Ok(index)
+}
+
+fn in_period(occurred_at: DateTime<Local>, period: Period) -> bool {
+    let (from, to) = period.timestamps();
+    from.is_none_or(|from| occurred_at >= from) && to.is_none_or(|to| occurred_at < to)
+}
+
+fn paginate<T>(items: Vec<T>, page: Option<Page>) -> Paged<T> {
+    let total = items.len() as i64;
+    let items: Vec<T> = match page {
+        Some(page) => items
+            .into_iter()
+            .skip(page.offset() as usize)
+            .take(page.size as usize)
+            .collect(),
+        None => items,
+    };
+    // Past the last page there are no rows to count, as with `COUNT(*) OVER ()`.
+    let total = if items.is_empty() { 0 } else { total };
+    Paged { items, total }
+}
+
+impl State {
+    fn next_id(&mut self) -> i64 {
+        self.last_id += 1;
+        self.last_id
+    }
+
+    /// Aliases of the user pointing at the rows `target` picks.
+    fn alias_rows(
+        &self,
+        user_id: i64,
+        target: fn(&AliasRow) -> Option<i64>,
+    ) -> impl Iterator<Item = NamedRow> + '_ {
+        self.aliases
+            .iter()
+            .filter(move |row| row.user_id == user_id)
+            .filter_map(move |row| {
+                Some(NamedRow {
+                    id: target(row)?,
+                    name: row.alias.clone(),
+                    alias: true,
+                })
+            })
+    }
+
+    fn account_id(&self, user_id: i64, name: &str) -> Result<i64, FinanceError> {
+        let rows: Vec<NamedRow> = self
+            .accounts
+            .iter()
+            .filter(|acc| acc.user_id == user_id)
+            .filter_map(|acc| {
+                Some(NamedRow {
+                    id: acc.id?,
+                    name: acc.name.clone(),
+                    alias: false,
+                })
+            })
+            .chain(self.alias_rows(user_id, |row| row.account_id))
+            .collect();
+        resolve_name(&rows, name).ok_or_else(|| FinanceError::UnknownAccount(name.to_string()))
+    }
+
+    fn category_id(&self, user_id: i64, name: &str) -> Result<i64, FinanceError> {
+        let rows: Vec<NamedRow> = self
+            .categories
+            .iter()
+            .filter(|row| row.category.user_id == user_id)
+            .filter_map(|row| {
+                Some(NamedRow {
+                    id: row.category.id?,
+                    name: row.category.name.clone(),
+                    alias: false,
+                })
+            })
+            .chain(self.alias_rows(user_id, |row| row.category_id))
+            .collect();
+        resolve_name(&rows, name).ok_or_else(|| FinanceError::UnknownCategory(name.to_string()))
+    }
+
+    fn account_name(&self, id: i64) -> String {
+        self.accounts
+            .iter()
+            .find(|acc| acc.id == Some(id))
+            .map(|acc| acc.name.clone())
+            .unwrap_or_default()
+    }
+
+    fn account_currency(&self, id: i64) -> String {
+        self.accounts
+            .iter()
+            .find(|acc| acc.id == Some(id))
+            .map(|acc| acc.currency.clone())
+            .unwrap_or_default()
+    }
+
+    fn category_name(&self, id: i64) -> String {
+        self.categories
+            .iter()
+            .find(|row| row.category.id == Some(id))
+            .map(|row| row.category.name.clone())
+            .unwrap_or_default()
+    }
+
+    fn recurring_of(&self, row: &RecurringRow) -> Recurring {
+        Recurring {
+            id: row.id,
+            user_id: row.user_id,
+            income: row.income,
+            amount: row.amount,
+            category: self.category_name(row.category_id),
+            account: self.account_name(row.account_id),
+            schedule: row.schedule,
+            next_run: row.next_run,
+        }
+    }
+
+    fn change_balance(&mut self, account_id: i64, amount: Money) {
+        if let Some(acc) = self
+            .accounts
+            .iter_mut()
+            .find(|acc| acc.id == Some(account_id))
+        {
+            acc.balance += amount;
+        }
+    }
+
+    /// Entries of the user in `period`, oldest first.
+    fn entries(entries: &[Entry], user_id: i64, period: Period) -> impl Iterator<Item = &Entry> {
+        let mut entries: Vec<&Entry> = entries
+            .iter()
+            .filter(|e| e.user_id == user_id && in_period(e.occurred_at, period))
+            .collect();
+        entries.sort_by_key(|e| (e.occurred_at, e.id));
+        entries.into_iter()
+    }
+
+    /// Returns the id of the new entry, `None` for an imported row whose
+    /// fingerprint was booked before.
+    fn add_entry(
+        &mut self,
+        income: bool,
+        user_id: i64,
+        entry: NewEntry,
+    ) -> Result<Option<i64>, FinanceError> {
+        validate_amount(entry.amount)?;
+        let category_id = self.category_id(user_id, &entry.category)?;
+        let account_id = self.account_id(user_id, &entry.account)?;
+
+        let fingerprint = fingerprint(account_id, &entry);
+        let entries = if income { &self.income } else { &self.expenses };
+        if entry.import_ref.is_some()
+            && entries.iter().any(|e| {
+                e.user_id == user_id && e.import_ref.is_some() && e.fingerprint == fingerprint
+            })
+        {
+            return Ok(None);
+        }
+
+        let id = self.next_id();
+        let amount = entry.amount;
+        let entry = Entry {
+            id,
+            user_id,
+            account_id,
+            category_id,
+            amount,
+            occurred_at: entry.date.map(start_of_day).unwrap_or_else(Local::now),
+            import_ref: entry.import_ref,
+            fingerprint,
+            created_at: Local::now(),
+            note: entry.note,
+        };
+        if income {
+            self.change_balance(account_id, amount);
+            self.income.push(entry);
+        } else {
+            self.change_balance(account_id, -amount);
+            self.expenses.push(entry);
+        }
+
+        Ok(Some(id))
+    }
+
+    fn del_entry(&mut self, income: bool, user_id: i64, id: i64) -> Result<(), FinanceError> {
+        let entries = if income {
+            &mut self.income
+        } else {
+            &mut self.expenses
+        };
+        let index = position(entries, user_id, id, |e| (e.id, e.user_id))?;
+        let entry = entries.remove(index);
+        let amount = if income { -entry.amount } else { entry.amount };
+        self.change_balance(entry.account_id, amount);
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl FinanceStore for MemoryStore {
+    async fn add_account(&self, mut account: Accounts) -> Result<(), FinanceError> {
+        let mut state = self.state();
+        account.id = Some(state.next_id());
+        state.accounts.push(account);
+        Ok(())
+    }
+
+    async fn get_accounts_page(
+        &self,
+        user_id: i64,
+        page: Option<Page>,
+    ) -> Result<Paged<Accounts>, FinanceError> {
+        let state = self.state();
+        let accounts = state
+            .accounts
+            .iter()
+            .filter(|acc| acc.user_id == user_id)
+            .cloned()
+            .collect();
+        Ok(paginate(accounts, page))
+    }
+
+    async fn edit_account(
+        &self,
+        user_id: i64,
+        id: i64,
+        name: String,
+        balance: Money,
+    ) -> Result<(), FinanceError> {
+        let mut state = self.state();
+        let index = position(&state.accounts, user_id, id, |acc| {
+            (acc.id.unwrap_or_default(), acc.user_id)
+        })?;
+        let acc = &mut state.accounts[index];
+        acc.name = name;
+        acc.balance = balance;
+        Ok(())
+    }
+
+    async fn del_account(&self, user_id: i64, id: i64) -> Result<(), FinanceError> {
+        let mut state = self.state();
+        let index = position(&state.accounts, user_id, id, |acc| {
+            (acc.id.unwrap_or_default(), acc.user_id)
+        })?;
+        let in_use = state
+            .expenses
+            .iter()
+            .chain(&state.income)
+            .any(|e| e.account_id == id)
+            || state
+                .transfers
+                .iter()
+                .any(|tr| tr.from_account_id == id || tr.to_account_id == id);
+        if in_use {
+            return Err(FinanceError::Validation(ACCOUNT_IN_USE.to_string()));
+        }
+        state.accounts.remove(index);
+        state.recurring.retain(|row| row.account_id != id);
+        state.aliases.retain(|row| row.account_id != Some(id));
+        state
+            .default_accounts
+            .retain(|_, account_id| *account_id != id);
+        Ok(())
+    }
+
+    async fn add_category(&self, mut category: Categories) -> Result<(), FinanceError> {
+        let mut state = self.state();
+        category.id = Some(state.next_id());
+        state.categories.push(CategoryRow {
+            category,
+            budget: None,
+        });
+        Ok(())
+    }
+
+    async fn get_categories_page(
+        &self,
+        user_id: i64,
+        page: Option<Page>,
+    ) -> Result<Paged<Categories>, FinanceError> {
+        let state = self.state();
+        let categories = state
+            .categories
+            .iter()
+            .filter(|row| row.category.user_id == user_id)
+            .map(|row| row.category.clone())
+            .collect();
+        Ok(paginate(categories, page))
+    }
+
+    async fn edit_category(
+        &self,
+        user_id: i64,
+        id: i64,
+        name: String,
+        description: String,
+    ) -> Result<(), FinanceError> {
+        let mut state = self.state();
+        let index = position(&state.categories, user_id, id, |row| {
+            (row.category.id.unwrap_or_default(), row.category.user_id)
+        })?;
+        let category = &mut state.categories[index].category;
+        category.name = name;
+        category.description = description;
+        Ok(())
+    }
+
+    async fn del_category(&self, user_id: i64, id: i64) -> Result<(), FinanceError> {
+        let mut state = self.state();
+        let index = position(&state.categories, user_id, id, |row| {
+            (row.category.id.unwrap_or_default(), row.category.user_id)
+        })?;
+        let in_use = state
+            .expenses
+            .iter()
+            .chain(&state.income)
+            .any(|e| e.category_id == id);
+        if in_use {
+            return Err(FinanceError::Validation(CATEGORY_IN_USE.to_string()));
+        }
+        state.categories.remove(index);
+        state.payee_rules.retain(|rule| rule.category_id != id);
+        state.recurring.retain(|row| row.category_id != id);
+        state.aliases.retain(|row| row.category_id != Some(id));
+        Ok(())
+    }
+
+    async fn add_entry(
+        &self,
+        user_id: i64,
+        income: bool,
+        entry: NewEntry,
+    ) -> Result<i64, FinanceError> {
+        let id = self.state().add_entry(income, user_id, entry)?;
+        Ok(id.expect("only imported rows are skipped"))
+    }
+
+    async fn get_expense_page(
+        &self,
+        user_id: i64,
+        period: Period,
+        tag: Option<String>,
+        page: Option<Page>,
+    ) -> Result<Paged<Expenses>, FinanceError> {
+        let state = self.state();
+        let expenses = State::entries(&state.expenses, user_id, period)
+            .filter(|e| tag.as_ref().is_none_or(|tag| e.note.tags.contains(tag)))
+            .map(|e| Expenses {
+                id: e.id,
+                account: state.account_name(e.account_id),
+                category: state.category_name(e.category_id),
+                amount: e.amount,
+                user_id: e.user_id,
+                occurred_at: e.occurred_at,
+                note: e.note.clone(),
+            })
+            .collect();
+        Ok(paginate(expenses, page))
+    }
+
+    async fn del_expense(&self, user_id: i64, id: i64) -> Result<(), FinanceError> {
+        self.state().del_entry(false, user_id, id)
+    }
+
+    async fn get_income_page(
+        &self,
+        user_id: i64,
+        period: Period,
+        tag: Option<String>,
+        page: Option<Page>,
+    ) -> Result<Paged<Income>, FinanceError> {
+        let state = self.state();
+        let income = State::entries(&state.income, user_id, period)
+            .filter(|e| tag.as_ref().is_none_or(|tag| e.note.tags.contains(tag)))
+            .map(|e| Income {
+                id: e.id,
+                account: state.account_name(e.account_id),
+                category: state.category_name(e.category_id),
+                amount: e.amount,
+                user_id: e.user_id,
+                occurred_at: e.occurred_at,
+                note: e.note.clone(),
+            })
+            .collect();
+        Ok(paginate(income, page))
+    }
+
+    async fn del_income(&self, user_id: i64, id: i64) -> Result<(), FinanceError> {
+        self.state().del_entry(true, user_id, id)
+    }
+
+    async fn import_rows(
+        &self,
+        user_id: i64,
+        account: String,
+        rows: Vec<StatementRow>,
+    ) -> Result<usize, FinanceError> {
+        let mut state = self.state();
+        // Check every row first so a bad one leaves nothing booked, as the
+        // transaction of the SQL stores does.
+        state.account_id(user_id, &account)?;
+        for row in &rows {
+            validate_amount(row.amount)?;
+            state.category_id(user_id, &row.category)?;
+        }
+
+        let mut added = 0;
+        for row in rows {
+            let entry = NewEntry {
+                amount: row.amount,
+                category: row.category,
+                account: account.clone(),
+                date: Some(row.date),
+                import_ref: Some(row.import_ref),
+                note: Note::default(),
+            };
+            if state
+                .add_entry(row.kind == EntryKind::Income, user_id, entry)?
+                .is_some()
+            {
+                added += 1;
+            }
+        }
+
+        Ok(added)
+    }
+
+    async fn get_import_fingerprints(&self, user_id: i64) -> Result<HashSet<String>, FinanceError> {
+        let state = self.state();
+        let fingerprints = state
+            .expenses
+            .iter()
+            .chain(&state.income)
+            .filter(|e| e.user_id == user_id && e.import_ref.is_some())
+            .map(|e| e.fingerprint.clone())
+            .collect();
+        Ok(fingerprints)
+    }
+
+    async fn has_recent_duplicate(
+        &self,
+        user_id: i64,
+        income: bool,
+        entry: &NewEntry,
+        since: DateTime<Local>,
+    ) -> Result<bool, FinanceError> {
+        let state = self.state();
+        let Ok(account_id) = state.account_id(user_id, &entry.account) else {
+            return Ok(false);
+        };
+        let fingerprint = fingerprint(account_id, entry);
+        let entries = if income {
+            &state.income
+        } else {
+            &state.expenses
+        };
+        Ok(entries
+            .iter()
+            .any(|e| e.user_id == user_id && e.fingerprint == fingerprint && e.created_at >= since))
+    }
+
+    async fn get_import_mapping(
+        &self,
+        user_id: i64,
+    ) -> Result<Option<ColumnMapping>, FinanceError> {
+        Ok(self.state().import_mappings.get(&user_id).copied())
+    }
+
+    async fn set_import_mapping(
+        &self,
+        user_id: i64,
+        mapping: ColumnMapping,
+    ) -> Result<(), FinanceError> {
+        self.state().import_mappings.insert(user_id, mapping);
+        Ok(())
+    }
+
+    async fn get_aliases(&self, user_id: i64) -> Result<Vec<Alias>, FinanceError> {
+        let state = self.state();
+        let mut aliases: Vec<Alias> = state
+            .aliases
+            .iter()
+            .filter(|row| row.user_id == user_id)
+            .map(|row| {
+                let target = match (row.category_id, row.account_id) {
+                    (Some(id), _) => AliasTarget::Category(state.category_name(id)),
+                    (None, id) => AliasTarget::Account(state.account_name(id.unwrap_or_default())),
+                };
+                Alias {
+                    alias: row.alias.clone(),
+                    target,
+                }
+            })
+            .collect();
+        aliases.sort_by(|a, b| a.alias.cmp(&b.alias));
+        Ok(aliases)
+    }
+
+    async fn set_alias(
+        &self,
+        user_id: i64,
+        alias: String,
+        name: String,
+    ) -> Result<AliasTarget, FinanceError> {
+        let mut state = self.state();
+        let (category_id, account_id, target) = match state.category_id(user_id, &name) {
+            Ok(id) => (
+                Some(id),
+                None,
+                AliasTarget::Category(state.category_name(id)),
+            ),
+            Err(_) => {
+                let id = state
+                    .account_id(user_id, &name)
+                    .map_err(|_| FinanceError::UnknownCategory(name))?;
+                (None, Some(id), AliasTarget::Account(state.account_name(id)))
+            }
+        };
+
+        let alias = alias.trim().to_lowercase();
+        state
+            .aliases
+            .retain(|row| row.user_id != user_id || row.alias != alias);
+        state.aliases.push(AliasRow {
+            user_id,
+            alias,
+            category_id,
+            account_id,
+        });
+        Ok(target)
+    }
+
+    async fn del_alias(&self, user_id: i64, alias: String) -> Result<(), FinanceError> {
+        let mut state = self.state();
+        let alias = alias.trim().to_lowercase();
+        let count = state.aliases.len();
+        state
+            .aliases
+            .retain(|row| row.user_id != user_id || row.alias != alias);
+        if state.aliases.len() == count {
+            return Err(FinanceError::NotFound);
+        }
+        Ok(())
+    }
+
+    async fn get_dialogue(&self, user_id: i64) -> Result<Option<String>, FinanceError> {
+        Ok(self.state().dialogues.get(&user_id).cloned())
+    }
+
+    async fn set_dialogue(&self, user_id: i64, state: String) -> Result<(), FinanceError> {
+        self.state().dialogues.insert(user_id, state);
+        Ok(())
+    }
+
+    async fn del_dialogue(&self, user_id: i64) -> Result<(), FinanceError> {
+        self.state().dialogues.remove(&user_id);
+        Ok(())
+    }
+
+    async fn save_callback_payload(
+        &self,
+        user_id: i64,
+        payload: String,
+    ) -> Result<i64, FinanceError> {
+        let mut state = self.state();
+        if let Some(row) = state
+            .callback_payloads
+            .iter()
+            .find(|row| row.user_id == user_id && row.payload == payload)
+        {
+            return Ok(row.id);
+        }
+        let id = state.next_id();
+        state.callback_payloads.push(CallbackPayloadRow {
+            id,
+            user_id,
+            payload,
+        });
+        Ok(id)
+    }
+
+    async fn get_callback_payload(
+        &self,
+        user_id: i64,
+        id: i64,
+    ) -> Result<Option<String>, FinanceError> {
+        Ok(self
+            .state()
+            .callback_payloads
+            .iter()
+            .find(|row| row.id == id && row.user_id == user_id)
+            .map(|row| row.payload.clone()))
+    }
+
+    async fn take_callback_payload(
+        &self,
+        user_id: i64,
+        id: i64,
+    ) -> Result<Option<String>, FinanceError> {
+        let mut state = self.state();
+        let Some(index) = state
+            .callback_payloads
+            .iter()
+            .position(|row| row.id == id && row.user_id == user_id)
+        else {
+            return Ok(None);
+        };
+        Ok(Some(state.callback_payloads.remove(index).payload))
+    }
+
+    async fn get_payee_rules(&self, user_id: i64) -> Result<Vec<PayeeRule>, FinanceError> {
+        let state = self.state();
+        let mut rules: Vec<PayeeRule> = state
+            .payee_rules
+            .iter()
+            .filter(|rule| rule.user_id == user_id)
+            .map(|rule| PayeeRule {
+                pattern: rule.pattern.clone(),
+                category: state.category_name(rule.category_id),
+            })
+            .collect();
+        rules.sort_by(|a, b| a.pattern.cmp(&b.pattern));
+        Ok(rules)
+    }
+
+    async fn set_payee_rule(
+        &self,
+        user_id: i64,
+        pattern: String,
+        category: String,
+    ) -> Result<(), FinanceError> {
+        let mut state = self.state();
+        let category_id = state.category_id(user_id, &category)?;
+        let pattern = pattern.to_lowercase();
+        match state
+            .payee_rules
+            .iter_mut()
+            .find(|rule| rule.user_id == user_id && rule.pattern == pattern)
+        {
+            Some(rule) => rule.category_id = category_id,
+            None => state.payee_rules.push(PayeeRuleRow {
+                user_id,
+                pattern,
+                category_id,
+            }),
+        }
+        Ok(())
+    }
+
+    async fn del_payee_rule(&self, user_id: i64, pattern: String) -> Result<(), FinanceError> {
+        let mut state = self.state();
+        let pattern = pattern.to_lowercase();
+        let count = state.payee_rules.len();
+        state
+            .payee_rules
+            .retain(|rule| rule.user_id != user_id || rule.pattern != pattern);
+        if state.payee_rules.len() == count {
+            return Err(FinanceError::NotFound);
+        }
+        Ok(())
+    }
+
+    async fn add_recurring(
+        &self,
+        user_id: i64,
+        recurring: NewRecurring,
+    ) -> Result<(), FinanceError> {
+        validate_amount(recurring.amount)?;
+        let mut state = self.state();
+        let category_id = state.category_id(user_id, &recurring.category)?;
+        let account_id = state.account_id(user_id, &recurring.account)?;
+        let id = state.next_id();
+        state.recurring.push(RecurringRow {
+            id,
+            user_id,
+            income: recurring.income,
+            amount: recurring.amount,
+            category_id,
+            account_id,
+            schedule: recurring.schedule,
+            next_run: recurring.next_run,
+        });
+        Ok(())
+    }
+
+    async fn get_recurring(&self, user_id: i64) -> Result<Vec<Recurring>, FinanceError> {
+        let state = self.state();
+        Ok(state
+            .recurring
+            .iter()
+            .filter(|row| row.user_id == user_id)
+            .map(|row| state.recurring_of(row))
+            .collect())
+    }
+
+    async fn del_recurring(&self, user_id: i64, id: i64) -> Result<(), FinanceError> {
+        let mut state = self.state();
+        let index = position(&state.recurring, user_id, id, |row| (row.id, row.user_id))?;
+        state.recurring.remove(index);
+        Ok(())
+    }
+
+    async fn due_recurring(&self, today: NaiveDate) -> Result<Vec<Recurring>, FinanceError> {
+        let state = self.state();
+        let mut due: Vec<Recurring> = state
+            .recurring
+            .iter()
+            .filter(|row| row.next_run <= today)
+            .map(|row| state.recurring_of(row))
+            .collect();
+        due.sort_by_key(|recurring| (recurring.next_run, recurring.id));
+        Ok(due)
+    }
+
+    async fn book_recurring_run(
+        &self,
+        user_id: i64,
+        id: i64,
+        run: NaiveDate,
+    ) -> Result<bool, FinanceError> {
+        let mut state = self.state();
+        let Some(index) = state
+            .recurring
+            .iter()
+            .position(|row| row.id == id && row.user_id == user_id && row.next_run == run)
+        else {
+            return Ok(false);
+        };
+        let recurring = state.recurring_of(&state.recurring[index]);
+        state.add_entry(recurring.income, user_id, recurring.entry(run))?;
+        state.recurring[index].next_run = recurring.schedule.next_after(run);
+        Ok(true)
+    }
+
+    async fn set_recurring_next_run(
+        &self,
+        user_id: i64,
+        id: i64,
+        next_run: NaiveDate,
+    ) -> Result<(), FinanceError> {
+        let mut state = self.state();
+        let index = position(&state.recurring, user_id, id, |row| (row.id, row.user_id))?;
+        state.recurring[index].next_run = next_run;
+        Ok(())
+    }
+
+    async fn backup(&self, user_id: i64) -> Result<Backup, FinanceError> {
+        let state = self.state();
+        let entries = |rows: &[Entry]| -> Vec<BackupEntry> {
+            rows.iter()
+                .filter(|e| e.user_id == user_id)
+                .map(|e| BackupEntry {
+                    account_id: e.account_id,
+                    category_id: e.category_id,
+                    amount: e.amount,
+                    occurred_at: e.occurred_at,
+                    import_ref: e.import_ref.clone(),
+                    note: e.note.clone(),
+                })
+                .collect()
+        };
+
+        Ok(Backup {
+            version: BACKUP_VERSION,
+            created_at: Local::now(),
+            accounts: state
+                .accounts
+                .iter()
+                .filter(|acc| acc.user_id == user_id)
+                .map(|acc| BackupAccount {
+                    id: acc.id.unwrap_or_default(),
+                    name: acc.name.clone(),
+                    balance: acc.balance,
+                    currency: acc.currency.clone(),
+                })
+                .collect(),
+            categories: state
+                .categories
+                .iter()
+                .filter(|row| row.category.user_id == user_id)
+                .map(|row| BackupCategory {
+                    id: row.category.id.unwrap_or_default(),
+                    name: row.category.name.clone(),
+                    description: row.category.description.clone(),
+                    budget: row
+                        .budget
+                        .map(|(limit, period)| BackupBudget { limit, period }),
+                })
+                .collect(),
+            expenses: entries(&state.expenses),
+            income: entries(&state.income),
+            transfers: state
+                .transfers
+                .iter()
+                .filter(|t| t.user_id == user_id)
+                .map(|t| BackupTransfer {
+                    from_account_id: t.from_account_id,
+                    to_account_id: t.to_account_id,
+                    amount: t.amount,
+                    occurred_at: t.occurred_at,
+                })
+                .collect(),
+            recurring: state
+                .recurring
+                .iter()
+                .filter(|row| row.user_id == user_id)
+                .map(|row| BackupRecurring {
+                    income: row.income,
+                    account_id: row.account_id,
+                    category_id: row.category_id,
+                    amount: row.amount,
+                    schedule: row.schedule,
+                    next_run: row.next_run,
+                })
+                .collect(),
+            settings: BackupSettings {
+                base_currency: state
+                    .base_currencies
+                    .get(&user_id)
+                    .cloned()
+                    .unwrap_or_else(|| DEFAULT_CURRENCY.to_string()),
+                rates: state
+                    .rates
+                    .iter()
+                    .filter(|((user, _, _), _)| *user == user_id)
+                    .map(|((_, from, to), rate)| ExchangeRates {
+                        from: from.clone(),
+                        to: to.clone(),
+                        rate: *rate,
+                    })
+                    .collect(),
+                import_mapping: state.import_mappings.get(&user_id).copied(),
+                payee_rules: state
+                    .payee_rules
+                    .iter()
+                    .filter(|rule| rule.user_id == user_id)
+                    .map(|rule| BackupPayeeRule {
+                        pattern: rule.pattern.clone(),
+                        category_id: rule.category_id,
+                    })
+                    .collect(),
+                default_account_id: state.default_accounts.get(&user_id).copied(),
+                aliases: state
+                    .aliases
+                    .iter()
+                    .filter(|row| row.user_id == user_id)
+                    .map(|row| BackupAlias {
+                        alias: row.alias.clone(),
+                        category_id: row.category_id,
+                        account_id: row.account_id,
+                    })
+                    .collect(),
+            },
+        })
+    }
+
+    async fn restore(&self, user_id: i64, backup: Backup) -> Result<(), FinanceError> {
+        backup.validate()?;
+        // Holding the lock throughout makes the replacement atomic.
+        let mut state = self.state();
+        state.accounts.retain(|acc| acc.user_id != user_id);
+        state
+            .categories
+            .retain(|row| row.category.user_id != user_id);
+        state.expenses.retain(|e| e.user_id != user_id);
+        state.income.retain(|e| e.user_id != user_id);
+        state.transfers.retain(|t| t.user_id != user_id);
+        state.rates.retain(|(user, _, _), _| *user != user_id);
+        state.base_currencies.remove(&user_id);
+        state.import_mappings.remove(&user_id);
+        state.payee_rules.retain(|rule| rule.user_id != user_id);
+        state.recurring.retain(|row| row.user_id != user_id);
+        state.default_accounts.remove(&user_id);
+        state.aliases.retain(|row| row.user_id != user_id);
+
+        let mut account_ids = HashMap::new();
+        for acc in backup.accounts {
+            let id = state.next_id();
+            account_ids.insert(acc.id, id);
+            state.accounts.push(Accounts {
+                id: Some(id),
+                name: acc.name,
+                balance: acc.balance,
+                user_id,
+                currency: acc.currency,
+            });
+        }
+        let mut category_ids = HashMap::new();
+        for cat in backup.categories {
+            let id = state.next_id();
+            category_ids.insert(cat.id, id);
+            state.categories.push(CategoryRow {
+                category: Categories {
+                    id: Some(id),
+                    name: cat.name,
+                    user_id,
+                    description: cat.description,
+                },
+                budget: cat.budget.map(|budget| (budget.limit, budget.period)),
+            });
+        }
+
+        // `validate` made sure every referred id is in the maps.
+        for (income, entries) in [(false, backup.expenses), (true, backup.income)] {
+            for entry in entries {
+                let account_id = account_ids[&entry.account_id];
+                let row = Entry {
+                    id: state.next_id(),
+                    user_id,
+                    account_id,
+                    category_id: category_ids[&entry.category_id],
+                    amount: entry.amount,
+                    occurred_at: entry.occurred_at,
+                    fingerprint: fingerprint(account_id, &entry.new_entry()),
+                    import_ref: entry.import_ref,
+                    created_at: Local::now(),
+                    note: entry.note,
+                };
+                if income {
+                    state.income.push(row);
+                } else {
+                    state.expenses.push(row);
+                }
+            }
+        }
+        for transfer in backup.transfers {
+            let id = state.next_id();
+            state.transfers.push(TransferRow {
+                id,
+                user_id,
+                from_account_id: account_ids[&transfer.from_account_id],
+                to_account_id: account_ids[&transfer.to_account_id],
+                amount: transfer.amount,
+                occurred_at: transfer.occurred_at,
+            });
+        }
+
+        for recurring in backup.recurring {
+            let id = state.next_id();
+            state.recurring.push(RecurringRow {
+                id,
+                user_id,
+                income: recurring.income,
+                amount: recurring.amount,
+                category_id: category_ids[&recurring.category_id],
+                account_id: account_ids[&recurring.account_id],
+                schedule: recurring.schedule,
+                next_run: recurring.next_run,
+            });
+        }
+
+        let settings = backup.settings;
+        state
+            .base_currencies
+            .insert(user_id, settings.base_currency);
+        for rate in settings.rates {
+            state.rates.insert((user_id, rate.from, rate.to), rate.rate);
+        }
+        if let Some(mapping) = settings.import_mapping {
+            state.import_mappings.insert(user_id, mapping);
+        }
+        if let Some(id) = settings.default_account_id {
+            state.default_accounts.insert(user_id, account_ids[&id]);
+        }
+        for rule in settings.payee_rules {
+            state.payee_rules.push(PayeeRuleRow {
+                user_id,
+                pattern: rule.pattern.to_lowercase(),
+                category_id: category_ids[&rule.category_id],
+            });
+        }
+        for alias in settings.aliases {
+            state.aliases.push(AliasRow {
+                user_id,
+                alias: alias.alias.to_lowercase(),
+                category_id: alias.category_id.map(|id| category_ids[&id]),
+                account_id: alias.account_id.map(|id| account_ids[&id]),
+            });
+        }
+        Ok(())
+    }
+
+    async fn add_transfer(
+        &self,
+        user_id: i64,
+        amount: Money,
+        from: String,
+        to: String,
+    ) -> Result<(), FinanceError> {
+        validate_amount(amount)?;
+
+        let mut state = self.state();
+        let from_account_id = state.account_id(user_id, &from)?;
+        let to_account_id = state.account_id(user_id, &to)?;
+        if from_account_id == to_account_id {
+            return Err(FinanceError::Validation(
+                "нельзя перевести деньги на тот же аккаунт".to_string(),
+            ));
+        }
+        validate_transfer_currency(
+            &state.account_currency(from_account_id),
+            &state.account_currency(to_account_id),
+        )?;
+        state.change_balance(from_account_id, -amount);
+        state.change_balance(to_account_id, amount);
+
+        let id = state.next_id();
+        state.transfers.push(TransferRow {
+            id,
+            user_id,
+            from_account_id,
+            to_account_id,
+            amount,
+            occurred_at: Local::now(),
+        });
+
+        Ok(())
+    }
+
+    async fn get_transfers_page(
+        &self,
+        user_id: i64,
+        period: Period,
+        page: Option<Page>,
+    ) -> Result<Paged<Transfers>, FinanceError> {
+        let state = self.state();
+        let mut rows: Vec<&TransferRow> = state
+            .transfers
+            .iter()
+            .filter(|tr| tr.user_id == user_id && in_period(tr.occurred_at, period))
+            .collect();
+        rows.sort_by_key(|tr| (tr.occurred_at, tr.id));
+        let transfers = rows
+            .into_iter()
+            .map(|tr| Transfers {
+                id: tr.id,
+                from_account: state.account_name(tr.from_account_id),
+                to_account: state.account_name(tr.to_account_id),
+                amount: tr.amount,
+                user_id: tr.user_id,
+                occurred_at: tr.occurred_at,
+            })
+            .collect();
+        Ok(paginate(transfers, page))
+    }
+
+    async fn del_transfer(&self, user_id: i64, id: i64) -> Result<(), FinanceError> {
+        let mut state = self.state();
+        let index = position(&state.transfers, user_id, id, |tr| (tr.id, tr.user_id))?;
+        let transfer = state.transfers.remove(index);
+        state.change_balance(transfer.from_account_id, transfer.amount);
+        state.change_balance(transfer.to_account_id, -transfer.amount);
+        Ok(())
+    }
+
+    async fn get_rates(&self, user_id: i64) -> Result<Vec<ExchangeRates>, FinanceError> {
+        let state = self.state();
+        let rates = state
+            .rates
+            .iter()
+            .filter(|((user, _, _), _)| *user == user_id)
+            .map(|((_, from, to), rate)| ExchangeRates {
+                from: from.clone(),
+                to: to.clone(),
+                rate: *rate,
+            })
+            .collect();
+        Ok(rates)
+    }
+
+    async fn set_rates(&self, user_id: i64, rates: &[ExchangeRates]) -> Result<(), FinanceError> {
+        let mut state = self.state();
+        for rate in rates {
+            state
+                .rates
+                .insert((user_id, rate.from.clone(), rate.to.clone()), rate.rate);
+        }
+        Ok(())
+    }
+
+    async fn get_base_currency(&self, user_id: i64) -> Result<String, FinanceError> {
+        Ok(self
+            .state()
+            .base_currencies
+            .get(&user_id)
+            .cloned()
+            .unwrap_or_else(|| DEFAULT_CURRENCY.to_string()))
+    }
+
+    async fn set_base_currency(&self, user_id: i64, currency: String) -> Result<(), FinanceError> {
+        self.state().base_currencies.insert(user_id, currency);
+        Ok(())
+    }
+
+    async fn get_default_account(&self, user_id: i64) -> Result<Option<String>, FinanceError> {
+        let state = self.state();
+        Ok(state
+            .default_accounts
+            .get(&user_id)
+            .map(|id| state.account_name(*id)))
+    }
+
+    async fn set_default_account(&self, user_id: i64, account: String) -> Result<(), FinanceError> {
+        let mut state = self.state();
+        let account_id = state.account_id(user_id, &account)?;
+        state.default_accounts.insert(user_id, account_id);
+        Ok(())
+    }
+
+    async fn set_budget(
+        &self,
+        user_id: i64,
+        category: String,
+        limit: Money,
+        period: BudgetPeriod,
+    ) -> Result<(), FinanceError> {
+        validate_amount(limit)?;
+        let mut state = self.state();
+        let id = state.category_id(user_id, &category)?;
+        let row = state
+            .categories
+            .iter_mut()
+            .find(|row| row.category.id == Some(id))
+            .ok_or(FinanceError::UnknownCategory(category))?;
+        row.budget = Some((limit, period));
+        Ok(())
+    }
+
+    async fn get_budget_sums(
+        &self,
+        user_id: i64,
+        category: Option<String>,
+    ) -> Result<Vec<BudgetSums>, FinanceError> {
+        let state = self.state();
+        // A budget asked for by a name that matches no category is none.
+        let category_id = match category {
+            Some(name) => match state.category_id(user_id, &name) {
+                Ok(id) => Some(id),
+                Err(_) => return Ok(vec![]),
+            },
+            None => None,
+        };
+        let mut budgets: Vec<BudgetSums> = state
+            .categories
+            .iter()
+            .filter(|row| row.category.user_id == user_id)
+            .filter(|row| category_id.is_none() || row.category.id == category_id)
+            .filter_map(|row| {
+                let (limit, period) = row.budget?;
+                let mut spent: BTreeMap<String, Money> = BTreeMap::new();
+                for e in State::entries(&state.expenses, user_id, period.period())
+                    .filter(|e| Some(e.category_id) == row.category.id)
+                {
+                    *spent
+                        .entry(state.account_currency(e.account_id))
+                        .or_insert(Money::ZERO) += e.amount;
+                }
+                Some(BudgetSums {
+                    category: row.category.name.clone(),
+                    limit,
+                    period,
+                    spent: spent.into_iter().collect(),
+                })
+            })
+            .collect();
+        budgets.sort_by(|a, b| a.category.cmp(&b.category));
+        Ok(budgets)
+    }
+
+    async fn get_report_sums(
+        &self,
+        user_id: i64,
+        period: Period,
+    ) -> Result<ReportSums, FinanceError> {
+        let state = self.state();
+        let by_currency = |rows: &[Entry]| {
+            let mut sums: BTreeMap<String, Money> = BTreeMap::new();
+            for e in State::entries(rows, user_id, period) {
+                *sums
+                    .entry(state.account_currency(e.account_id))
+                    .or_insert(Money::ZERO) += e.amount;
+            }
+            sums.into_iter().collect()
+        };
+
+        let mut by_category: BTreeMap<(String, String), Money> = BTreeMap::new();
+        for e in State::entries(&state.expenses, user_id, period) {
+            let key = (
+                state.category_name(e.category_id),
+                state.account_currency(e.account_id),
+            );
+            *by_category.entry(key).or_insert(Money::ZERO) += e.amount;
+        }
+
+        Ok(ReportSums {
+            income: by_currency(&state.income),
+            expenses: by_currency(&state.expenses),
+            categories: by_category
+                .into_iter()
+                .map(|((category, currency), sum)| (category, currency, sum))
+                .collect(),
+        })
+    }
+
+    fn ledger(
+        &self,
+        user_id: i64,
+        period: Period,
+    ) -> BoxStream<'_, Result<LedgerEntry, FinanceError>> {
+        let state = self.state();
+        let entry = |kind, e: &Entry| LedgerEntry {
+            occurred_at: e.occurred_at,
+            kind,
+            account: state.account_name(e.account_id),
+            category: state.category_name(e.category_id),
+            amount: e.amount,
+            currency: state.account_currency(e.account_id),
+            note: e.note.to_string(),
+        };
+        let mut entries: Vec<(LedgerEntry, i64)> = State::entries(&state.expenses, user_id, period)
+            .map(|e| (entry(EntryKind::Expense, e), e.id))
+            .chain(
+                State::entries(&state.income, user_id, period)
+                    .map(|e| (entry(EntryKind::Income, e), e.id)),
+            )
+            .chain(
+                state
+                    .transfers
+                    .iter()
+                    .filter(|tr| tr.user_id == user_id && in_period(tr.occurred_at, period))
+                    .map(|tr| {
+                        let entry = LedgerEntry {
+                            occurred_at: tr.occurred_at,
+                            kind: EntryKind::Transfer,
+                            account: state.account_name(tr.from_account_id),
+                            category: state.account_name(tr.to_account_id),
+                            amount: tr.amount,
+                            currency: state.account_currency(tr.from_account_id),
+                            note: String::new(),
+                        };
+                        (entry, tr.id)
+                    }),
+            )
+            .collect();
+        // Same order as the SQL stores: time, then kind name, then id.
+        entries.sort_by(|(a, a_id), (b, b_id)| {
+            (a.occurred_at, a.kind.as_str(), a_id).cmp(&(b.occurred_at, b.kind.as_str(), b_id))
+        });
+        stream::iter(entries.into_iter().map(|(entry, _)| Ok(entry))).boxed()
+    }
+}
@@ -1,21 +1,49 @@
-use futures::TryStreamExt;
-use sqlx::{Postgres, Row};
-
-use sqlx::postgres::{PgPool, PgPoolOptions};
-use std::error::Error;
-use teloxide::{prelude::*, utils::command::BotCommands};
-
-pub async fn get_sqlx_database_client() -> Result<PgPool, Box<dyn Error>> {
-    let database_url = dotenv::var("POSTGRESQL_URL").expect("POSTGRESQL_URL must be set");
-    let pool = PgPoolOptions::new()
-        .max_connections(30)
-        .connect(&database_url)
-        .await?;
-
-    sqlx::migrate!("./migrations").run(&pool).await?;
-
-    Ok(pool)
-}
+pub mod args;
+pub mod backup;
+pub mod budget;
+pub mod chart;
+pub mod currency;
+pub mod duplicate;
+pub mod error;
+pub mod export;
+pub mod memory;
+pub mod money;
+pub mod names;
+pub mod note;
+pub mod period;
+pub mod postgres;
+pub mod quick;
+pub mod recurring;
+pub mod report;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod statement;
+pub mod store;
+
+pub use args::*;
+pub use backup::*;
+pub use budget::*;
+pub use chart::*;
+pub use currency::*;
+pub use duplicate::*;
+pub use error::*;
+pub use export::*;
+pub use memory::*;
+pub use money::*;
+pub use names::*;
+pub use note::*;
+pub use period::*;
+pub use postgres::*;
+pub use quick::*;
+pub use recurring::*;
+pub use report::*;
+#[cfg(feature = "sqlite")]
+pub use sqlite::*;
+pub use statement::*;
+pub use store::*;
+
+use chrono::{DateTime, Local, NaiveDate};
+use teloxide::utils::command::{BotCommands, ParseError};
 
 #[derive(BotCommands, Clone)]
 #[command(
@@ -31,17 +59,25 @@ pub enum Command {
     Total,
     #[command(description = "accounts amount of money")]
     Accounts,
-    #[command(description = "add account\nexample: /addaccount sber 150", parse_with = "split")]
-    AddAccount { name: String, balance: i64 },
-    #[command(description = "edit account", parse_with = "split")]
-    EditAccount { id: i64, name: String, balance: i64 },
+    #[command(description = "add account, currency defaults to the base one\nexample: /addaccount sber 150.50 RUB", parse_with = parse_account)]
+    AddAccount {
+        name: String,
+        balance: Money,
+        currency: Option<String>,
+    },
+    #[command(description = "edit account\nexample: /editaccount 3 \"сбер вклад\" 1000", parse_with = parse_edit_account)]
+    EditAccount {
+        id: i64,
+        name: String,
+        balance: Money,
+    },
     #[command(description = "delete account")]
     DelAccount(i64),
     #[command(description = "available categories")]
     Categories,
-    #[command(description = "add category\nexample: /addcategory продукта продукты_из_магазина", parse_with = "split")]
+    #[command(description = "add category, the description is the rest of the line\nexample: /addcategory \"продукты из магазина\" еда и бытовая химия", parse_with = parse_category)]
     AddCategory { name: String, description: String },
-    #[command(description = "edit category", parse_with = "split")]
+    #[command(description = "edit category\nexample: /editcategory 2 \"продукты из магазина\" еда и бытовая химия", parse_with = parse_edit_category)]
     EditCategory {
         id: i64,
         name: String,
@@ -49,383 +85,489 @@ pub enum Command {
     },
     #[command(description = "delete category")]
     DelCategory(i64),
-    #[command(description = "display expenses", parse_with = "split")]
-    Expenses,
-    #[command(description = "display income", parse_with = "split")]
-    Income,
-    #[command(description = "add expense\nexample: /addexpense 200 кафе tinkoff", parse_with = "split")]
+    #[command(description = "display expenses, only those with a tag if given\nexample: /expenses month, /expenses 2026-09, /expenses 2026-09-01..2026-09-15, /expenses #trip", parse_with = parse_listing)]
+    Expenses { period: Period, tag: Option<String> },
+    #[command(description = "display income, only those with a tag if given\nexample: /income week", parse_with = parse_listing)]
+    Income { period: Period, tag: Option<String> },
+    #[command(description = "add expense, asks for what is missing step by step, the rest of the line is a note with #tags\nexample: /addexpense 199,90 кафе tinkoff 2026-09-15 ужин с клиентом #work", parse_with = parse_transaction)]
     AddExpense {
-        amount: i64,
+        amount: Money,
         category: String,
         account: String,
+        date: Option<NaiveDate>,
+        note: Note,
     },
-    #[command(description = "add income\nexample: /addincome 500 зарплата tinkoff", parse_with = "split")]
+    #[command(description = "add income, the rest of the line is a note with #tags\nexample: /addincome 500 зарплата tinkoff аванс", parse_with = parse_transaction)]
     AddIncome {
-        amount: i64,
+        amount: Money,
         category: String,
         account: String,
+        date: Option<NaiveDate>,
+        note: Note,
     },
     #[command(description = "delete expense")]
     DelExp(i64),
     #[command(description = "delete income")]
     DelInc(i64),
+    #[command(description = "transfer money between accounts\nexample: /transfer 1000 tinkoff \"сбер вклад\"", parse_with = parse_transfer)]
+    Transfer {
+        amount: Money,
+        from: String,
+        to: String,
+    },
+    #[command(description = "display transfers\nexample: /transfers month")]
+    Transfers(Period),
+    #[command(description = "delete transfer")]
+    DelTransfer(i64),
+    #[command(description = "set exchange rate\nexample: /setrate USD RUB 92.5", parse_with = parse_set_rate)]
+    SetRate { from: String, to: String, rate: f64 },
+    #[command(description = "import exchange rates from CSV\nexample: /importrates USD,RUB,92.5 EUR,RUB,100.2")]
+    ImportRates(String),
+    #[command(description = "display exchange rates")]
+    Rates,
+    #[command(description = "set base currency for /total\nexample: /basecurrency RUB")]
+    BaseCurrency(String),
+    #[command(description = "account of plain messages like \"кафе 200\" that name none, shows it without arguments\nexample: /defaultaccount tinkoff")]
+    DefaultAccount(String),
+    #[command(description = "another name of a category or account, lists the aliases without arguments\nexample: /alias кофе кафе")]
+    Alias(String),
+    #[command(description = "delete alias\nexample: /delalias кофе")]
+    DelAlias(String),
+    #[command(description = "set category budget in the base currency, month by default\nexample: /setbudget кафе 5000 month", parse_with = parse_budget)]
+    SetBudget {
+        category: String,
+        limit: Money,
+        period: BudgetPeriod,
+    },
+    #[command(description = "display budgets for the current period")]
+    Budgets,
+    #[command(description = "income and expense summary, month by default\nexample: /report 2026-09")]
+    Report(String),
+    #[command(description = "export operations as a csv, ledger or beancount file, all by default\nexample: /export ledger 2026-09", parse_with = parse_export)]
+    Export { format: ExportFormat, period: Period },
+    #[command(description = "chart of expenses by category or of balance and expenses by day, month by default\nexample: /chart pie, /chart line 2026-09", parse_with = parse_chart)]
+    Chart { kind: ChartKind, period: Period },
+    #[command(description = "columns of CSV statements sent as a file: date, amount, category, description\nexample: /importmapping 1 5 10 12")]
+    ImportMapping(String),
+    #[command(description = "categorize imported operations of a payee, lists the rules without arguments\nexample: /payeerule yandex taxi такси")]
+    PayeeRule(String),
+    #[command(description = "delete payee rule\nexample: /delpayeerule yandex taxi")]
+    DelPayeeRule(String),
+    #[command(description = "add expense or income booked on schedule, monthly on a day or weekly on a weekday\nexample: /addrecurring expense 30000 аренда tinkoff monthly 5, /addrecurring income 50000 зарплата tinkoff weekly fri", parse_with = parse_recurring)]
+    AddRecurring {
+        income: bool,
+        amount: Money,
+        category: String,
+        account: String,
+        schedule: Schedule,
+    },
+    #[command(description = "display recurring operations")]
+    Recurring,
+    #[command(description = "delete recurring operation")]
+    DelRecurring(i64),
+    #[command(description = "download all your data as a file for /restore")]
+    Backup,
+    #[command(description = "replace all your data with a /backup file")]
+    Restore,
 }
-pub struct Categories {
-    pub id: Option<i64>,
-    pub name: String,
-    pub user_id: i64,
-    pub description: String,
-}
-impl Categories {
-    pub async fn add(&self, pool: PgPool) -> Result<(), Box<dyn Error>> {
-        let query = "INSERT INTO categories (name, user_id, description) VALUES ($1, $2, $3)";
-        sqlx::query(query)
-            .bind(&self.name)
-            .bind(&self.user_id)
-            .bind(&self.description)
-            .execute(&pool)
-            .await?;
-        Ok(())
+
+/// Parses `<format> [period]` for `/export`.
+pub fn parse_export(s: String) -> Result<(ExportFormat, Period), ParseError> {
+    let (format, period) = s.trim().split_once(' ').unwrap_or((s.trim(), ""));
+    if format.is_empty() {
+        return Err(ParseError::TooFewArguments {
+            expected: 1,
+            found: 0,
+            message: "Expected export format".to_string(),
+        });
     }
-}
 
-pub struct Accounts {
-    pub id: Option<i64>,
-    pub name: String,
-    pub balance: i64,
-    pub user_id: i64,
+    let format = format
+        .parse()
+        .map_err(|e| ParseError::IncorrectFormat(Box::new(e)))?;
+    let period = period
+        .parse()
+        .map_err(|e| ParseError::IncorrectFormat(Box::new(e)))?;
+
+    Ok((format, period))
 }
 
-impl Accounts {
-    pub async fn add(&self, pool: PgPool) -> Result<(), Box<dyn Error>> {
-        let query = "INSERT INTO accounts (name, balance, user_id) VALUES ($1, $2, $3)";
-        sqlx::query(query)
-            .bind(&self.name)
-            .bind(&self.balance)
-            .bind(&self.user_id)
-            .execute(&pool)
-            .await?;
-        Ok(())
+/// Parses `<expense|income> <amount> <category> <account> <schedule>` for
+/// `/addrecurring`.
+pub fn parse_recurring(s: String) -> Result<(bool, Money, String, String, Schedule), ParseError> {
+    let args = split_args(&s)?;
+    if args.len() < 6 {
+        return Err(ParseError::TooFewArguments {
+            expected: 6,
+            found: args.len(),
+            message: "Expected kind, amount, category, account and schedule".to_string(),
+        });
+    }
+    if args.len() > 6 {
+        return Err(ParseError::TooManyArguments {
+            expected: 6,
+            found: args.len(),
+            message: format!("Excess argument: {}", args[6]),
+        });
     }
-}
 
-pub struct Expenses {
-    pub id: i64,
-    pub account: String,
-    pub category: String,
-    pub amount: i64,
-    pub user_id: i64,
+    let income = match args[0].to_lowercase().as_str() {
+        "expense" | "расход" => false,
+        "income" | "доход" => true,
+        kind => {
+            return Err(ParseError::IncorrectFormat(
+                format!("неизвестный вид операции \"{kind}\", используйте expense или income")
+                    .into(),
+            ))
+        }
+    };
+    let amount = args[1]
+        .parse()
+        .map_err(|e| ParseError::IncorrectFormat(Box::new(e)))?;
+    let schedule = args[4..]
+        .join(" ")
+        .parse()
+        .map_err(|e| ParseError::IncorrectFormat(Box::new(e)))?;
+
+    Ok((
+        income,
+        amount,
+        args[2].to_string(),
+        args[3].to_string(),
+        schedule,
+    ))
 }
 
-pub struct Income {
-    pub id: i64,
-    pub account: String,
-    pub category: String,
-    pub amount: i64,
-    pub user_id: i64,
-}
-// pub struct Database {
-//     pub pool: PgPool
-// }
-
-// impl Database {
-//     fn get_total(&self) -> i128{
-//         32
-//     }
-// }
-
-pub async fn get_accounts(pool: PgPool, user_id: i64) -> Result<Vec<Accounts>, Box<dyn Error>> {
-    let q = "SELECT * FROM accounts WHERE user_id = $1 ";
-    let query = sqlx::query(q).bind(user_id);
-    let mut rows = query.fetch(&pool);
-
-    let mut accounts = vec![];
-
-    while let Some(row) = rows.try_next().await? {
-        accounts.push(Accounts {
-            id: row.get("id"),
-            name: row.get("name"),
-            balance: row.get("balance"),
-            user_id: row.get("user_id"),
-        })
+/// Parses `<pie|line> [period]` for `/chart`, the current month by default.
+pub fn parse_chart(s: String) -> Result<(ChartKind, Period), ParseError> {
+    let (kind, period) = s.trim().split_once(' ').unwrap_or((s.trim(), ""));
+    if kind.is_empty() {
+        return Err(ParseError::TooFewArguments {
+            expected: 1,
+            found: 0,
+            message: "Expected chart kind: pie or line".to_string(),
+        });
     }
 
-    Ok(accounts)
+    let kind = kind
+        .parse()
+        .map_err(|e| ParseError::IncorrectFormat(Box::new(e)))?;
+    let period = match period.trim() {
+        "" => Period::Month,
+        period => period
+            .parse()
+            .map_err(|e| ParseError::IncorrectFormat(Box::new(e)))?,
+    };
+
+    Ok((kind, period))
 }
 
-pub async fn get_categories(pool: PgPool, user_id: i64) -> Result<Vec<Categories>, Box<dyn Error>> {
-    let q = "SELECT * FROM categories WHERE user_id = $1 ";
-    let query = sqlx::query(q).bind(user_id);
-    let mut rows = query.fetch(&pool);
-
-    let mut categories = vec![];
-
-    while let Some(row) = rows.try_next().await? {
-        categories.push(Categories {
-            id: row.get("id"),
-            name: row.get("name"),
-            user_id: row.get("user_id"),
-            description: row.get("description"),
-        })
+/// Parses `<category> <amount> [month|week]` for `/setbudget`.
+pub fn parse_budget(s: String) -> Result<(String, Money, BudgetPeriod), ParseError> {
+    let args = split_args(&s)?;
+    if args.len() < 2 {
+        return Err(ParseError::TooFewArguments {
+            expected: 2,
+            found: args.len(),
+            message: "Expected category and amount".to_string(),
+        });
+    }
+    if args.len() > 3 {
+        return Err(ParseError::TooManyArguments {
+            expected: 3,
+            found: args.len(),
+            message: format!("Excess argument: {}", args[3]),
+        });
     }
 
-    Ok(categories)
+    let limit = args[1]
+        .parse()
+        .map_err(|e| ParseError::IncorrectFormat(Box::new(e)))?;
+    let period = match args.get(2) {
+        Some(p) => p
+            .parse()
+            .map_err(|e| ParseError::IncorrectFormat(Box::new(e)))?,
+        None => BudgetPeriod::default(),
+    };
+
+    Ok((args[0].to_string(), limit, period))
 }
 
-pub async fn del_account(pool: PgPool, id: i64) -> Result<(), Box<dyn Error>> {
-    let q = "DELETE FROM accounts WHERE id = $1 ";
-    sqlx::query(q).bind(id).execute(&pool).await?;
-
-    Ok(())
-}
+/// Parses `<name> <balance> [currency]` for `/addaccount`.
+pub fn parse_account(s: String) -> Result<(String, Money, Option<String>), ParseError> {
+    let args = split_args(&s)?;
+    if args.len() < 2 {
+        return Err(ParseError::TooFewArguments {
+            expected: 2,
+            found: args.len(),
+            message: "Expected name and balance".to_string(),
+        });
+    }
+    if args.len() > 3 {
+        return Err(ParseError::TooManyArguments {
+            expected: 3,
+            found: args.len(),
+            message: format!("Excess argument: {}", args[3]),
+        });
+    }
 
-pub async fn del_category(pool: PgPool, id: i64) -> Result<(), Box<dyn Error>> {
-    let q = "DELETE FROM categories WHERE id = $1 ";
-    sqlx::query(q).bind(id).execute(&pool).await?;
+    let balance = args[1]
+        .parse()
+        .map_err(|e| ParseError::IncorrectFormat(Box::new(e)))?;
+    let currency = args
+        .get(2)
+        .map(|c| parse_currency(c))
+        .transpose()
+        .map_err(|e| ParseError::IncorrectFormat(Box::new(e)))?;
 
-    Ok(())
+    Ok((args[0].to_string(), balance, currency))
 }
 
-pub async fn edit_category(
-    pool: PgPool,
-    id: i64,
-    name: String,
-    description: String,
-) -> Result<(), Box<dyn Error>> {
-    let q = "UPDATE categories SET name = $1, description = $2 WHERE id = $3 ";
-    sqlx::query(q)
-        .bind(name)
-        .bind(description)
-        .bind(id)
-        .execute(&pool).await?;
-
-    Ok(())
-}
+/// Parses `<id> <name> <balance>` for `/editaccount`.
+pub fn parse_edit_account(s: String) -> Result<(i64, String, Money), ParseError> {
+    let args = split_args(&s)?;
+    if args.len() != 3 {
+        return Err(ParseError::Custom(
+            "Expected id, name and balance, example: /editaccount 3 \"сбер вклад\" 1000".into(),
+        ));
+    }
 
-pub async fn edit_account(
-    pool: PgPool,
-    id: i64,
-    name: String,
-    balance: i64,
-) -> Result<(), Box<dyn Error>> {
-    let q = "UPDATE accounts SET name = $1, balance = $2 WHERE id = $3 ";
-    sqlx::query(q)
-        .bind(name)
-        .bind(balance)
-        .bind(id)
-        .execute(&pool)
-        .await?;
+    let id = args[0]
+        .parse()
+        .map_err(|e| ParseError::IncorrectFormat(Box::new(e)))?;
+    let balance = args[2]
+        .parse()
+        .map_err(|e| ParseError::IncorrectFormat(Box::new(e)))?;
 
-    Ok(())
+    Ok((id, args[1].clone(), balance))
 }
 
-pub async fn get_expense(pool: PgPool, user_id: i64) -> Result<Vec<Expenses>, Box<dyn Error>> {
-    let q = "SELECT expenses.id, accounts.name AS account_name, categories.name AS category_name, expenses.amount, expenses.user_id
-    FROM expenses
-    JOIN accounts ON expenses.account_id = accounts.id
-    JOIN categories ON expenses.category_id = categories.id
-    WHERE expenses.user_id = $1; ";
-    let query = sqlx::query(q).bind(user_id);
-    let mut rows = query.fetch(&pool);
-
-    let mut expenses = vec![];
-
-    while let Some(row) = rows.try_next().await? {
-
-        expenses.push(Expenses {
-            id: row.get("id"),
-            account: row.get("account_name"),
-            category: row.get("category_name"),
-            amount: row.get("amount"),
-            user_id: row.get("user_id")
-        });
-    }
-
-    Ok(expenses)
+/// Parses `<name> <description...>` for `/addcategory`.
+pub fn parse_category(s: String) -> Result<(String, String), ParseError> {
+    name_and_description(Args::new(&s))
 }
 
-pub async fn get_income(pool: PgPool, user_id: i64) -> Result<Vec<Income>, Box<dyn Error>> {
-    let q = "select income.id, accounts.name AS account_name, categories.name AS category_name, income.amount, income.user_id
-    FROM income
-    JOIN accounts ON income.account_id = accounts.id
-    JOIN categories ON income.category_id = categories.id
-    WHERE income.user_id = $1";
-    let query = sqlx::query(q).bind(user_id);
-    let mut rows = query.fetch(&pool);
-
-    let mut income = vec![];
-
-    while let Some(row) = rows.try_next().await? {
-
-        income.push(Income {
-            id: row.get("id"),
-            account: row.get("account_name"),
-            category: row.get("category_name"),
-            amount: row.get("amount"),
-            user_id: row.get("user_id")
-        });
+fn name_and_description(mut args: Args) -> Result<(String, String), ParseError> {
+    let name = args.next_arg()?;
+    let description = args.rest();
+    match name {
+        Some(name) if !description.is_empty() => Ok((name, description)),
+        name => Err(ParseError::TooFewArguments {
+            expected: 2,
+            found: usize::from(name.is_some()),
+            message: "Expected name and description".to_string(),
+        }),
     }
-
-    Ok(income)
 }
 
-pub async fn add_expense(
-    pool: PgPool,
-    user_id: i64,
-    amount: i64,
-    category: String,
-    account: String,
-) -> Result<(), Box<dyn Error>> {
-    let first_q = "SELECT id FROM categories WHERE user_id = $1 AND name = $2 ";
-    let firts_query: sqlx::query::Query<'_, Postgres, sqlx::postgres::PgArguments> =
-        sqlx::query(first_q).bind(user_id).bind(category);
-    let cat_id: i64 = firts_query.fetch_one(&pool).await?.get("id");
-
-    let second_q = "SELECT id FROM accounts WHERE user_id = $1 AND name = $2 ";
-    let second_query: sqlx::query::Query<'_, Postgres, sqlx::postgres::PgArguments> =
-        sqlx::query(second_q).bind(user_id).bind(account);
-
-    let acc_id: i64 = second_query.fetch_one(&pool).await?.get("id");
-
-    let balance_q = "SELECT balance FROM accounts WHERE id = $1 ";
-    let balance_query = sqlx::query(balance_q).bind(acc_id);
-
-    let mut balance: i64 = balance_query.fetch_one(&pool).await?.get("balance");
-
-    balance = balance - amount;
-
-    let set_balance_q = "UPDATE accounts SET balance = $1 WHERE id = $2 ";
-    sqlx::query(set_balance_q)
-        .bind(balance)
-        .bind(acc_id)
-        .execute(&pool)
-        .await?;
-
-    let query =
-        "INSERT INTO expenses (account_id, category_id, amount, user_id) VALUES ($1, $2, $3, $4)";
-    sqlx::query(query)
-        .bind(acc_id)
-        .bind(cat_id)
-        .bind(amount)
-        .bind(user_id)
-        .execute(&pool)
-        .await?;
-
-    Ok(())
+/// Parses `<id> <name> <description...>` for `/editcategory`.
+pub fn parse_edit_category(s: String) -> Result<(i64, String, String), ParseError> {
+    let mut args = Args::new(&s);
+    let id = args
+        .next_arg()?
+        .unwrap_or_default()
+        .parse()
+        .map_err(|e| ParseError::IncorrectFormat(Box::new(e)))?;
+    let (name, description) = name_and_description(args)?;
+
+    Ok((id, name, description))
 }
 
+/// Parses `<amount> <from> <to>` for `/transfer`.
+pub fn parse_transfer(s: String) -> Result<(Money, String, String), ParseError> {
+    let args = split_args(&s)?;
+    if args.len() != 3 {
+        return Err(ParseError::Custom(
+            "Expected amount and two accounts, example: /transfer 1000 tinkoff sber".into(),
+        ));
+    }
 
-pub async fn add_income(
-    pool: PgPool,
-    user_id: i64,
-    amount: i64,
-    category: String,
-    account: String,
-) -> Result<(), Box<dyn Error>> {
-    let first_q = "SELECT id FROM categories WHERE user_id = $1 AND name = $2 ";
-    let firts_query: sqlx::query::Query<'_, Postgres, sqlx::postgres::PgArguments> =
-        sqlx::query(first_q).bind(user_id).bind(category);
-    let cat_id: i64 = firts_query.fetch_one(&pool).await?.get("id");
-
-    let second_q = "SELECT id FROM accounts WHERE user_id = $1 AND name = $2 ";
-    let second_query: sqlx::query::Query<'_, Postgres, sqlx::postgres::PgArguments> =
-        sqlx::query(second_q).bind(user_id).bind(account);
-
-    let acc_id: i64 = second_query.fetch_one(&pool).await?.get("id");
-
-    let balance_q = "SELECT balance FROM accounts WHERE id = $1 ";
-    let balance_query = sqlx::query(balance_q).bind(acc_id);
-
-    let mut balance: i64 = balance_query.fetch_one(&pool).await?.get("balance");
-
-    balance = balance + amount;
-
-    let set_balance_q = "UPDATE accounts SET balance = $1 WHERE id = $2 ";
-    sqlx::query(set_balance_q)
-        .bind(balance)
-        .bind(acc_id)
-        .execute(&pool)
-        .await?;
-
-    let query =
-        "INSERT INTO income (account_id, category_id, amount, user_id) VALUES ($1, $2, $3, $4)";
-    sqlx::query(query)
-        .bind(acc_id)
-        .bind(cat_id)
-        .bind(amount)
-        .bind(user_id)
-        .execute(&pool)
-        .await?;
+    let amount = args[0]
+        .parse()
+        .map_err(|e| ParseError::IncorrectFormat(Box::new(e)))?;
 
-    Ok(())
+    Ok((amount, args[1].clone(), args[2].clone()))
 }
 
+/// Parses `<from> <to> <rate>` for `/setrate`.
+pub fn parse_set_rate(s: String) -> Result<(String, String, f64), ParseError> {
+    let args: Vec<&str> = s.split_whitespace().collect();
+    if args.len() != 3 {
+        return Err(ParseError::Custom(
+            "Expected two currencies and a rate, example: /setrate USD RUB 92.5".into(),
+        ));
+    }
 
-pub async fn del_income(pool: PgPool, id: i64,) -> Result<(), Box<dyn Error>> {
-
-    // let first_q = "SELECT id FROM categories WHERE user_id = $1 AND name = $2 ";
-    // let firts_query: sqlx::query::Query<'_, Postgres, sqlx::postgres::PgArguments> =
-    //     sqlx::query(first_q).bind(user_id).bind(category);
-    // let cat_id: i64 = firts_query.fetch_one(&pool).await?.get("id");
-
-    let acc_id_q = "SELECT account_id FROM income WHERE id = $1";
-    let acc_id_query = sqlx::query(acc_id_q).bind(id);
-    let acc_id: i64 = acc_id_query.fetch_one(&pool).await?.get("account_id");
+    let incorrect = |e: CurrencyParseError| ParseError::IncorrectFormat(Box::new(e));
+    Ok((
+        parse_currency(args[0]).map_err(incorrect)?,
+        parse_currency(args[1]).map_err(incorrect)?,
+        parse_rate(args[2]).map_err(incorrect)?,
+    ))
+}
 
-    let amount_q = "SELECT amount FROM income WHERE id = $1";
-    let amount_query = sqlx::query(amount_q).bind(id);
-    let amount: i64 = amount_query.fetch_one(&pool).await?.get("amount");
+/// Parses `<amount> <category> <account> [date] [note]` for `/addexpense`
+/// and `/addincome`, the note taking the rest of the line with its `#tags`.
+pub fn parse_transaction(
+    s: String,
+) -> Result<(Money, String, String, Option<NaiveDate>, Note), ParseError> {
+    let mut args = Args::new(&s);
+    let mut names = vec![];
+    while names.len() < 3 {
+        match args.next_arg()? {
+            Some(arg) => names.push(arg),
+            None => {
+                return Err(ParseError::TooFewArguments {
+                    expected: 3,
+                    found: names.len(),
+                    message: "Expected amount, category and account".to_string(),
+                })
+            }
+        }
+    }
 
-    let balance_q = "SELECT balance FROM accounts WHERE id = $1 ";
-    let balance_query = sqlx::query(balance_q).bind(acc_id);
+    let amount = names[0]
+        .parse()
+        .map_err(|e| ParseError::IncorrectFormat(Box::new(e)))?;
+    // Anything but a date after the account starts the note, though a
+    // mistyped date is an error rather than a note.
+    let mut after_date = args.clone();
+    let date = match after_date.next_arg().ok().flatten() {
+        Some(date) => {
+            parse_date_arg(&date).map_err(|e| ParseError::IncorrectFormat(Box::new(e)))?
+        }
+        None => None,
+    };
+    if date.is_some() {
+        args = after_date;
+    }
+    let note = Note::parse(&args.rest());
 
-    let mut balance: i64 = balance_query.fetch_one(&pool).await?.get("balance");
-    balance = balance - amount;
+    let account = names.pop().unwrap_or_default();
+    let category = names.pop().unwrap_or_default();
+    Ok((amount, category, account, date, note))
+}
 
-    let set_balance_q = "UPDATE accounts SET balance = $1 WHERE id = $2 ";
-    sqlx::query(set_balance_q)
-        .bind(balance)
-        .bind(acc_id)
-        .execute(&pool)
-        .await?;
+/// Parses `[period] [#tag]` for `/expenses` and `/income`.
+pub fn parse_listing(s: String) -> Result<(Period, Option<String>), ParseError> {
+    let (tags, period): (Vec<&str>, Vec<&str>) =
+        s.split_whitespace().partition(|word| word.starts_with('#'));
+    if tags.len() > 1 {
+        return Err(ParseError::TooManyArguments {
+            expected: 1,
+            found: tags.len(),
+            message: format!("Excess tag: {}", tags[1]),
+        });
+    }
 
-    let q = "DELETE FROM income WHERE id = $1";
-    sqlx::query(q).bind(id).execute(&pool).await?;
+    let tag = tags
+        .first()
+        .map(|tag| parse_tag(tag).ok_or_else(|| format!("неверный тег \"{tag}\"")))
+        .transpose()
+        .map_err(|e| ParseError::IncorrectFormat(e.into()))?;
+    let period = period
+        .join(" ")
+        .parse()
+        .map_err(|e| ParseError::IncorrectFormat(Box::new(e)))?;
+
+    Ok((period, tag))
+}
 
-    Ok(())
+#[derive(Clone)]
+pub struct Categories {
+    pub id: Option<i64>,
+    pub name: String,
+    pub user_id: i64,
+    pub description: String,
 }
 
+#[derive(Clone)]
+pub struct Accounts {
+    pub id: Option<i64>,
+    pub name: String,
+    pub balance: Money,
+    pub user_id: i64,
+    pub currency: String,
+}
 
-pub async fn del_expense(pool: PgPool, id: i64,) -> Result<(), Box<dyn Error>> {
+pub struct Expenses {
+    pub id: i64,
+    pub account: String,
+    pub category: String,
+    pub amount: Money,
+    pub user_id: i64,
+    pub occurred_at: DateTime<Local>,
+    pub note: Note,
+}
 
-    // let first_q = "SELECT id FROM categories WHERE user_id = $1 AND name = $2 ";
-    // let firts_query: sqlx::query::Query<'_, Postgres, sqlx::postgres::PgArguments> =
-    //     sqlx::query(first_q).bind(user_id).bind(category);
-    // let cat_id: i64 = firts_query.fetch_one(&pool).await?.get("id");
+pub struct Income {
+    pub id: i64,
+    pub account: String,
+    pub category: String,
+    pub amount: Money,
+    pub user_id: i64,
+    pub occurred_at: DateTime<Local>,
+    pub note: Note,
+}
 
-    let acc_id_q = "SELECT account_id FROM expenses WHERE id = $1";
-    let acc_id_query = sqlx::query(acc_id_q).bind(id);
-    let acc_id: i64 = acc_id_query.fetch_one(&pool).await?.get("account_id");
+/// Expense or income to book, typed in or read from a statement.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct NewEntry {
+    pub amount: Money,
+    pub category: String,
+    pub account: String,
+    pub date: Option<NaiveDate>,
+    /// `StatementRow::import_ref` of an imported row.
+    pub import_ref: Option<String>,
+    pub note: Note,
+}
 
-    let amount_q = "SELECT amount FROM expenses WHERE id = $1";
-    let amount_query = sqlx::query(amount_q).bind(id);
-    let amount: i64 = amount_query.fetch_one(&pool).await?.get("amount");
+pub struct Transfers {
+    pub id: i64,
+    pub from_account: String,
+    pub to_account: String,
+    pub amount: Money,
+    pub user_id: i64,
+    pub occurred_at: DateTime<Local>,
+}
+/// One page of a listing, `number` starts from 0.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Page {
+    pub number: i64,
+    pub size: i64,
+}
 
-    let balance_q = "SELECT balance FROM accounts WHERE id = $1 ";
-    let balance_query = sqlx::query(balance_q).bind(acc_id);
+impl Page {
+    pub fn offset(&self) -> i64 {
+        self.number * self.size
+    }
+}
 
-    let mut balance: i64 = balance_query.fetch_one(&pool).await?.get("balance");
-    balance = balance + amount;
+pub struct Paged<T> {
+    pub items: Vec<T>,
+    /// Number of rows on all pages.
+    pub total: i64,
+}
 
-    let set_balance_q = "UPDATE accounts SET balance = $1 WHERE id = $2 ";
-    sqlx::query(set_balance_q)
-        .bind(balance)
-        .bind(acc_id)
-        .execute(&pool)
-        .await?;
+const ACCOUNT_IN_USE: &str = "по аккаунту есть операции, сначала удалите их";
+const CATEGORY_IN_USE: &str = "по категории есть операции, сначала удалите их";
 
-    let q = "DELETE FROM expenses WHERE id = $1";
-    sqlx::query(q).bind(id).execute(&pool).await?;
+fn validate_amount(amount: Money) -> Result<(), FinanceError> {
+    if amount <= Money::ZERO {
+        return Err(FinanceError::Validation(
+            "сумма должна быть больше нуля".to_string(),
+        ));
+    }
+    Ok(())
+}
 
+/// A transfer moves the same amount out of one account and into the other, so
+/// both have to be in one currency.
+fn validate_transfer_currency(from: &str, to: &str) -> Result<(), FinanceError> {
+    if from != to {
+        return Err(FinanceError::Validation(format!(
+            "перевод между аккаунтами в разных валютах ({from} и {to}) не поддерживается"
+        )));
+    }
     Ok(())
-}
\ No newline at end of file
+}
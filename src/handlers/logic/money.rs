@@ -0,0 +1,154 @@
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Neg, Sub};
+use std::str::FromStr;
+
+/// Amount of money stored as minor units (kopecks, cents).
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    sqlx::Type,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+#[sqlx(transparent)]
+pub struct Money(pub i64);
+
+impl Money {
+    pub const ZERO: Money = Money(0);
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let abs = self.0.unsigned_abs();
+        write!(f, "{sign}{}.{:02}", abs / 100, abs % 100)
+    }
+}
+
+impl FromStr for Money {
+    type Err = MoneyParseError;
+
+    /// Accepts `199`, `199.9`, `199.90` and `199,90`, optionally signed.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || MoneyParseError(s.to_string());
+        let (negative, digits) = match s.trim().strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s.trim().strip_prefix('+').unwrap_or(s.trim())),
+        };
+        let (whole, fraction) = match digits.split_once(['.', ',']) {
+            Some((whole, fraction)) => (whole, fraction),
+            None => (digits, ""),
+        };
+        if whole.is_empty()
+            || !whole.bytes().all(|b| b.is_ascii_digit())
+            || fraction.len() > 2
+            || !fraction.bytes().all(|b| b.is_ascii_digit())
+        {
+            return Err(err());
+        }
+
+        let whole: i64 = whole.parse().map_err(|_| err())?;
+        let fraction: i64 = match fraction.len() {
+            0 => 0,
+            1 => fraction.parse::<i64>().map_err(|_| err())? * 10,
+            _ => fraction.parse().map_err(|_| err())?,
+        };
+        let minor = whole
+            .checked_mul(100)
+            .and_then(|m| m.checked_add(fraction))
+            .ok_or_else(err)?;
+
+        Ok(Money(if negative { -minor } else { minor }))
+    }
+}
+
+impl Add for Money {
+    type Output = Money;
+
+    fn add(self, rhs: Money) -> Money {
+        Money(self.0 + rhs.0)
+    }
+}
+
+impl AddAssign for Money {
+    fn add_assign(&mut self, rhs: Money) {
+        self.0 += rhs.0;
+    }
+}
+
+impl Sub for Money {
+    type Output = Money;
+
+    fn sub(self, rhs: Money) -> Money {
+        Money(self.0 - rhs.0)
+    }
+}
+
+impl Neg for Money {
+    type Output = Money;
+
+    fn neg(self) -> Money {
+        Money(-self.0)
+    }
+}
+
+impl Sum for Money {
+    fn sum<I: Iterator<Item = Money>>(iter: I) -> Money {
+        iter.fold(Money::ZERO, Add::add)
+    }
+}
+
+#[derive(Debug)]
+pub struct MoneyParseError(String);
+
+impl fmt::Display for MoneyParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "неверная сумма \"{}\", используйте формат 199.90 или 199,90",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for MoneyParseError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_both_decimal_separators() {
+        assert_eq!("199.90".parse::<Money>().unwrap(), Money(19990));
+        assert_eq!("199,90".parse::<Money>().unwrap(), Money(19990));
+        assert_eq!("199,9".parse::<Money>().unwrap(), Money(19990));
+        assert_eq!("200".parse::<Money>().unwrap(), Money(20000));
+        assert_eq!("0.05".parse::<Money>().unwrap(), Money(5));
+        assert_eq!("-15.5".parse::<Money>().unwrap(), Money(-1550));
+        assert_eq!("+3".parse::<Money>().unwrap(), Money(300));
+    }
+
+    #[test]
+    fn rejects_malformed_amounts() {
+        for s in ["", ".50", "1.234", "1.2.3", "abc", "1e3", "--1", "1,-5"] {
+            assert!(s.parse::<Money>().is_err(), "{s} should not parse");
+        }
+        assert!("92233720368547758.08".parse::<Money>().is_err());
+    }
+
+    #[test]
+    fn formats_with_two_decimals() {
+        assert_eq!(Money(19990).to_string(), "199.90");
+        assert_eq!(Money(5).to_string(), "0.05");
+        assert_eq!(Money(-1550).to_string(), "-15.50");
+        assert_eq!(Money::ZERO.to_string(), "0.00");
+    }
+}
@@ -0,0 +1,113 @@
+use std::fmt;
+
+/// How many names a "did you mean" reply offers.
+pub const SUGGESTIONS: usize = 3;
+
+/// Another name of a category or account, set with `/alias`.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Alias {
+    /// Stored lowercased, as it is matched in any case.
+    pub alias: String,
+    pub target: AliasTarget,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum AliasTarget {
+    Category(String),
+    Account(String),
+}
+
+impl fmt::Display for AliasTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AliasTarget::Category(name) => write!(f, "категория {name}"),
+            AliasTarget::Account(name) => write!(f, "аккаунт {name}"),
+        }
+    }
+}
+
+/// Category or account row a typed name can refer to.
+pub struct NamedRow {
+    pub id: i64,
+    pub name: String,
+    /// Whether `name` is an alias of the row rather than its own name.
+    pub alias: bool,
+}
+
+/// Id of the row `name` refers to: the one called exactly so, else the one
+/// called so in another case, else the one with this alias.
+pub fn resolve_name(rows: &[NamedRow], name: &str) -> Option<i64> {
+    let name = name.trim();
+    let lowercase = name.to_lowercase();
+    let own = || rows.iter().filter(|row| !row.alias);
+    own()
+        .find(|row| row.name == name)
+        .or_else(|| own().find(|row| row.name.to_lowercase() == lowercase))
+        .or_else(|| rows.iter().find(|row| row.alias && row.name == lowercase))
+        .map(|row| row.id)
+}
+
+/// Names of `rows` close enough to `name` to be what the user meant, the
+/// closest first, with their edit distances.
+pub fn closest_names<'a>(rows: &'a [NamedRow], name: &str) -> Vec<(&'a NamedRow, usize)> {
+    let name = name.trim().to_lowercase();
+    // A typo per three letters, so short names don't match everything.
+    let max_distance = (name.chars().count() / 3).max(1);
+    let mut close: Vec<_> = rows
+        .iter()
+        .filter(|row| !row.alias)
+        .map(|row| (row, strsim::levenshtein(&name, &row.name.to_lowercase())))
+        .filter(|(_, distance)| *distance <= max_distance)
+        .collect();
+    close.sort_by(|(a, a_distance), (b, b_distance)| {
+        a_distance.cmp(b_distance).then_with(|| a.name.cmp(&b.name))
+    });
+    close.truncate(SUGGESTIONS);
+    close
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows() -> Vec<NamedRow> {
+        let row = |id, name: &str, alias| NamedRow {
+            id,
+            name: name.to_string(),
+            alias,
+        };
+        vec![
+            row(1, "кафе", false),
+            row(2, "Кафе", false),
+            row(3, "такси", false),
+            row(3, "uber", true),
+            row(4, "квартира", false),
+        ]
+    }
+
+    #[test]
+    fn names_match_in_any_case_and_by_alias() {
+        let rows = rows();
+        assert_eq!(resolve_name(&rows, "кафе"), Some(1));
+        assert_eq!(resolve_name(&rows, "Кафе"), Some(2));
+        assert_eq!(resolve_name(&rows, "ТАКСИ "), Some(3));
+        assert_eq!(resolve_name(&rows, "Uber"), Some(3));
+        assert_eq!(resolve_name(&rows, "кофе"), None);
+    }
+
+    #[test]
+    fn close_names_are_ranked_by_distance() {
+        let rows = rows();
+        let names = |name| -> Vec<(&str, usize)> {
+            closest_names(&rows, name)
+                .into_iter()
+                .map(|(row, distance)| (row.name.as_str(), distance))
+                .collect()
+        };
+        assert_eq!(names("кофе"), [("Кафе", 1), ("кафе", 1)]);
+        assert_eq!(names("таксм"), [("такси", 1)]);
+        assert_eq!(names("квартра"), [("квартира", 1)]);
+        assert!(names("ubr").is_empty());
+        assert!(names("зарплата").is_empty());
+    }
+}
@@ -0,0 +1,75 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Longest tag, a longer `#word` stays in the text of the note.
+pub const MAX_TAG_CHARS: usize = 32;
+
+/// Free text and `#tags` of an expense or income:
+/// `/addexpense 2500 кафе tinkoff ужин с клиентом #work`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Note {
+    #[serde(default)]
+    pub text: String,
+    /// Lowercase, without `#`, sorted and unique.
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+impl Note {
+    pub fn new(text: String, tags: impl IntoIterator<Item = String>) -> Self {
+        let mut tags: Vec<String> = tags.into_iter().map(|tag| tag.to_lowercase()).collect();
+        tags.sort();
+        tags.dedup();
+        Note { text, tags }
+    }
+
+    /// Splits the words of `s` into the tags and the text of the others.
+    pub fn parse(s: &str) -> Self {
+        let (tags, words): (Vec<&str>, Vec<&str>) = s
+            .split_whitespace()
+            .partition(|word| parse_tag(word).is_some());
+        Note::new(words.join(" "), tags.into_iter().filter_map(parse_tag))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty() && self.tags.is_empty()
+    }
+}
+
+impl fmt::Display for Note {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = Some(self.text.clone()).filter(|text| !text.is_empty());
+        let tags = self.tags.iter().map(|tag| format!("#{tag}"));
+        let words: Vec<String> = text.into_iter().chain(tags).collect();
+        write!(f, "{}", words.join(" "))
+    }
+}
+
+/// Tag named by a `#word` of letters, digits, `_` and `-`, lowercased.
+pub fn parse_tag(word: &str) -> Option<String> {
+    let tag = word.strip_prefix('#')?;
+    let valid = |c: char| c.is_alphanumeric() || c == '_' || c == '-';
+    if tag.is_empty() || tag.chars().count() > MAX_TAG_CHARS || !tag.chars().all(valid) {
+        return None;
+    }
+    Some(tag.to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tags_are_taken_out_of_the_text() {
+        let note = Note::parse("ужин  с #Work клиентом #trip #work №5");
+        assert_eq!(note.text, "ужин с клиентом №5");
+        assert_eq!(note.tags, ["trip", "work"]);
+        assert_eq!(note.to_string(), "ужин с клиентом №5 #trip #work");
+
+        assert_eq!(Note::parse("#отпуск").to_string(), "#отпуск");
+        assert_eq!(Note::parse("# #a.b").text, "# #a.b");
+        assert!(Note::parse("  ").is_empty());
+        let long = format!("#{}", "я".repeat(MAX_TAG_CHARS + 1));
+        assert_eq!(Note::parse(&long).text, long);
+    }
+}
@@ -0,0 +1,315 @@
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, TimeZone};
+use std::fmt;
+use std::str::FromStr;
+
+/// Time span used to filter expenses and income.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Period {
+    #[default]
+    All,
+    Today,
+    Week,
+    Month,
+    CalendarMonth {
+        year: i32,
+        month: u32,
+    },
+    /// Inclusive range of days.
+    Range {
+        from: NaiveDate,
+        to: NaiveDate,
+    },
+}
+
+impl Period {
+    /// Half-open `[from, to)` day bounds of the period relative to `today`,
+    /// `None` when the period is unbounded.
+    pub fn bounds(&self, today: NaiveDate) -> Option<(NaiveDate, NaiveDate)> {
+        match *self {
+            Period::All => None,
+            Period::Today => Some((today, today + Duration::days(1))),
+            Period::Week => {
+                let monday = today - Duration::days(today.weekday().num_days_from_monday() as i64);
+                Some((monday, monday + Duration::days(7)))
+            }
+            Period::Month => month_bounds(today.year(), today.month()),
+            Period::CalendarMonth { year, month } => month_bounds(year, month),
+            Period::Range { from, to } => Some((from, to + Duration::days(1))),
+        }
+    }
+
+    /// Bounds as local timestamps, ready to be bound to an `occurred_at` query.
+    pub fn timestamps(&self) -> (Option<DateTime<Local>>, Option<DateTime<Local>>) {
+        match self.bounds(Local::now().date_naive()) {
+            Some((from, to)) => (Some(start_of_day(from)), Some(start_of_day(to))),
+            None => (None, None),
+        }
+    }
+
+    /// Period of the same length right before this one, `None` for `All`.
+    /// Months are compared with whole calendar months.
+    pub fn previous(&self, today: NaiveDate) -> Option<Period> {
+        let (from, to) = self.bounds(today)?;
+        let last_day = from - Duration::days(1);
+        match self {
+            Period::Month | Period::CalendarMonth { .. } => Some(Period::CalendarMonth {
+                year: last_day.year(),
+                month: last_day.month(),
+            }),
+            _ => Some(Period::Range {
+                from: from - (to - from),
+                to: last_day,
+            }),
+        }
+    }
+}
+
+/// Writes the period in the form accepted by `FromStr`.
+impl fmt::Display for Period {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Period::All => write!(f, "all"),
+            Period::Today => write!(f, "today"),
+            Period::Week => write!(f, "week"),
+            Period::Month => write!(f, "month"),
+            Period::CalendarMonth { year, month } => write!(f, "{year:04}-{month:02}"),
+            Period::Range { from, to } => write!(f, "{from}..{to}"),
+        }
+    }
+}
+
+impl FromStr for Period {
+    type Err = PeriodParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_lowercase();
+        let period = match s.as_str() {
+            "" | "all" | "всё" | "все" => Period::All,
+            "today" | "сегодня" => Period::Today,
+            "week" | "неделя" => Period::Week,
+            "month" | "месяц" => Period::Month,
+            _ => {
+                if let Some((from, to)) = s.split_once("..") {
+                    let from = parse_date(from).ok_or_else(|| PeriodParseError(s.clone()))?;
+                    let to = parse_date(to).ok_or_else(|| PeriodParseError(s.clone()))?;
+                    if from > to {
+                        return Err(PeriodParseError(s));
+                    }
+                    Period::Range { from, to }
+                } else if let Some(day) = parse_date(&s) {
+                    Period::Range { from: day, to: day }
+                } else {
+                    let first = NaiveDate::parse_from_str(&format!("{s}-01"), "%Y-%m-%d")
+                        .map_err(|_| PeriodParseError(s.clone()))?;
+                    Period::CalendarMonth {
+                        year: first.year(),
+                        month: first.month(),
+                    }
+                }
+            }
+        };
+        Ok(period)
+    }
+}
+
+#[derive(Debug)]
+pub struct PeriodParseError(String);
+
+impl fmt::Display for PeriodParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "неизвестный период \"{}\", используйте today, week, month, 2026-09 или 2026-09-01..2026-09-15",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for PeriodParseError {}
+
+/// Parses a day written as `2026-09-15` or `15.09.2026`.
+pub fn parse_date(s: &str) -> Option<NaiveDate> {
+    let s = s.trim();
+    NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .or_else(|_| NaiveDate::parse_from_str(s, "%d.%m.%Y"))
+        .ok()
+}
+
+/// Day of an optional date argument: `None` for a word that is no date, an
+/// error for one shaped like `2026-13-01` that is no valid day.
+pub fn parse_date_arg(s: &str) -> Result<Option<NaiveDate>, DateParseError> {
+    if let Some(date) = parse_date(s) {
+        return Ok(Some(date));
+    }
+    let parts: Vec<&str> = s.split('-').collect();
+    let date_like = parts.len() == 3
+        && parts
+            .iter()
+            .all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit()));
+    if date_like {
+        return Err(DateParseError(s.to_string()));
+    }
+    Ok(None)
+}
+
+#[derive(Debug)]
+pub struct DateParseError(String);
+
+impl fmt::Display for DateParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "неверная дата \"{}\", используйте 2026-09-15 или 15.09.2026",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for DateParseError {}
+
+pub fn start_of_day(day: NaiveDate) -> DateTime<Local> {
+    let midnight = day.and_hms_opt(0, 0, 0).unwrap();
+    Local
+        .from_local_datetime(&midnight)
+        .earliest()
+        .unwrap_or_else(|| Local.from_utc_datetime(&midnight))
+}
+
+fn month_bounds(year: i32, month: u32) -> Option<(NaiveDate, NaiveDate)> {
+    let from = NaiveDate::from_ymd_opt(year, month, 1)?;
+    let to = if month == 12 {
+        NaiveDate::from_ymd_opt(year + 1, 1, 1)?
+    } else {
+        NaiveDate::from_ymd_opt(year, month + 1, 1)?
+    };
+    Some((from, to))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn parses_keywords() {
+        assert_eq!("".parse::<Period>().unwrap(), Period::All);
+        assert_eq!("today".parse::<Period>().unwrap(), Period::Today);
+        assert_eq!("Неделя".parse::<Period>().unwrap(), Period::Week);
+        assert_eq!("month".parse::<Period>().unwrap(), Period::Month);
+    }
+
+    #[test]
+    fn parses_months_and_ranges() {
+        assert_eq!(
+            "2026-09".parse::<Period>().unwrap(),
+            Period::CalendarMonth {
+                year: 2026,
+                month: 9
+            }
+        );
+        assert_eq!(
+            "2026-09-01..15.09.2026".parse::<Period>().unwrap(),
+            Period::Range {
+                from: day(2026, 9, 1),
+                to: day(2026, 9, 15)
+            }
+        );
+        assert!("2026-09-15..2026-09-01".parse::<Period>().is_err());
+        assert!("yesterday".parse::<Period>().is_err());
+        assert!("2026-13".parse::<Period>().is_err());
+    }
+
+    #[test]
+    fn date_arguments_are_dates_or_other_words() {
+        assert_eq!(
+            parse_date_arg("15.09.2026").unwrap(),
+            Some(day(2026, 9, 15))
+        );
+        assert_eq!(parse_date_arg("ужин").unwrap(), None);
+        assert_eq!(parse_date_arg("2-3").unwrap(), None);
+        assert_eq!(parse_date_arg("5").unwrap(), None);
+        let err = parse_date_arg("2026-13-01").unwrap_err();
+        assert!(err.to_string().starts_with("неверная дата \"2026-13-01\""));
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for s in [
+            "all",
+            "today",
+            "week",
+            "month",
+            "2026-09",
+            "2026-09-01..2026-09-15",
+        ] {
+            assert_eq!(s.parse::<Period>().unwrap().to_string(), s);
+        }
+    }
+
+    #[test]
+    fn computes_bounds() {
+        let today = day(2026, 10, 18);
+        assert_eq!(Period::All.bounds(today), None);
+        assert_eq!(
+            Period::Week.bounds(today),
+            Some((day(2026, 10, 12), day(2026, 10, 19)))
+        );
+        assert_eq!(
+            Period::CalendarMonth {
+                year: 2026,
+                month: 12
+            }
+            .bounds(today),
+            Some((day(2026, 12, 1), day(2027, 1, 1)))
+        );
+        assert_eq!(
+            Period::Range {
+                from: day(2026, 9, 1),
+                to: day(2026, 9, 1)
+            }
+            .bounds(today),
+            Some((day(2026, 9, 1), day(2026, 9, 2)))
+        );
+    }
+
+    #[test]
+    fn computes_previous_period() {
+        let today = day(2026, 10, 18);
+        assert_eq!(Period::All.previous(today), None);
+        assert_eq!(
+            Period::Today.previous(today),
+            Some(Period::Range {
+                from: day(2026, 10, 17),
+                to: day(2026, 10, 17)
+            })
+        );
+        assert_eq!(
+            Period::Week.previous(today),
+            Some(Period::Range {
+                from: day(2026, 10, 5),
+                to: day(2026, 10, 11)
+            })
+        );
+        assert_eq!(
+            Period::CalendarMonth {
+                year: 2026,
+                month: 1
+            }
+            .previous(today),
+            Some(Period::CalendarMonth {
+                year: 2025,
+                month: 12
+            })
+        );
+        assert_eq!(
+            Period::Month.previous(today),
+            Some(Period::CalendarMonth {
+                year: 2026,
+                month: 9
+            })
+        );
+    }
+}
@@ -0,0 +1,1824 @@
+use super::*;
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+use futures::{StreamExt, TryStreamExt};
+use sqlx::postgres::{PgPool, PgPoolOptions, PgRow};
+use sqlx::{Postgres, Row, Transaction};
+use std::collections::{HashMap, HashSet};
+
+#[derive(Clone)]
+pub struct PgStore {
+    pool: PgPool,
+}
+
+impl PgStore {
+    pub async fn connect(url: &str) -> Result<Self, FinanceError> {
+        let pool = PgPoolOptions::new()
+            .max_connections(30)
+            .connect(url)
+            .await?;
+
+        sqlx::migrate!("./migrations").run(&pool).await?;
+
+        Ok(PgStore { pool })
+    }
+}
+
+/// Tells a missing row from one owned by another user after a query scoped
+/// to the user matched nothing.
+async fn missing_row<'c, E>(executor: E, table: &str, id: i64) -> FinanceError
+where
+    E: sqlx::Executor<'c, Database = Postgres>,
+{
+    let q = format!("SELECT user_id FROM {table} WHERE id = $1");
+    match sqlx::query(&q).bind(id).fetch_optional(executor).await {
+        Ok(Some(_)) => FinanceError::NotOwner,
+        Ok(None) => FinanceError::NotFound,
+        Err(e) => e.into(),
+    }
+}
+
+/// Categories or accounts of the user with their aliases, as `query`
+/// selects them, to resolve a typed name.
+async fn named_rows<'c, E>(
+    executor: E,
+    query: &str,
+    user_id: i64,
+) -> Result<Vec<NamedRow>, FinanceError>
+where
+    E: sqlx::Executor<'c, Database = Postgres>,
+{
+    let rows = sqlx::query(query).bind(user_id).fetch_all(executor).await?;
+    Ok(rows
+        .iter()
+        .map(|row| NamedRow {
+            id: row.get("id"),
+            name: row.get("name"),
+            alias: row.get("alias"),
+        })
+        .collect())
+}
+
+/// Id of the user's category called `name` in any case or by an alias.
+async fn category_id<'c, E>(executor: E, user_id: i64, name: &str) -> Result<i64, FinanceError>
+where
+    E: sqlx::Executor<'c, Database = Postgres>,
+{
+    let q = "SELECT id, name, FALSE AS alias FROM categories WHERE user_id = $1
+    UNION ALL SELECT category_id AS id, alias AS name, TRUE AS alias FROM aliases
+    WHERE user_id = $1 AND category_id IS NOT NULL";
+    let rows = named_rows(executor, q, user_id).await?;
+    resolve_name(&rows, name).ok_or_else(|| FinanceError::UnknownCategory(name.to_string()))
+}
+
+/// Id of the user's account called `name` in any case or by an alias.
+async fn account_id<'c, E>(executor: E, user_id: i64, name: &str) -> Result<i64, FinanceError>
+where
+    E: sqlx::Executor<'c, Database = Postgres>,
+{
+    let q = "SELECT id, name, FALSE AS alias FROM accounts WHERE user_id = $1
+    UNION ALL SELECT account_id AS id, alias AS name, TRUE AS alias FROM aliases
+    WHERE user_id = $1 AND account_id IS NOT NULL";
+    let rows = named_rows(executor, q, user_id).await?;
+    resolve_name(&rows, name).ok_or_else(|| FinanceError::UnknownAccount(name.to_string()))
+}
+
+/// Reports deleting a row the ledger still refers to as a validation error.
+fn referenced(e: sqlx::Error, message: &str) -> FinanceError {
+    match e {
+        sqlx::Error::Database(db) if db.is_foreign_key_violation() => {
+            FinanceError::Validation(message.to_string())
+        }
+        e => e.into(),
+    }
+}
+
+/// Columns of `Recurring`, to be followed by the `WHERE` clause.
+const RECURRING_QUERY: &str = "SELECT recurring.id, recurring.user_id, recurring.income, recurring.amount,
+    categories.name AS category_name, accounts.name AS account_name, recurring.schedule, recurring.next_run
+    FROM recurring
+    JOIN categories ON recurring.category_id = categories.id
+    JOIN accounts ON recurring.account_id = accounts.id";
+
+fn recurring_from_row(row: &PgRow) -> Result<Recurring, FinanceError> {
+    let schedule: String = row.get("schedule");
+    Ok(Recurring {
+        id: row.get("id"),
+        user_id: row.get("user_id"),
+        income: row.get("income"),
+        amount: row.get("amount"),
+        category: row.get("category_name"),
+        account: row.get("account_name"),
+        schedule: Schedule::from_column(&schedule)?,
+        next_run: row.get("next_run"),
+    })
+}
+
+/// Note of a row selecting the `note` and the space separated `tags` of an
+/// expense or income.
+fn note_from_row(row: &PgRow) -> Note {
+    let text: Option<String> = row.get("note");
+    let tags: Option<String> = row.get("tags");
+    Note {
+        text: text.unwrap_or_default(),
+        tags: tags
+            .unwrap_or_default()
+            .split_whitespace()
+            .map(str::to_string)
+            .collect(),
+    }
+}
+
+/// Stores the note and tags of the expense or income `id`.
+async fn insert_note(
+    tx: &mut Transaction<'_, Postgres>,
+    income: bool,
+    id: i64,
+    note: &Note,
+) -> Result<(), FinanceError> {
+    let kind = if income { "income" } else { "expense" };
+    if !note.text.is_empty() {
+        let q = format!("INSERT INTO {kind}_notes ({kind}_id, note) VALUES ($1, $2)");
+        sqlx::query(&q)
+            .bind(id)
+            .bind(&note.text)
+            .execute(&mut **tx)
+            .await?;
+    }
+    let q = format!("INSERT INTO {kind}_tags ({kind}_id, tag) VALUES ($1, $2)");
+    for tag in &note.tags {
+        sqlx::query(&q)
+            .bind(id)
+            .bind(tag)
+            .execute(&mut **tx)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Books an expense or income inside `tx`, shared by adding and importing.
+/// Returns the id of the new row, `None` for an imported row whose
+/// fingerprint was booked before.
+async fn insert_entry(
+    tx: &mut Transaction<'_, Postgres>,
+    income: bool,
+    user_id: i64,
+    entry: NewEntry,
+) -> Result<Option<i64>, FinanceError> {
+    validate_amount(entry.amount)?;
+
+    let cat_id = category_id(&mut **tx, user_id, &entry.category).await?;
+    let acc_id = account_id(&mut **tx, user_id, &entry.account).await?;
+
+    // Lock the account row so concurrent ledger changes are applied one by one.
+    let lock_q = "SELECT id FROM accounts WHERE id = $1 FOR UPDATE";
+    sqlx::query(lock_q).bind(acc_id).execute(&mut **tx).await?;
+
+    let (table, sign) = if income {
+        ("income", "+")
+    } else {
+        ("expenses", "-")
+    };
+    let fingerprint = fingerprint(acc_id, &entry);
+    let query = format!(
+        "INSERT INTO {table} (account_id, category_id, amount, user_id, occurred_at, import_ref, fingerprint)
+    VALUES ($1, $2, $3, $4, COALESCE($5, now()), $6, $7)
+    ON CONFLICT (user_id, fingerprint) WHERE import_ref IS NOT NULL DO NOTHING
+    RETURNING id"
+    );
+    let inserted = sqlx::query(&query)
+        .bind(acc_id)
+        .bind(cat_id)
+        .bind(entry.amount)
+        .bind(user_id)
+        .bind(entry.date.map(start_of_day))
+        .bind(entry.import_ref)
+        .bind(fingerprint)
+        .fetch_optional(&mut **tx)
+        .await?;
+    let Some(inserted) = inserted else {
+        return Ok(None);
+    };
+
+    let set_balance_q = format!("UPDATE accounts SET balance = balance {sign} $1 WHERE id = $2");
+    sqlx::query(&set_balance_q)
+        .bind(entry.amount)
+        .bind(acc_id)
+        .execute(&mut **tx)
+        .await?;
+
+    let id = inserted.get("id");
+    insert_note(tx, income, id, &entry.note).await?;
+
+    Ok(Some(id))
+}
+
+#[async_trait]
+impl FinanceStore for PgStore {
+    async fn add_account(&self, account: Accounts) -> Result<(), FinanceError> {
+        let query =
+            "INSERT INTO accounts (name, balance, user_id, currency) VALUES ($1, $2, $3, $4)";
+        sqlx::query(query)
+            .bind(&account.name)
+            .bind(account.balance)
+            .bind(account.user_id)
+            .bind(&account.currency)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn add_category(&self, category: Categories) -> Result<(), FinanceError> {
+        let query = "INSERT INTO categories (name, user_id, description) VALUES ($1, $2, $3)";
+        sqlx::query(query)
+            .bind(&category.name)
+            .bind(category.user_id)
+            .bind(&category.description)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_accounts_page(
+        &self,
+        user_id: i64,
+        page: Option<Page>,
+    ) -> Result<Paged<Accounts>, FinanceError> {
+        let q = "SELECT *, COUNT(*) OVER () AS total FROM accounts WHERE user_id = $1 ORDER BY id LIMIT $2 OFFSET $3";
+        let query = sqlx::query(q)
+            .bind(user_id)
+            .bind(page.map(|p| p.size))
+            .bind(page.map_or(0, |p| p.offset()));
+        let mut rows = query.fetch(&self.pool);
+
+        let mut accounts = vec![];
+        let mut total = 0;
+
+        while let Some(row) = rows.try_next().await? {
+            total = row.get("total");
+            accounts.push(Accounts {
+                id: row.get("id"),
+                name: row.get("name"),
+                balance: row.get("balance"),
+                user_id: row.get("user_id"),
+                currency: row.get("currency"),
+            })
+        }
+
+        Ok(Paged {
+            items: accounts,
+            total,
+        })
+    }
+
+    async fn get_categories_page(
+        &self,
+        user_id: i64,
+        page: Option<Page>,
+    ) -> Result<Paged<Categories>, FinanceError> {
+        let q = "SELECT *, COUNT(*) OVER () AS total FROM categories WHERE user_id = $1 ORDER BY id LIMIT $2 OFFSET $3";
+        let query = sqlx::query(q)
+            .bind(user_id)
+            .bind(page.map(|p| p.size))
+            .bind(page.map_or(0, |p| p.offset()));
+        let mut rows = query.fetch(&self.pool);
+
+        let mut categories = vec![];
+        let mut total = 0;
+
+        while let Some(row) = rows.try_next().await? {
+            total = row.get("total");
+            categories.push(Categories {
+                id: row.get("id"),
+                name: row.get("name"),
+                user_id: row.get("user_id"),
+                description: row.get("description"),
+            })
+        }
+
+        Ok(Paged {
+            items: categories,
+            total,
+        })
+    }
+
+    async fn del_account(&self, user_id: i64, id: i64) -> Result<(), FinanceError> {
+        let q = "DELETE FROM accounts WHERE id = $1 AND user_id = $2 ";
+        let res = sqlx::query(q)
+            .bind(id)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| referenced(e, ACCOUNT_IN_USE))?;
+        if res.rows_affected() == 0 {
+            return Err(missing_row(&self.pool, "accounts", id).await);
+        }
+
+        Ok(())
+    }
+
+    async fn del_category(&self, user_id: i64, id: i64) -> Result<(), FinanceError> {
+        let q = "DELETE FROM categories WHERE id = $1 AND user_id = $2 ";
+        let res = sqlx::query(q)
+            .bind(id)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| referenced(e, CATEGORY_IN_USE))?;
+        if res.rows_affected() == 0 {
+            return Err(missing_row(&self.pool, "categories", id).await);
+        }
+
+        Ok(())
+    }
+
+    async fn edit_category(
+        &self,
+        user_id: i64,
+        id: i64,
+        name: String,
+        description: String,
+    ) -> Result<(), FinanceError> {
+        let q = "UPDATE categories SET name = $1, description = $2 WHERE id = $3 AND user_id = $4 ";
+        let res = sqlx::query(q)
+            .bind(name)
+            .bind(description)
+            .bind(id)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+        if res.rows_affected() == 0 {
+            return Err(missing_row(&self.pool, "categories", id).await);
+        }
+
+        Ok(())
+    }
+
+    async fn edit_account(
+        &self,
+        user_id: i64,
+        id: i64,
+        name: String,
+        balance: Money,
+    ) -> Result<(), FinanceError> {
+        let q = "UPDATE accounts SET name = $1, balance = $2 WHERE id = $3 AND user_id = $4 ";
+        let res = sqlx::query(q)
+            .bind(name)
+            .bind(balance)
+            .bind(id)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+        if res.rows_affected() == 0 {
+            return Err(missing_row(&self.pool, "accounts", id).await);
+        }
+
+        Ok(())
+    }
+
+    async fn get_expense_page(
+        &self,
+        user_id: i64,
+        period: Period,
+        tag: Option<String>,
+        page: Option<Page>,
+    ) -> Result<Paged<Expenses>, FinanceError> {
+        let (from, to) = period.timestamps();
+        let q = "SELECT expenses.id, accounts.name AS account_name, categories.name AS category_name, expenses.amount, expenses.user_id, expenses.occurred_at, expense_notes.note,
+        (SELECT string_agg(tag, ' ' ORDER BY tag) FROM expense_tags WHERE expense_id = expenses.id) AS tags, COUNT(*) OVER () AS total
+    FROM expenses
+    JOIN accounts ON expenses.account_id = accounts.id
+    JOIN categories ON expenses.category_id = categories.id
+    LEFT JOIN expense_notes ON expense_notes.expense_id = expenses.id
+    WHERE expenses.user_id = $1
+    AND ($2::timestamptz IS NULL OR expenses.occurred_at >= $2)
+    AND ($3::timestamptz IS NULL OR expenses.occurred_at < $3)
+    AND ($6::text IS NULL OR EXISTS (SELECT 1 FROM expense_tags WHERE expense_id = expenses.id AND tag = $6))
+    ORDER BY expenses.occurred_at, expenses.id
+    LIMIT $4 OFFSET $5";
+        let query = sqlx::query(q)
+            .bind(user_id)
+            .bind(from)
+            .bind(to)
+            .bind(page.map(|p| p.size))
+            .bind(page.map_or(0, |p| p.offset()))
+            .bind(tag);
+        let mut rows = query.fetch(&self.pool);
+
+        let mut expenses = vec![];
+        let mut total = 0;
+
+        while let Some(row) = rows.try_next().await? {
+            total = row.get("total");
+            expenses.push(Expenses {
+                id: row.get("id"),
+                account: row.get("account_name"),
+                category: row.get("category_name"),
+                amount: row.get("amount"),
+                user_id: row.get("user_id"),
+                occurred_at: row.get("occurred_at"),
+                note: note_from_row(&row),
+            });
+        }
+
+        Ok(Paged {
+            items: expenses,
+            total,
+        })
+    }
+
+    async fn get_income_page(
+        &self,
+        user_id: i64,
+        period: Period,
+        tag: Option<String>,
+        page: Option<Page>,
+    ) -> Result<Paged<Income>, FinanceError> {
+        let (from, to) = period.timestamps();
+        let q = "select income.id, accounts.name AS account_name, categories.name AS category_name, income.amount, income.user_id, income.occurred_at, income_notes.note,
+        (SELECT string_agg(tag, ' ' ORDER BY tag) FROM income_tags WHERE income_id = income.id) AS tags, COUNT(*) OVER () AS total
+    FROM income
+    JOIN accounts ON income.account_id = accounts.id
+    JOIN categories ON income.category_id = categories.id
+    LEFT JOIN income_notes ON income_notes.income_id = income.id
+    WHERE income.user_id = $1
+    AND ($2::timestamptz IS NULL OR income.occurred_at >= $2)
+    AND ($3::timestamptz IS NULL OR income.occurred_at < $3)
+    AND ($6::text IS NULL OR EXISTS (SELECT 1 FROM income_tags WHERE income_id = income.id AND tag = $6))
+    ORDER BY income.occurred_at, income.id
+    LIMIT $4 OFFSET $5";
+        let query = sqlx::query(q)
+            .bind(user_id)
+            .bind(from)
+            .bind(to)
+            .bind(page.map(|p| p.size))
+            .bind(page.map_or(0, |p| p.offset()))
+            .bind(tag);
+        let mut rows = query.fetch(&self.pool);
+
+        let mut income = vec![];
+        let mut total = 0;
+
+        while let Some(row) = rows.try_next().await? {
+            total = row.get("total");
+            income.push(Income {
+                id: row.get("id"),
+                account: row.get("account_name"),
+                category: row.get("category_name"),
+                amount: row.get("amount"),
+                user_id: row.get("user_id"),
+                occurred_at: row.get("occurred_at"),
+                note: note_from_row(&row),
+            });
+        }
+
+        Ok(Paged {
+            items: income,
+            total,
+        })
+    }
+
+    async fn add_entry(
+        &self,
+        user_id: i64,
+        income: bool,
+        entry: NewEntry,
+    ) -> Result<i64, FinanceError> {
+        let mut tx = self.pool.begin().await?;
+        let id = insert_entry(&mut tx, income, user_id, entry).await?;
+        tx.commit().await?;
+
+        Ok(id.expect("only imported rows are skipped"))
+    }
+
+    async fn del_income(&self, user_id: i64, id: i64) -> Result<(), FinanceError> {
+        let mut tx = self.pool.begin().await?;
+
+        // Deleting first locks the income row, so a second concurrent delete of
+        // the same id finds nothing and cannot reverse the balance twice. Rows of
+        // other users are never matched and are reported as not found.
+        let q = "DELETE FROM income WHERE id = $1 AND user_id = $2 RETURNING account_id, amount";
+        let row = sqlx::query(q)
+            .bind(id)
+            .bind(user_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+        let Some(row) = row else {
+            return Err(missing_row(&mut *tx, "income", id).await);
+        };
+        let acc_id: i64 = row.get("account_id");
+        let amount: Money = row.get("amount");
+
+        let set_balance_q = "UPDATE accounts SET balance = balance - $1 WHERE id = $2 ";
+        sqlx::query(set_balance_q)
+            .bind(amount)
+            .bind(acc_id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn import_rows(
+        &self,
+        user_id: i64,
+        account: String,
+        rows: Vec<StatementRow>,
+    ) -> Result<usize, FinanceError> {
+        let mut tx = self.pool.begin().await?;
+
+        let mut added = 0;
+        for row in rows {
+            let entry = NewEntry {
+                amount: row.amount,
+                category: row.category,
+                account: account.clone(),
+                date: Some(row.date),
+                import_ref: Some(row.import_ref),
+                note: Note::default(),
+            };
+            let income = row.kind == EntryKind::Income;
+            if insert_entry(&mut tx, income, user_id, entry)
+                .await?
+                .is_some()
+            {
+                added += 1;
+            }
+        }
+
+        tx.commit().await?;
+
+        Ok(added)
+    }
+
+    async fn get_import_fingerprints(&self, user_id: i64) -> Result<HashSet<String>, FinanceError> {
+        let q = "SELECT fingerprint FROM expenses WHERE user_id = $1 AND import_ref IS NOT NULL
+    UNION
+    SELECT fingerprint FROM income WHERE user_id = $1 AND import_ref IS NOT NULL";
+        let mut rows = sqlx::query(q).bind(user_id).fetch(&self.pool);
+
+        let mut fingerprints = HashSet::new();
+
+        while let Some(row) = rows.try_next().await? {
+            fingerprints.insert(row.get("fingerprint"));
+        }
+
+        Ok(fingerprints)
+    }
+
+    async fn has_recent_duplicate(
+        &self,
+        user_id: i64,
+        income: bool,
+        entry: &NewEntry,
+        since: DateTime<Local>,
+    ) -> Result<bool, FinanceError> {
+        let acc_id = match account_id(&self.pool, user_id, &entry.account).await {
+            Ok(id) => id,
+            Err(FinanceError::UnknownAccount(_)) => return Ok(false),
+            Err(e) => return Err(e),
+        };
+
+        let table = if income { "income" } else { "expenses" };
+        let q = format!(
+            "SELECT 1 FROM {table} WHERE user_id = $1 AND fingerprint = $2 AND created_at >= $3 LIMIT 1"
+        );
+        let duplicate = sqlx::query(&q)
+            .bind(user_id)
+            .bind(fingerprint(acc_id, entry))
+            .bind(since)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(duplicate.is_some())
+    }
+
+    async fn get_import_mapping(
+        &self,
+        user_id: i64,
+    ) -> Result<Option<ColumnMapping>, FinanceError> {
+        let q = "SELECT date_column, amount_column, category_column, description_column
+    FROM import_mappings WHERE user_id = $1";
+        let row = sqlx::query(q)
+            .bind(user_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.map(|row| ColumnMapping {
+            date: row.get::<i64, _>("date_column") as usize,
+            amount: row.get::<i64, _>("amount_column") as usize,
+            category: row.get::<i64, _>("category_column") as usize,
+            description: row
+                .get::<Option<i64>, _>("description_column")
+                .map(|c| c as usize),
+        }))
+    }
+
+    async fn set_import_mapping(
+        &self,
+        user_id: i64,
+        mapping: ColumnMapping,
+    ) -> Result<(), FinanceError> {
+        let q = "INSERT INTO import_mappings (user_id, date_column, amount_column, category_column, description_column)
+    VALUES ($1, $2, $3, $4, $5)
+    ON CONFLICT (user_id) DO UPDATE SET date_column = EXCLUDED.date_column, amount_column = EXCLUDED.amount_column,
+    category_column = EXCLUDED.category_column, description_column = EXCLUDED.description_column";
+        sqlx::query(q)
+            .bind(user_id)
+            .bind(mapping.date as i64)
+            .bind(mapping.amount as i64)
+            .bind(mapping.category as i64)
+            .bind(mapping.description.map(|c| c as i64))
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn get_aliases(&self, user_id: i64) -> Result<Vec<Alias>, FinanceError> {
+        let q = "SELECT aliases.alias, categories.name AS category, accounts.name AS account FROM aliases
+    LEFT JOIN categories ON aliases.category_id = categories.id
+    LEFT JOIN accounts ON aliases.account_id = accounts.id
+    WHERE aliases.user_id = $1 ORDER BY aliases.alias";
+        let rows = sqlx::query(q).bind(user_id).fetch_all(&self.pool).await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let target = match row.get::<Option<String>, _>("category") {
+                    Some(category) => AliasTarget::Category(category),
+                    None => AliasTarget::Account(row.get("account")),
+                };
+                Alias {
+                    alias: row.get("alias"),
+                    target,
+                }
+            })
+            .collect())
+    }
+
+    async fn set_alias(
+        &self,
+        user_id: i64,
+        alias: String,
+        name: String,
+    ) -> Result<AliasTarget, FinanceError> {
+        let mut tx = self.pool.begin().await?;
+
+        let (category_id, account_id, target) = match category_id(&mut *tx, user_id, &name).await {
+            Ok(id) => {
+                let q = "SELECT name FROM categories WHERE id = $1";
+                let row = sqlx::query(q).bind(id).fetch_one(&mut *tx).await?;
+                (Some(id), None, AliasTarget::Category(row.get("name")))
+            }
+            Err(FinanceError::UnknownCategory(_)) => {
+                let id = account_id(&mut *tx, user_id, &name)
+                    .await
+                    .map_err(|_| FinanceError::UnknownCategory(name))?;
+                let q = "SELECT name FROM accounts WHERE id = $1";
+                let row = sqlx::query(q).bind(id).fetch_one(&mut *tx).await?;
+                (None, Some(id), AliasTarget::Account(row.get("name")))
+            }
+            Err(e) => return Err(e),
+        };
+
+        let q = "INSERT INTO aliases (user_id, alias, category_id, account_id) VALUES ($1, $2, $3, $4)
+    ON CONFLICT (user_id, alias) DO UPDATE SET category_id = EXCLUDED.category_id, account_id = EXCLUDED.account_id";
+        sqlx::query(q)
+            .bind(user_id)
+            .bind(alias.trim().to_lowercase())
+            .bind(category_id)
+            .bind(account_id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(target)
+    }
+
+    async fn del_alias(&self, user_id: i64, alias: String) -> Result<(), FinanceError> {
+        let q = "DELETE FROM aliases WHERE user_id = $1 AND alias = $2";
+        let res = sqlx::query(q)
+            .bind(user_id)
+            .bind(alias.trim().to_lowercase())
+            .execute(&self.pool)
+            .await?;
+        if res.rows_affected() == 0 {
+            return Err(FinanceError::NotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn get_dialogue(&self, user_id: i64) -> Result<Option<String>, FinanceError> {
+        let q = "SELECT state FROM dialogues WHERE user_id = $1";
+        let row = sqlx::query(q)
+            .bind(user_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.map(|row| row.get("state")))
+    }
+
+    async fn set_dialogue(&self, user_id: i64, state: String) -> Result<(), FinanceError> {
+        let q = "INSERT INTO dialogues (user_id, state) VALUES ($1, $2)
+    ON CONFLICT (user_id) DO UPDATE SET state = EXCLUDED.state";
+        sqlx::query(q)
+            .bind(user_id)
+            .bind(state)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn del_dialogue(&self, user_id: i64) -> Result<(), FinanceError> {
+        let q = "DELETE FROM dialogues WHERE user_id = $1";
+        sqlx::query(q).bind(user_id).execute(&self.pool).await?;
+
+        Ok(())
+    }
+
+    async fn save_callback_payload(
+        &self,
+        user_id: i64,
+        payload: String,
+    ) -> Result<i64, FinanceError> {
+        let q = "SELECT id FROM callback_payloads WHERE user_id = $1 AND payload = $2 ORDER BY id LIMIT 1";
+        let saved: Option<i64> = sqlx::query_scalar(q)
+            .bind(user_id)
+            .bind(&payload)
+            .fetch_optional(&self.pool)
+            .await?;
+        if let Some(id) = saved {
+            return Ok(id);
+        }
+
+        let q = "INSERT INTO callback_payloads (user_id, payload) VALUES ($1, $2) RETURNING id";
+        let id = sqlx::query_scalar(q)
+            .bind(user_id)
+            .bind(payload)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(id)
+    }
+
+    async fn get_callback_payload(
+        &self,
+        user_id: i64,
+        id: i64,
+    ) -> Result<Option<String>, FinanceError> {
+        let q = "SELECT payload FROM callback_payloads WHERE id = $1 AND user_id = $2";
+        let payload = sqlx::query_scalar(q)
+            .bind(id)
+            .bind(user_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(payload)
+    }
+
+    async fn take_callback_payload(
+        &self,
+        user_id: i64,
+        id: i64,
+    ) -> Result<Option<String>, FinanceError> {
+        // Deleting returns the payload once, however often the button is pressed.
+        let q = "DELETE FROM callback_payloads WHERE id = $1 AND user_id = $2 RETURNING payload";
+        let payload = sqlx::query_scalar(q)
+            .bind(id)
+            .bind(user_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(payload)
+    }
+
+    async fn get_payee_rules(&self, user_id: i64) -> Result<Vec<PayeeRule>, FinanceError> {
+        let q = "SELECT payee_rules.pattern, categories.name FROM payee_rules
+    JOIN categories ON payee_rules.category_id = categories.id
+    WHERE payee_rules.user_id = $1 ORDER BY payee_rules.pattern";
+        let rows = sqlx::query(q).bind(user_id).fetch_all(&self.pool).await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| PayeeRule {
+                pattern: row.get("pattern"),
+                category: row.get("name"),
+            })
+            .collect())
+    }
+
+    async fn set_payee_rule(
+        &self,
+        user_id: i64,
+        pattern: String,
+        category: String,
+    ) -> Result<(), FinanceError> {
+        let category_id = category_id(&self.pool, user_id, &category).await?;
+
+        let q = "INSERT INTO payee_rules (user_id, pattern, category_id) VALUES ($1, $2, $3)
+    ON CONFLICT (user_id, pattern) DO UPDATE SET category_id = EXCLUDED.category_id";
+        sqlx::query(q)
+            .bind(user_id)
+            .bind(pattern.to_lowercase())
+            .bind(category_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn del_payee_rule(&self, user_id: i64, pattern: String) -> Result<(), FinanceError> {
+        let q = "DELETE FROM payee_rules WHERE user_id = $1 AND pattern = $2 ";
+        let res = sqlx::query(q)
+            .bind(user_id)
+            .bind(pattern.to_lowercase())
+            .execute(&self.pool)
+            .await?;
+        if res.rows_affected() == 0 {
+            return Err(FinanceError::NotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn add_recurring(
+        &self,
+        user_id: i64,
+        recurring: NewRecurring,
+    ) -> Result<(), FinanceError> {
+        validate_amount(recurring.amount)?;
+
+        let category_id = category_id(&self.pool, user_id, &recurring.category).await?;
+        let account_id = account_id(&self.pool, user_id, &recurring.account).await?;
+
+        let q = "INSERT INTO recurring (user_id, income, amount, category_id, account_id, schedule, next_run)
+    VALUES ($1, $2, $3, $4, $5, $6, $7)";
+        sqlx::query(q)
+            .bind(user_id)
+            .bind(recurring.income)
+            .bind(recurring.amount)
+            .bind(category_id)
+            .bind(account_id)
+            .bind(recurring.schedule.to_column())
+            .bind(recurring.next_run)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn get_recurring(&self, user_id: i64) -> Result<Vec<Recurring>, FinanceError> {
+        let q = format!("{RECURRING_QUERY} WHERE recurring.user_id = $1 ORDER BY recurring.id");
+        let rows = sqlx::query(&q).bind(user_id).fetch_all(&self.pool).await?;
+        rows.iter().map(recurring_from_row).collect()
+    }
+
+    async fn del_recurring(&self, user_id: i64, id: i64) -> Result<(), FinanceError> {
+        let q = "DELETE FROM recurring WHERE id = $1 AND user_id = $2 ";
+        let res = sqlx::query(q)
+            .bind(id)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+        if res.rows_affected() == 0 {
+            return Err(missing_row(&self.pool, "recurring", id).await);
+        }
+
+        Ok(())
+    }
+
+    async fn due_recurring(&self, today: NaiveDate) -> Result<Vec<Recurring>, FinanceError> {
+        let q = format!("{RECURRING_QUERY} WHERE recurring.next_run <= $1 ORDER BY recurring.next_run, recurring.id");
+        let rows = sqlx::query(&q).bind(today).fetch_all(&self.pool).await?;
+        rows.iter().map(recurring_from_row).collect()
+    }
+
+    async fn book_recurring_run(
+        &self,
+        user_id: i64,
+        id: i64,
+        run: NaiveDate,
+    ) -> Result<bool, FinanceError> {
+        let mut tx = self.pool.begin().await?;
+
+        // An instance booking the run holds the row and one that has booked it
+        // has moved `next_run` on, so either way the run is not booked twice.
+        let q = format!(
+            "{RECURRING_QUERY} WHERE recurring.id = $1 AND recurring.user_id = $2 AND recurring.next_run = $3 FOR UPDATE OF recurring SKIP LOCKED"
+        );
+        let row = sqlx::query(&q)
+            .bind(id)
+            .bind(user_id)
+            .bind(run)
+            .fetch_optional(&mut *tx)
+            .await?;
+        let Some(row) = row else {
+            return Ok(false);
+        };
+        let recurring = recurring_from_row(&row)?;
+        insert_entry(&mut tx, recurring.income, user_id, recurring.entry(run)).await?;
+
+        sqlx::query("UPDATE recurring SET next_run = $1 WHERE id = $2")
+            .bind(recurring.schedule.next_after(run))
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(true)
+    }
+
+    async fn set_recurring_next_run(
+        &self,
+        user_id: i64,
+        id: i64,
+        next_run: NaiveDate,
+    ) -> Result<(), FinanceError> {
+        let q = "UPDATE recurring SET next_run = $1 WHERE id = $2 AND user_id = $3 ";
+        let res = sqlx::query(q)
+            .bind(next_run)
+            .bind(id)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+        if res.rows_affected() == 0 {
+            return Err(missing_row(&self.pool, "recurring", id).await);
+        }
+
+        Ok(())
+    }
+
+    async fn backup(&self, user_id: i64) -> Result<Backup, FinanceError> {
+        let mut tx = self.pool.begin().await?;
+        // One snapshot, so rows added meanwhile don't refer to missing ones.
+        sqlx::query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ READ ONLY")
+            .execute(&mut *tx)
+            .await?;
+
+        let q = "SELECT id, name, balance, currency FROM accounts WHERE user_id = $1 ORDER BY id";
+        let accounts = sqlx::query(q)
+            .bind(user_id)
+            .fetch_all(&mut *tx)
+            .await?
+            .into_iter()
+            .map(|row| BackupAccount {
+                id: row.get("id"),
+                name: row.get("name"),
+                balance: row.get("balance"),
+                currency: row.get("currency"),
+            })
+            .collect();
+
+        let q = "SELECT id, name, description, budget_limit, budget_period FROM categories WHERE user_id = $1 ORDER BY id";
+        let categories = sqlx::query(q)
+            .bind(user_id)
+            .fetch_all(&mut *tx)
+            .await?
+            .into_iter()
+            .map(|row| {
+                let limit: Option<Money> = row.get("budget_limit");
+                let period: Option<String> = row.get("budget_period");
+                BackupCategory {
+                    id: row.get("id"),
+                    name: row.get("name"),
+                    description: row.get("description"),
+                    budget: limit.map(|limit| BackupBudget {
+                        limit,
+                        period: period.unwrap_or_default().parse().unwrap_or_default(),
+                    }),
+                }
+            })
+            .collect();
+
+        let mut entries = vec![];
+        for (table, kind) in [("expenses", "expense"), ("income", "income")] {
+            let q = format!("SELECT account_id, category_id, amount, occurred_at, import_ref, {kind}_notes.note,
+        (SELECT string_agg(tag, ' ' ORDER BY tag) FROM {kind}_tags WHERE {kind}_id = {table}.id) AS tags
+    FROM {table}
+    LEFT JOIN {kind}_notes ON {kind}_notes.{kind}_id = {table}.id
+    WHERE user_id = $1 ORDER BY id");
+            let rows = sqlx::query(&q).bind(user_id).fetch_all(&mut *tx).await?;
+            entries.push(
+                rows.into_iter()
+                    .map(|row| BackupEntry {
+                        account_id: row.get("account_id"),
+                        category_id: row.get("category_id"),
+                        amount: row.get("amount"),
+                        occurred_at: row.get("occurred_at"),
+                        import_ref: row.get("import_ref"),
+                        note: note_from_row(&row),
+                    })
+                    .collect(),
+            );
+        }
+        let income = entries.pop().unwrap_or_default();
+        let expenses = entries.pop().unwrap_or_default();
+
+        let q = "SELECT from_account_id, to_account_id, amount, occurred_at FROM transfers WHERE user_id = $1 ORDER BY id";
+        let transfers = sqlx::query(q)
+            .bind(user_id)
+            .fetch_all(&mut *tx)
+            .await?
+            .into_iter()
+            .map(|row| BackupTransfer {
+                from_account_id: row.get("from_account_id"),
+                to_account_id: row.get("to_account_id"),
+                amount: row.get("amount"),
+                occurred_at: row.get("occurred_at"),
+            })
+            .collect();
+
+        let q = "SELECT income, account_id, category_id, amount, schedule, next_run FROM recurring WHERE user_id = $1 ORDER BY id";
+        let mut recurring = vec![];
+        for row in sqlx::query(q).bind(user_id).fetch_all(&mut *tx).await? {
+            let schedule: String = row.get("schedule");
+            recurring.push(BackupRecurring {
+                income: row.get("income"),
+                account_id: row.get("account_id"),
+                category_id: row.get("category_id"),
+                amount: row.get("amount"),
+                schedule: Schedule::from_column(&schedule)?,
+                next_run: row.get("next_run"),
+            });
+        }
+
+        let q = "SELECT base_currency, default_account_id FROM user_settings WHERE user_id = $1";
+        let settings = sqlx::query(q)
+            .bind(user_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+        let base_currency = settings
+            .as_ref()
+            .map(|row| row.get("base_currency"))
+            .unwrap_or_else(|| DEFAULT_CURRENCY.to_string());
+        let default_account_id = settings.and_then(|row| row.get("default_account_id"));
+
+        let q = "SELECT from_currency, to_currency, rate FROM exchange_rates WHERE user_id = $1 ORDER BY from_currency, to_currency";
+        let rates = sqlx::query(q)
+            .bind(user_id)
+            .fetch_all(&mut *tx)
+            .await?
+            .into_iter()
+            .map(|row| ExchangeRates {
+                from: row.get("from_currency"),
+                to: row.get("to_currency"),
+                rate: row.get("rate"),
+            })
+            .collect();
+
+        let q = "SELECT date_column, amount_column, category_column, description_column
+    FROM import_mappings WHERE user_id = $1";
+        let import_mapping = sqlx::query(q)
+            .bind(user_id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .map(|row| ColumnMapping {
+                date: row.get::<i64, _>("date_column") as usize,
+                amount: row.get::<i64, _>("amount_column") as usize,
+                category: row.get::<i64, _>("category_column") as usize,
+                description: row
+                    .get::<Option<i64>, _>("description_column")
+                    .map(|c| c as usize),
+            });
+
+        let q =
+            "SELECT alias, category_id, account_id FROM aliases WHERE user_id = $1 ORDER BY alias";
+        let aliases = sqlx::query(q)
+            .bind(user_id)
+            .fetch_all(&mut *tx)
+            .await?
+            .into_iter()
+            .map(|row| BackupAlias {
+                alias: row.get("alias"),
+                category_id: row.get("category_id"),
+                account_id: row.get("account_id"),
+            })
+            .collect();
+
+        let q = "SELECT pattern, category_id FROM payee_rules WHERE user_id = $1 ORDER BY pattern";
+        let payee_rules = sqlx::query(q)
+            .bind(user_id)
+            .fetch_all(&mut *tx)
+            .await?
+            .into_iter()
+            .map(|row| BackupPayeeRule {
+                pattern: row.get("pattern"),
+                category_id: row.get("category_id"),
+            })
+            .collect();
+
+        tx.commit().await?;
+
+        Ok(Backup {
+            version: BACKUP_VERSION,
+            created_at: Local::now(),
+            accounts,
+            categories,
+            expenses,
+            income,
+            transfers,
+            recurring,
+            settings: BackupSettings {
+                base_currency,
+                rates,
+                import_mapping,
+                payee_rules,
+                default_account_id,
+                aliases,
+            },
+        })
+    }
+
+    async fn restore(&self, user_id: i64, backup: Backup) -> Result<(), FinanceError> {
+        backup.validate()?;
+        let mut tx = self.pool.begin().await?;
+
+        // Rows referring to others go first.
+        for table in [
+            "recurring",
+            "aliases",
+            "payee_rules",
+            "expenses",
+            "income",
+            "transfers",
+            "categories",
+            "accounts",
+            "exchange_rates",
+            "user_settings",
+            "import_mappings",
+        ] {
+            let q = format!("DELETE FROM {table} WHERE user_id = $1");
+            sqlx::query(&q).bind(user_id).execute(&mut *tx).await?;
+        }
+
+        let mut account_ids = HashMap::new();
+        let q = "INSERT INTO accounts (name, balance, user_id, currency) VALUES ($1, $2, $3, $4) RETURNING id";
+        for acc in &backup.accounts {
+            let id: i64 = sqlx::query(q)
+                .bind(&acc.name)
+                .bind(acc.balance)
+                .bind(user_id)
+                .bind(&acc.currency)
+                .fetch_one(&mut *tx)
+                .await?
+                .get("id");
+            account_ids.insert(acc.id, id);
+        }
+
+        let mut category_ids = HashMap::new();
+        let q = "INSERT INTO categories (name, description, user_id, budget_limit, budget_period) VALUES ($1, $2, $3, $4, $5) RETURNING id";
+        for cat in &backup.categories {
+            let id: i64 = sqlx::query(q)
+                .bind(&cat.name)
+                .bind(&cat.description)
+                .bind(user_id)
+                .bind(cat.budget.map(|budget| budget.limit))
+                .bind(cat.budget.map(|budget| budget.period.as_str()))
+                .fetch_one(&mut *tx)
+                .await?
+                .get("id");
+            category_ids.insert(cat.id, id);
+        }
+
+        // `validate` made sure every referred id is in the maps.
+        for (income, entries) in [(false, &backup.expenses), (true, &backup.income)] {
+            let table = if income { "income" } else { "expenses" };
+            let q = format!("INSERT INTO {table} (account_id, category_id, amount, user_id, occurred_at, import_ref, fingerprint) VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING id");
+            for entry in entries {
+                let account_id = account_ids[&entry.account_id];
+                let id = sqlx::query(&q)
+                    .bind(account_id)
+                    .bind(category_ids[&entry.category_id])
+                    .bind(entry.amount)
+                    .bind(user_id)
+                    .bind(entry.occurred_at)
+                    .bind(&entry.import_ref)
+                    .bind(fingerprint(account_id, &entry.new_entry()))
+                    .fetch_one(&mut *tx)
+                    .await?
+                    .get("id");
+                insert_note(&mut tx, income, id, &entry.note).await?;
+            }
+        }
+
+        let q = "INSERT INTO transfers (from_account_id, to_account_id, amount, user_id, occurred_at) VALUES ($1, $2, $3, $4, $5)";
+        for transfer in &backup.transfers {
+            sqlx::query(q)
+                .bind(account_ids[&transfer.from_account_id])
+                .bind(account_ids[&transfer.to_account_id])
+                .bind(transfer.amount)
+                .bind(user_id)
+                .bind(transfer.occurred_at)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        let q = "INSERT INTO recurring (user_id, income, amount, category_id, account_id, schedule, next_run)
+    VALUES ($1, $2, $3, $4, $5, $6, $7)";
+        for recurring in &backup.recurring {
+            sqlx::query(q)
+                .bind(user_id)
+                .bind(recurring.income)
+                .bind(recurring.amount)
+                .bind(category_ids[&recurring.category_id])
+                .bind(account_ids[&recurring.account_id])
+                .bind(recurring.schedule.to_column())
+                .bind(recurring.next_run)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        let settings = &backup.settings;
+        let q = "INSERT INTO user_settings (user_id, base_currency, default_account_id) VALUES ($1, $2, $3)";
+        sqlx::query(q)
+            .bind(user_id)
+            .bind(&settings.base_currency)
+            .bind(settings.default_account_id.map(|id| account_ids[&id]))
+            .execute(&mut *tx)
+            .await?;
+
+        let q = "INSERT INTO exchange_rates (user_id, from_currency, to_currency, rate) VALUES ($1, $2, $3, $4)
+    ON CONFLICT (user_id, from_currency, to_currency) DO UPDATE SET rate = EXCLUDED.rate";
+        for rate in &settings.rates {
+            sqlx::query(q)
+                .bind(user_id)
+                .bind(&rate.from)
+                .bind(&rate.to)
+                .bind(rate.rate)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        if let Some(mapping) = settings.import_mapping {
+            let q = "INSERT INTO import_mappings (user_id, date_column, amount_column, category_column, description_column)
+    VALUES ($1, $2, $3, $4, $5)";
+            sqlx::query(q)
+                .bind(user_id)
+                .bind(mapping.date as i64)
+                .bind(mapping.amount as i64)
+                .bind(mapping.category as i64)
+                .bind(mapping.description.map(|c| c as i64))
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        let q = "INSERT INTO payee_rules (user_id, pattern, category_id) VALUES ($1, $2, $3)
+    ON CONFLICT (user_id, pattern) DO UPDATE SET category_id = EXCLUDED.category_id";
+        for rule in &settings.payee_rules {
+            sqlx::query(q)
+                .bind(user_id)
+                .bind(rule.pattern.to_lowercase())
+                .bind(category_ids[&rule.category_id])
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        let q =
+            "INSERT INTO aliases (user_id, alias, category_id, account_id) VALUES ($1, $2, $3, $4)";
+        for alias in &settings.aliases {
+            sqlx::query(q)
+                .bind(user_id)
+                .bind(alias.alias.to_lowercase())
+                .bind(alias.category_id.map(|id| category_ids[&id]))
+                .bind(alias.account_id.map(|id| account_ids[&id]))
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn del_expense(&self, user_id: i64, id: i64) -> Result<(), FinanceError> {
+        let mut tx = self.pool.begin().await?;
+
+        let q = "DELETE FROM expenses WHERE id = $1 AND user_id = $2 RETURNING account_id, amount";
+        let row = sqlx::query(q)
+            .bind(id)
+            .bind(user_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+        let Some(row) = row else {
+            return Err(missing_row(&mut *tx, "expenses", id).await);
+        };
+        let acc_id: i64 = row.get("account_id");
+        let amount: Money = row.get("amount");
+
+        let set_balance_q = "UPDATE accounts SET balance = balance + $1 WHERE id = $2 ";
+        sqlx::query(set_balance_q)
+            .bind(amount)
+            .bind(acc_id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn get_transfers_page(
+        &self,
+        user_id: i64,
+        period: Period,
+        page: Option<Page>,
+    ) -> Result<Paged<Transfers>, FinanceError> {
+        let (from, to) = period.timestamps();
+        let q = "SELECT transfers.id, from_acc.name AS from_name, to_acc.name AS to_name, transfers.amount, transfers.user_id, transfers.occurred_at, COUNT(*) OVER () AS total
+    FROM transfers
+    JOIN accounts AS from_acc ON transfers.from_account_id = from_acc.id
+    JOIN accounts AS to_acc ON transfers.to_account_id = to_acc.id
+    WHERE transfers.user_id = $1
+    AND ($2::timestamptz IS NULL OR transfers.occurred_at >= $2)
+    AND ($3::timestamptz IS NULL OR transfers.occurred_at < $3)
+    ORDER BY transfers.occurred_at, transfers.id
+    LIMIT $4 OFFSET $5";
+        let query = sqlx::query(q)
+            .bind(user_id)
+            .bind(from)
+            .bind(to)
+            .bind(page.map(|p| p.size))
+            .bind(page.map_or(0, |p| p.offset()));
+        let mut rows = query.fetch(&self.pool);
+
+        let mut transfers = vec![];
+        let mut total = 0;
+
+        while let Some(row) = rows.try_next().await? {
+            total = row.get("total");
+            transfers.push(Transfers {
+                id: row.get("id"),
+                from_account: row.get("from_name"),
+                to_account: row.get("to_name"),
+                amount: row.get("amount"),
+                user_id: row.get("user_id"),
+                occurred_at: row.get("occurred_at"),
+            });
+        }
+
+        Ok(Paged {
+            items: transfers,
+            total,
+        })
+    }
+
+    async fn add_transfer(
+        &self,
+        user_id: i64,
+        amount: Money,
+        from: String,
+        to: String,
+    ) -> Result<(), FinanceError> {
+        validate_amount(amount)?;
+
+        let mut tx = self.pool.begin().await?;
+
+        let from_id = account_id(&mut *tx, user_id, &from).await?;
+        let to_id = account_id(&mut *tx, user_id, &to).await?;
+        if from_id == to_id {
+            return Err(FinanceError::Validation(
+                "нельзя перевести деньги на тот же аккаунт".to_string(),
+            ));
+        }
+
+        // Both rows are locked in id order, so opposite transfers running at the
+        // same time wait for each other instead of deadlocking.
+        let lock_q = "SELECT currency FROM accounts WHERE id IN ($1, $2) ORDER BY id FOR UPDATE";
+        let currencies: Vec<String> = sqlx::query_scalar(lock_q)
+            .bind(from_id)
+            .bind(to_id)
+            .fetch_all(&mut *tx)
+            .await?;
+        validate_transfer_currency(&currencies[0], &currencies[1])?;
+
+        let set_balance_q = "UPDATE accounts SET balance = balance + CASE WHEN id = $2 THEN -$1 ELSE $1 END WHERE id IN ($2, $3)";
+        sqlx::query(set_balance_q)
+            .bind(amount)
+            .bind(from_id)
+            .bind(to_id)
+            .execute(&mut *tx)
+            .await?;
+
+        let query = "INSERT INTO transfers (from_account_id, to_account_id, amount, user_id) VALUES ($1, $2, $3, $4)";
+        sqlx::query(query)
+            .bind(from_id)
+            .bind(to_id)
+            .bind(amount)
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn del_transfer(&self, user_id: i64, id: i64) -> Result<(), FinanceError> {
+        let mut tx = self.pool.begin().await?;
+
+        let q = "DELETE FROM transfers WHERE id = $1 AND user_id = $2 RETURNING from_account_id, to_account_id, amount";
+        let row = sqlx::query(q)
+            .bind(id)
+            .bind(user_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+        let Some(row) = row else {
+            return Err(missing_row(&mut *tx, "transfers", id).await);
+        };
+        let from_id: i64 = row.get("from_account_id");
+        let to_id: i64 = row.get("to_account_id");
+        let amount: Money = row.get("amount");
+
+        let set_balance_q = "UPDATE accounts SET balance = balance + CASE WHEN id = $2 THEN $1 ELSE -$1 END WHERE id IN ($2, $3)";
+        sqlx::query(set_balance_q)
+            .bind(amount)
+            .bind(from_id)
+            .bind(to_id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn get_rates(&self, user_id: i64) -> Result<Vec<ExchangeRates>, FinanceError> {
+        let q = "SELECT from_currency, to_currency, rate FROM exchange_rates WHERE user_id = $1 ORDER BY from_currency, to_currency";
+        let query = sqlx::query(q).bind(user_id);
+        let mut rows = query.fetch(&self.pool);
+
+        let mut rates = vec![];
+
+        while let Some(row) = rows.try_next().await? {
+            rates.push(ExchangeRates {
+                from: row.get("from_currency"),
+                to: row.get("to_currency"),
+                rate: row.get("rate"),
+            });
+        }
+
+        Ok(rates)
+    }
+
+    async fn set_rates(&self, user_id: i64, rates: &[ExchangeRates]) -> Result<(), FinanceError> {
+        let mut tx = self.pool.begin().await?;
+
+        let q = "INSERT INTO exchange_rates (user_id, from_currency, to_currency, rate) VALUES ($1, $2, $3, $4)
+    ON CONFLICT (user_id, from_currency, to_currency) DO UPDATE SET rate = EXCLUDED.rate";
+        for rate in rates {
+            sqlx::query(q)
+                .bind(user_id)
+                .bind(&rate.from)
+                .bind(&rate.to)
+                .bind(rate.rate)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn get_base_currency(&self, user_id: i64) -> Result<String, FinanceError> {
+        let q = "SELECT base_currency FROM user_settings WHERE user_id = $1";
+        let row = sqlx::query(q)
+            .bind(user_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row
+            .map(|row| row.get("base_currency"))
+            .unwrap_or_else(|| DEFAULT_CURRENCY.to_string()))
+    }
+
+    async fn set_base_currency(&self, user_id: i64, currency: String) -> Result<(), FinanceError> {
+        let q = "INSERT INTO user_settings (user_id, base_currency) VALUES ($1, $2)
+    ON CONFLICT (user_id) DO UPDATE SET base_currency = EXCLUDED.base_currency";
+        sqlx::query(q)
+            .bind(user_id)
+            .bind(currency)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn get_default_account(&self, user_id: i64) -> Result<Option<String>, FinanceError> {
+        let q = "SELECT accounts.name FROM user_settings
+    JOIN accounts ON user_settings.default_account_id = accounts.id
+    WHERE user_settings.user_id = $1";
+        let row = sqlx::query(q)
+            .bind(user_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.map(|row| row.get("name")))
+    }
+
+    async fn set_default_account(&self, user_id: i64, account: String) -> Result<(), FinanceError> {
+        let account_id = account_id(&self.pool, user_id, &account).await?;
+
+        let q = "INSERT INTO user_settings (user_id, default_account_id) VALUES ($1, $2)
+    ON CONFLICT (user_id) DO UPDATE SET default_account_id = EXCLUDED.default_account_id";
+        sqlx::query(q)
+            .bind(user_id)
+            .bind(account_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn set_budget(
+        &self,
+        user_id: i64,
+        category: String,
+        limit: Money,
+        period: BudgetPeriod,
+    ) -> Result<(), FinanceError> {
+        validate_amount(limit)?;
+        let category_id = category_id(&self.pool, user_id, &category).await?;
+        let q = "UPDATE categories SET budget_limit = $1, budget_period = $2 WHERE id = $3";
+        sqlx::query(q)
+            .bind(limit)
+            .bind(period.as_str())
+            .bind(category_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn get_budget_sums(
+        &self,
+        user_id: i64,
+        category: Option<String>,
+    ) -> Result<Vec<BudgetSums>, FinanceError> {
+        // A budget asked for by a name that matches no category is none.
+        let category_id = match category {
+            Some(name) => match category_id(&self.pool, user_id, &name).await {
+                Ok(id) => Some(id),
+                Err(FinanceError::UnknownCategory(_)) => return Ok(vec![]),
+                Err(e) => return Err(e),
+            },
+            None => None,
+        };
+        let (week_from, week_to) = Period::Week.timestamps();
+        let (month_from, month_to) = Period::Month.timestamps();
+        let q = "SELECT categories.id, categories.name, categories.budget_limit, categories.budget_period, accounts.currency, COALESCE(SUM(expenses.amount), 0)::BIGINT AS spent
+    FROM categories
+    LEFT JOIN expenses ON expenses.category_id = categories.id
+    AND expenses.occurred_at >= CASE WHEN categories.budget_period = 'week' THEN $2 ELSE $4 END
+    AND expenses.occurred_at < CASE WHEN categories.budget_period = 'week' THEN $3 ELSE $5 END
+    LEFT JOIN accounts ON expenses.account_id = accounts.id
+    WHERE categories.user_id = $1
+    AND categories.budget_limit IS NOT NULL
+    AND ($6::bigint IS NULL OR categories.id = $6)
+    GROUP BY categories.id, accounts.currency
+    ORDER BY categories.name, categories.id, accounts.currency";
+        let query = sqlx::query(q)
+            .bind(user_id)
+            .bind(week_from)
+            .bind(week_to)
+            .bind(month_from)
+            .bind(month_to)
+            .bind(category_id);
+        let mut rows = query.fetch(&self.pool);
+
+        let mut budgets: Vec<BudgetSums> = vec![];
+        let mut last_id = None;
+
+        // A category spent from accounts in several currencies comes in a row
+        // per currency, and one without expenses in a row without a currency.
+        while let Some(row) = rows.try_next().await? {
+            let id: i64 = row.get("id");
+            if last_id != Some(id) {
+                last_id = Some(id);
+                let period: String = row.get("budget_period");
+                budgets.push(BudgetSums {
+                    category: row.get("name"),
+                    limit: row.get("budget_limit"),
+                    period: period.parse().unwrap_or_default(),
+                    spent: vec![],
+                });
+            }
+            if let Some(currency) = row.get::<Option<String>, _>("currency") {
+                let budget = budgets.last_mut().expect("pushed above");
+                budget.spent.push((currency, row.get("spent")));
+            }
+        }
+
+        Ok(budgets)
+    }
+
+    async fn get_report_sums(
+        &self,
+        user_id: i64,
+        period: Period,
+    ) -> Result<ReportSums, FinanceError> {
+        let (from, to) = period.timestamps();
+        let mut sums = ReportSums::default();
+        for (table, totals) in [
+            ("income", &mut sums.income),
+            ("expenses", &mut sums.expenses),
+        ] {
+            let q = format!(
+                "SELECT accounts.currency, SUM({table}.amount)::BIGINT AS total
+    FROM {table}
+    JOIN accounts ON {table}.account_id = accounts.id
+    WHERE {table}.user_id = $1
+    AND ($2::timestamptz IS NULL OR {table}.occurred_at >= $2)
+    AND ($3::timestamptz IS NULL OR {table}.occurred_at < $3)
+    GROUP BY accounts.currency
+    ORDER BY accounts.currency"
+            );
+            let mut rows = sqlx::query(&q)
+                .bind(user_id)
+                .bind(from)
+                .bind(to)
+                .fetch(&self.pool);
+            while let Some(row) = rows.try_next().await? {
+                totals.push((row.get("currency"), row.get("total")));
+            }
+        }
+
+        let categories_q = "SELECT categories.name AS category_name, accounts.currency, SUM(expenses.amount)::BIGINT AS total
+    FROM expenses
+    JOIN categories ON expenses.category_id = categories.id
+    JOIN accounts ON expenses.account_id = accounts.id
+    WHERE expenses.user_id = $1
+    AND ($2::timestamptz IS NULL OR expenses.occurred_at >= $2)
+    AND ($3::timestamptz IS NULL OR expenses.occurred_at < $3)
+    GROUP BY categories.name, accounts.currency
+    ORDER BY categories.name, accounts.currency";
+        let query = sqlx::query(categories_q).bind(user_id).bind(from).bind(to);
+        let mut rows = query.fetch(&self.pool);
+
+        while let Some(row) = rows.try_next().await? {
+            sums.categories.push((
+                row.get("category_name"),
+                row.get("currency"),
+                row.get("total"),
+            ));
+        }
+
+        Ok(sums)
+    }
+
+    fn ledger(
+        &self,
+        user_id: i64,
+        period: Period,
+    ) -> BoxStream<'_, Result<LedgerEntry, FinanceError>> {
+        let (from, to) = period.timestamps();
+        let q = "SELECT 'expense' AS kind, expenses.id AS id, expenses.occurred_at AS occurred_at, accounts.name AS account_name, categories.name AS category_name, expenses.amount, accounts.currency, expense_notes.note,
+        (SELECT string_agg(tag, ' ' ORDER BY tag) FROM expense_tags WHERE expense_id = expenses.id) AS tags
+    FROM expenses
+    JOIN accounts ON expenses.account_id = accounts.id
+    JOIN categories ON expenses.category_id = categories.id
+    LEFT JOIN expense_notes ON expense_notes.expense_id = expenses.id
+    WHERE expenses.user_id = $1
+    AND ($2::timestamptz IS NULL OR expenses.occurred_at >= $2)
+    AND ($3::timestamptz IS NULL OR expenses.occurred_at < $3)
+    UNION ALL
+    SELECT 'income', income.id, income.occurred_at, accounts.name, categories.name, income.amount, accounts.currency, income_notes.note,
+        (SELECT string_agg(tag, ' ' ORDER BY tag) FROM income_tags WHERE income_id = income.id)
+    FROM income
+    JOIN accounts ON income.account_id = accounts.id
+    JOIN categories ON income.category_id = categories.id
+    LEFT JOIN income_notes ON income_notes.income_id = income.id
+    WHERE income.user_id = $1
+    AND ($2::timestamptz IS NULL OR income.occurred_at >= $2)
+    AND ($3::timestamptz IS NULL OR income.occurred_at < $3)
+    UNION ALL
+    SELECT 'transfer', transfers.id, transfers.occurred_at, from_acc.name, to_acc.name, transfers.amount, from_acc.currency, NULL, NULL
+    FROM transfers
+    JOIN accounts from_acc ON transfers.from_account_id = from_acc.id
+    JOIN accounts to_acc ON transfers.to_account_id = to_acc.id
+    WHERE transfers.user_id = $1
+    AND ($2::timestamptz IS NULL OR transfers.occurred_at >= $2)
+    AND ($3::timestamptz IS NULL OR transfers.occurred_at < $3)
+    ORDER BY occurred_at, kind, id";
+        sqlx::query(q)
+            .bind(user_id)
+            .bind(from)
+            .bind(to)
+            .fetch(&self.pool)
+            .map_ok(|row| LedgerEntry {
+                occurred_at: row.get("occurred_at"),
+                kind: EntryKind::from_column(row.get("kind")),
+                account: row.get("account_name"),
+                category: row.get("category_name"),
+                amount: row.get("amount"),
+                currency: row.get("currency"),
+                note: note_from_row(&row).to_string(),
+            })
+            .map_err(FinanceError::from)
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::logic::store::tests::{balance, setup_user, test_store};
+
+    #[tokio::test]
+    async fn concurrent_ledger_changes_keep_balance_consistent() {
+        let Some(store) = test_store().await else {
+            return;
+        };
+        let user_id = setup_user(&store, 1000).await;
+
+        let mut tasks = vec![];
+        for i in 0..50 {
+            let store = store.clone();
+            tasks.push(tokio::spawn(async move {
+                let res = if i % 2 == 0 {
+                    store
+                        .add_expense(user_id, Money(3), "food".into(), "card".into(), None)
+                        .await
+                } else {
+                    store
+                        .add_income(user_id, Money(5), "food".into(), "card".into(), None)
+                        .await
+                };
+                res.map_err(|e| e.to_string())
+            }));
+        }
+        for task in futures::future::join_all(tasks).await {
+            task.unwrap().unwrap();
+        }
+
+        assert_eq!(balance(&store, user_id).await, 1000 - 25 * 3 + 25 * 5);
+
+        let expenses = store.get_expense(user_id, Period::All).await.unwrap();
+        let income = store.get_income(user_id, Period::All).await.unwrap();
+        let mut tasks = vec![];
+        for exp in expenses {
+            let store = store.clone();
+            tasks.push(tokio::spawn(async move {
+                store
+                    .del_expense(user_id, exp.id)
+                    .await
+                    .map_err(|e| e.to_string())
+            }));
+        }
+        for inc in income {
+            let store = store.clone();
+            tasks.push(tokio::spawn(async move {
+                store
+                    .del_income(user_id, inc.id)
+                    .await
+                    .map_err(|e| e.to_string())
+            }));
+        }
+        for task in futures::future::join_all(tasks).await {
+            task.unwrap().unwrap();
+        }
+
+        assert_eq!(balance(&store, user_id).await, 1000);
+    }
+
+    #[tokio::test]
+    async fn concurrent_delete_of_same_expense_reverts_once() {
+        let Some(store) = test_store().await else {
+            return;
+        };
+        let user_id = setup_user(&store, 100).await;
+
+        store
+            .add_expense(user_id, Money(40), "food".into(), "card".into(), None)
+            .await
+            .unwrap();
+        let id = store.get_expense(user_id, Period::All).await.unwrap()[0].id;
+
+        let tasks: Vec<_> = (0..10)
+            .map(|_| {
+                let store = store.clone();
+                tokio::spawn(async move {
+                    store
+                        .del_expense(user_id, id)
+                        .await
+                        .map_err(|e| e.to_string())
+                })
+            })
+            .collect();
+        let deleted = futures::future::join_all(tasks)
+            .await
+            .into_iter()
+            .filter(|res| res.as_ref().unwrap().is_ok())
+            .count();
+
+        assert_eq!(deleted, 1);
+        assert_eq!(balance(&store, user_id).await, 100);
+    }
+}
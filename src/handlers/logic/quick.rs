@@ -0,0 +1,94 @@
+use super::Money;
+
+/// Words before the account at the end of a quick entry: `кафе 200 с tinkoff`.
+const ACCOUNT_MARKERS: [&str; 4] = ["с", "со", "на", "from"];
+
+/// Expense or income typed as a plain message instead of a command.
+#[derive(Debug, PartialEq, Eq)]
+pub struct QuickEntry {
+    pub income: bool,
+    pub amount: Money,
+    pub category: String,
+    /// `None` when the message names no account and the default one is used.
+    pub account: Option<String>,
+}
+
+/// Reads messages like `кафе 200`, `+50000 зарплата` or `200 такси с tinkoff`:
+/// one positive amount, `+` for income, the category around it and optionally
+/// the account after `с`.
+pub fn parse_quick_entry(text: &str) -> Option<QuickEntry> {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let is_amount = |word: &str| !word.starts_with('-') && word.parse::<Money>().is_ok();
+    let amount_at = words.iter().position(|word| is_amount(word))?;
+    if words[amount_at + 1..].iter().any(|word| is_amount(word)) {
+        return None;
+    }
+
+    let marker_at = words
+        .iter()
+        .rposition(|word| ACCOUNT_MARKERS.contains(&word.to_lowercase().as_str()))
+        .filter(|&at| at > amount_at && at + 1 < words.len());
+    let (words, account) = match marker_at {
+        Some(at) => (&words[..at], Some(words[at + 1..].join(" "))),
+        None => (&words[..], None),
+    };
+
+    let amount: Money = words[amount_at].parse().ok()?;
+    if amount <= Money::ZERO {
+        return None;
+    }
+    let category = [&words[..amount_at], &words[amount_at + 1..]]
+        .concat()
+        .join(" ");
+    if category.is_empty() {
+        return None;
+    }
+
+    Some(QuickEntry {
+        income: words[amount_at].starts_with('+'),
+        amount,
+        category,
+        account,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(income: bool, amount: i64, category: &str, account: Option<&str>) -> QuickEntry {
+        QuickEntry {
+            income,
+            amount: Money(amount),
+            category: category.to_string(),
+            account: account.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn parses_amount_category_and_account() {
+        assert_eq!(
+            parse_quick_entry("кафе 200"),
+            Some(entry(false, 20000, "кафе", None))
+        );
+        assert_eq!(
+            parse_quick_entry("+50000 зарплата"),
+            Some(entry(true, 5000000, "зарплата", None))
+        );
+        assert_eq!(
+            parse_quick_entry("200 такси с tinkoff"),
+            Some(entry(false, 20000, "такси", Some("tinkoff")))
+        );
+        assert_eq!(
+            parse_quick_entry("кафе 350,50 со Сбер карта"),
+            Some(entry(false, 35050, "кафе", Some("Сбер карта")))
+        );
+    }
+
+    #[test]
+    fn ignores_other_messages() {
+        for text in ["привет", "200", "кафе -200", "кафе 0", "кафе 200 300", ""] {
+            assert_eq!(parse_quick_entry(text), None, "{text}");
+        }
+    }
+}
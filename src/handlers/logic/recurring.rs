@@ -0,0 +1,261 @@
+use super::{Money, NewEntry, Note};
+use chrono::{Datelike, Duration, NaiveDate, Weekday};
+use std::fmt;
+use std::str::FromStr;
+
+/// When a recurring operation is booked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub enum Schedule {
+    /// Every month on the day, on the last day of the shorter months.
+    Monthly(u32),
+    Weekly(Weekday),
+}
+
+const WEEKDAYS: [(Weekday, &str, &str); 7] = [
+    (Weekday::Mon, "пн", "понедельник"),
+    (Weekday::Tue, "вт", "вторник"),
+    (Weekday::Wed, "ср", "среда"),
+    (Weekday::Thu, "чт", "четверг"),
+    (Weekday::Fri, "пт", "пятница"),
+    (Weekday::Sat, "сб", "суббота"),
+    (Weekday::Sun, "вс", "воскресенье"),
+];
+
+fn last_day_of_month(year: i32, month: u32) -> u32 {
+    let (year, month) = if month == 12 {
+        (year + 1, 1)
+    } else {
+        (year, month + 1)
+    };
+    NaiveDate::from_ymd_opt(year, month, 1)
+        .and_then(|first| first.pred_opt())
+        .map_or(28, |last| last.day())
+}
+
+impl Schedule {
+    /// The first day of the schedule not earlier than `date`.
+    pub fn next_on_or_after(&self, date: NaiveDate) -> NaiveDate {
+        match *self {
+            Schedule::Monthly(day) => {
+                let in_month = |year: i32, month: u32| {
+                    let day = day.min(last_day_of_month(year, month));
+                    NaiveDate::from_ymd_opt(year, month, day).unwrap_or(date)
+                };
+                let this_month = in_month(date.year(), date.month());
+                if this_month >= date {
+                    return this_month;
+                }
+                if date.month() == 12 {
+                    in_month(date.year() + 1, 1)
+                } else {
+                    in_month(date.year(), date.month() + 1)
+                }
+            }
+            Schedule::Weekly(weekday) => {
+                let ahead = (7 + weekday.num_days_from_monday()
+                    - date.weekday().num_days_from_monday())
+                    % 7;
+                date + Duration::days(ahead as i64)
+            }
+        }
+    }
+
+    /// The day of the schedule following `date`.
+    pub fn next_after(&self, date: NaiveDate) -> NaiveDate {
+        self.next_on_or_after(date + Duration::days(1))
+    }
+
+    /// Text stored in the `schedule` column: `monthly:5` or `weekly:mon`.
+    pub fn to_column(&self) -> String {
+        match self {
+            Schedule::Monthly(day) => format!("monthly:{day}"),
+            Schedule::Weekly(weekday) => format!("weekly:{}", weekday.to_string().to_lowercase()),
+        }
+    }
+
+    pub(crate) fn from_column(schedule: &str) -> Result<Self, ScheduleParseError> {
+        schedule.replacen(':', " ", 1).parse()
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Schedule::Monthly(day) => write!(f, "ежемесячно {day} числа"),
+            Schedule::Weekly(weekday) => {
+                let name = WEEKDAYS
+                    .iter()
+                    .find(|(day, _, _)| day == weekday)
+                    .map_or("", |(_, _, name)| name);
+                write!(f, "еженедельно, {name}")
+            }
+        }
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = ScheduleParseError;
+
+    /// Reads `monthly <day>` or `weekly <weekday>`, in English or Russian.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || ScheduleParseError(s.trim().to_string());
+        let s = s.trim().to_lowercase();
+        let (every, on) = s.split_once(char::is_whitespace).ok_or_else(error)?;
+        let on = on.trim();
+        match every {
+            "monthly" | "ежемесячно" => match on.parse() {
+                Ok(day @ 1..=31) => Ok(Schedule::Monthly(day)),
+                _ => Err(error()),
+            },
+            "weekly" | "еженедельно" => {
+                let weekday = WEEKDAYS
+                    .iter()
+                    .find(|(_, short, long)| on == *short || on == *long)
+                    .map(|(weekday, _, _)| *weekday);
+                weekday
+                    .or_else(|| on.parse().ok())
+                    .map(Schedule::Weekly)
+                    .ok_or_else(error)
+            }
+            _ => Err(error()),
+        }
+    }
+}
+
+impl From<Schedule> for String {
+    fn from(schedule: Schedule) -> Self {
+        schedule.to_column()
+    }
+}
+
+impl TryFrom<String> for Schedule {
+    type Error = ScheduleParseError;
+
+    fn try_from(schedule: String) -> Result<Self, Self::Error> {
+        Schedule::from_column(&schedule)
+    }
+}
+
+#[derive(Debug)]
+pub struct ScheduleParseError(String);
+
+impl fmt::Display for ScheduleParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "неверное расписание \"{}\", используйте monthly <день месяца> или weekly <день недели>",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for ScheduleParseError {}
+
+/// Expense or income booked by the scheduler.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Recurring {
+    pub id: i64,
+    pub user_id: i64,
+    pub income: bool,
+    pub amount: Money,
+    pub category: String,
+    pub account: String,
+    pub schedule: Schedule,
+    /// The day of the next operation to book, in the past after downtime.
+    pub next_run: NaiveDate,
+}
+
+impl Recurring {
+    /// The expense or income booked on `run`.
+    pub fn entry(&self, run: NaiveDate) -> NewEntry {
+        NewEntry {
+            amount: self.amount,
+            category: self.category.clone(),
+            account: self.account.clone(),
+            date: Some(run),
+            import_ref: None,
+            note: Note::default(),
+        }
+    }
+}
+
+/// Recurring operation to add with `/addrecurring`.
+pub struct NewRecurring {
+    pub income: bool,
+    pub amount: Money,
+    pub category: String,
+    pub account: String,
+    pub schedule: Schedule,
+    pub next_run: NaiveDate,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn parses_schedules() {
+        assert_eq!(
+            "monthly 5".parse::<Schedule>().unwrap(),
+            Schedule::Monthly(5)
+        );
+        assert_eq!(
+            "Еженедельно пн".parse::<Schedule>().unwrap(),
+            Schedule::Weekly(Weekday::Mon)
+        );
+        assert_eq!(
+            "weekly friday".parse::<Schedule>().unwrap(),
+            Schedule::Weekly(Weekday::Fri)
+        );
+        assert!("monthly 32".parse::<Schedule>().is_err());
+        assert!("daily".parse::<Schedule>().is_err());
+
+        for schedule in [Schedule::Monthly(31), Schedule::Weekly(Weekday::Sun)] {
+            let column = schedule.to_column();
+            assert_eq!(
+                Schedule::from_column(&column).unwrap(),
+                schedule,
+                "{column}"
+            );
+        }
+        assert_eq!(Schedule::Weekly(Weekday::Wed).to_column(), "weekly:wed");
+    }
+
+    #[test]
+    fn monthly_runs_stay_on_their_day() {
+        let schedule = Schedule::Monthly(31);
+        assert_eq!(
+            schedule.next_on_or_after(date(2026, 1, 31)),
+            date(2026, 1, 31)
+        );
+        assert_eq!(schedule.next_after(date(2026, 1, 31)), date(2026, 2, 28));
+        assert_eq!(schedule.next_after(date(2026, 2, 28)), date(2026, 3, 31));
+        assert_eq!(schedule.next_after(date(2026, 12, 31)), date(2027, 1, 31));
+
+        let schedule = Schedule::Monthly(5);
+        assert_eq!(
+            schedule.next_on_or_after(date(2026, 10, 6)),
+            date(2026, 11, 5)
+        );
+    }
+
+    #[test]
+    fn weekly_runs_fall_on_the_weekday() {
+        let schedule = Schedule::Weekly(Weekday::Mon);
+        // 2026-10-18 is a Sunday.
+        assert_eq!(
+            schedule.next_on_or_after(date(2026, 10, 18)),
+            date(2026, 10, 19)
+        );
+        assert_eq!(
+            schedule.next_on_or_after(date(2026, 10, 19)),
+            date(2026, 10, 19)
+        );
+        assert_eq!(schedule.next_after(date(2026, 10, 19)), date(2026, 10, 26));
+    }
+}
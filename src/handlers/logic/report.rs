@@ -0,0 +1,141 @@
+use super::{convert, totals, ExchangeRates, Money};
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+
+/// Number of expense categories listed in a report.
+pub const REPORT_TOP_CATEGORIES: usize = 5;
+
+/// Sums of a period in the currencies of the accounts they were booked to.
+#[derive(Debug, Default)]
+pub struct ReportSums {
+    /// Currency and sum.
+    pub income: Vec<(String, Money)>,
+    pub expenses: Vec<(String, Money)>,
+    /// Category, currency and sum.
+    pub categories: Vec<(String, String, Money)>,
+}
+
+/// Income and expenses of a period in the base currency.
+pub struct Report {
+    pub income: Money,
+    pub expenses: Money,
+    /// Biggest expense categories, largest first.
+    pub top_categories: Vec<(String, Money)>,
+    /// Currencies left out because no rate to the base is known.
+    pub missing_rates: Vec<String>,
+}
+
+impl Report {
+    /// Converts the sums to `base` the way /total converts balances.
+    pub fn new(sums: ReportSums, base: &str, rates: &[ExchangeRates]) -> Self {
+        let income = totals(sums.income, base, rates);
+        let expenses = totals(sums.expenses, base, rates);
+
+        let mut by_category: BTreeMap<String, Money> = BTreeMap::new();
+        for (category, currency, sum) in sums.categories {
+            if let Some(sum) = convert(sum, &currency, base, rates) {
+                *by_category.entry(category).or_insert(Money::ZERO) += sum;
+            }
+        }
+        let mut top_categories: Vec<(String, Money)> = by_category.into_iter().collect();
+        // Stable sort keeps equal totals in name order.
+        top_categories.sort_by_key(|(_, total)| Reverse(*total));
+        top_categories.truncate(REPORT_TOP_CATEGORIES);
+
+        let mut missing_rates = income.missing_rates;
+        missing_rates.extend(expenses.missing_rates);
+        missing_rates.sort();
+        missing_rates.dedup();
+
+        Report {
+            income: income.converted,
+            expenses: expenses.converted,
+            top_categories,
+            missing_rates,
+        }
+    }
+
+    pub fn net(&self) -> Money {
+        self.income - self.expenses
+    }
+
+    /// Share of all expenses spent in a category, in percent.
+    pub fn share(&self, amount: Money) -> i64 {
+        percent_of(amount, self.expenses)
+    }
+}
+
+/// Change from `previous` to `current` in percent, `None` when there was
+/// nothing to compare with.
+pub fn change_percent(current: Money, previous: Money) -> Option<i64> {
+    if previous.0 == 0 {
+        return None;
+    }
+    Some(percent_of(current - previous, Money(previous.0.abs())))
+}
+
+fn percent_of(part: Money, whole: Money) -> i64 {
+    if whole.0 == 0 {
+        return 0;
+    }
+    (part.0 as i128 * 100 / whole.0 as i128) as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn computes_net_and_shares() {
+        let report = Report {
+            income: Money(10000),
+            expenses: Money(4000),
+            top_categories: vec![("кафе".to_string(), Money(3000))],
+            missing_rates: vec![],
+        };
+        assert_eq!(report.net(), Money(6000));
+        assert_eq!(report.share(Money(3000)), 75);
+    }
+
+    #[test]
+    fn converts_sums_to_the_base_currency() {
+        let rates = [ExchangeRates {
+            from: "USD".to_string(),
+            to: "RUB".to_string(),
+            rate: 90.0,
+        }];
+        let sum = |currency: &str, sum| (currency.to_string(), Money(sum));
+        let category =
+            |name: &str, currency: &str, sum| (name.to_string(), currency.to_string(), Money(sum));
+        let sums = ReportSums {
+            income: vec![sum("RUB", 10000), sum("USD", 100), sum("EUR", 5)],
+            expenses: vec![sum("RUB", 3000), sum("USD", 50)],
+            categories: vec![
+                category("кафе", "RUB", 3000),
+                category("кафе", "USD", 10),
+                category("отель", "USD", 40),
+                category("такси", "EUR", 5),
+            ],
+        };
+
+        let report = Report::new(sums, "RUB", &rates);
+        assert_eq!(report.income, Money(19000));
+        assert_eq!(report.expenses, Money(7500));
+        assert_eq!(
+            report.top_categories,
+            [
+                ("кафе".to_string(), Money(3900)),
+                ("отель".to_string(), Money(3600))
+            ]
+        );
+        assert_eq!(report.missing_rates, ["EUR"]);
+    }
+
+    #[test]
+    fn computes_change() {
+        assert_eq!(change_percent(Money(150), Money(100)), Some(50));
+        assert_eq!(change_percent(Money(50), Money(100)), Some(-50));
+        assert_eq!(change_percent(Money(50), Money(-100)), Some(150));
+        assert_eq!(change_percent(Money(50), Money(0)), None);
+    }
+}
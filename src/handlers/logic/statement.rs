@@ -0,0 +1,602 @@
+use super::{parse_date, EntryKind, Money};
+use chrono::NaiveDate;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Columns of a statement set by `/importmapping`, counted from 0.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ColumnMapping {
+    pub date: usize,
+    pub amount: usize,
+    pub category: usize,
+    pub description: Option<usize>,
+}
+
+impl fmt::Display for ColumnMapping {
+    /// Writes the columns counted from 1, in the form accepted by `FromStr`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {}",
+            self.date + 1,
+            self.amount + 1,
+            self.category + 1
+        )?;
+        if let Some(description) = self.description {
+            write!(f, " {}", description + 1)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for ColumnMapping {
+    type Err = StatementError;
+
+    /// Parses `<date> <amount> <category> [description]` column numbers.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let columns = s
+            .split_whitespace()
+            .map(|c| c.parse::<usize>().ok().filter(|c| *c > 0).map(|c| c - 1))
+            .collect::<Option<Vec<_>>>()
+            .filter(|columns| (3..=4).contains(&columns.len()))
+            .ok_or_else(|| {
+                StatementError("укажите номера колонок даты, суммы, категории и описания".into())
+            })?;
+        Ok(ColumnMapping {
+            date: columns[0],
+            amount: columns[1],
+            category: columns[2],
+            description: columns.get(3).copied(),
+        })
+    }
+}
+
+/// Statement layout of a bank, recognised by the names in its header.
+pub struct BankLayout {
+    pub bank: &'static str,
+    date: &'static str,
+    amount: &'static str,
+    category: &'static str,
+    description: &'static str,
+    /// Column with the operation status and the value of completed operations.
+    status: Option<(&'static str, &'static str)>,
+    /// Whether amounts without a sign are expenses, income having a `+`.
+    unsigned_is_expense: bool,
+}
+
+pub const BANK_LAYOUTS: [BankLayout; 2] = [
+    BankLayout {
+        bank: "Тинькофф",
+        date: "Дата операции",
+        amount: "Сумма операции",
+        category: "Категория",
+        description: "Описание",
+        status: Some(("Статус", "OK")),
+        unsigned_is_expense: false,
+    },
+    BankLayout {
+        bank: "Сбербанк",
+        date: "Дата операции",
+        amount: "Сумма в валюте счёта",
+        category: "Категория",
+        description: "Описание",
+        status: None,
+        unsigned_is_expense: true,
+    },
+];
+
+/// Operation read from a statement.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StatementRow {
+    pub date: NaiveDate,
+    pub kind: EntryKind,
+    /// Always positive, `kind` tells the direction.
+    pub amount: Money,
+    /// Category as named by the bank.
+    pub category: String,
+    pub description: String,
+    /// Identifies the row among all imports of the user, so a statement
+    /// imported twice doesn't add its rows twice.
+    pub import_ref: String,
+}
+
+#[derive(Debug)]
+pub struct Statement {
+    /// Bank of the detected CSV layout or the name of the file format, `None`
+    /// when the saved mapping was used.
+    pub bank: Option<&'static str>,
+    pub rows: Vec<StatementRow>,
+}
+
+#[derive(Debug)]
+pub struct StatementError(String);
+
+impl fmt::Display for StatementError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for StatementError {}
+
+/// Sends operations of a payee to a category on import, whatever category
+/// the bank gave them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PayeeRule {
+    /// Lowercased part of the payee or description.
+    pub pattern: String,
+    pub category: String,
+}
+
+/// Category of the rule matching `payee`, the longest pattern winning over
+/// shorter ones that match too.
+pub fn match_payee<'a>(rules: &'a [PayeeRule], payee: &str) -> Option<&'a str> {
+    let payee = payee.to_lowercase();
+    rules
+        .iter()
+        .filter(|rule| payee.contains(&rule.pattern))
+        .max_by_key(|rule| rule.pattern.chars().count())
+        .map(|rule| rule.category.as_str())
+}
+
+/// Tells apart identical operations of one statement by numbering the repeats.
+fn unique_ref(seen: &mut HashMap<String, usize>, key: String) -> String {
+    let repeat = seen.entry(key.clone()).or_default();
+    *repeat += 1;
+    match *repeat {
+        1 => key,
+        n => format!("{key}#{n}"),
+    }
+}
+
+/// Text of an uploaded statement, banks still export in Windows-1251.
+pub fn decode_statement(bytes: &[u8]) -> String {
+    let bytes = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes);
+    match std::str::from_utf8(bytes) {
+        Ok(text) => text.to_string(),
+        Err(_) => encoding_rs::WINDOWS_1251.decode(bytes).0.into_owned(),
+    }
+}
+
+/// Reads the operations of a CSV statement with a header row, using a known
+/// bank layout or else the user's saved `mapping`.
+pub fn parse_statement(
+    text: &str,
+    mapping: Option<ColumnMapping>,
+) -> Result<Statement, StatementError> {
+    let header = text.lines().next().unwrap_or_default();
+    let delimiter = if header.matches(';').count() >= header.matches(',').count() {
+        b';'
+    } else {
+        b','
+    };
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .flexible(true)
+        .from_reader(text.as_bytes());
+    let headers = reader
+        .headers()
+        .map_err(|e| StatementError(format!("не удалось прочитать выписку: {e}")))?
+        .clone();
+    let column = |name: &str| headers.iter().position(|h| h.trim() == name);
+
+    let detected = BANK_LAYOUTS.iter().find_map(|layout| {
+        let mapping = ColumnMapping {
+            date: column(layout.date)?,
+            amount: column(layout.amount)?,
+            category: column(layout.category)?,
+            description: column(layout.description),
+        };
+        let status = match layout.status {
+            Some((name, ok)) => Some((column(name)?, ok)),
+            None => None,
+        };
+        Some((layout, mapping, status))
+    });
+    let (bank, mapping, status, unsigned_is_expense) = match (detected, mapping) {
+        (Some((layout, mapping, status)), _) => (
+            Some(layout.bank),
+            mapping,
+            status,
+            layout.unsigned_is_expense,
+        ),
+        (None, Some(mapping)) => (None, mapping, None, false),
+        (None, None) => {
+            return Err(StatementError(
+                "не удалось определить формат выписки, задайте колонки через /importmapping".into(),
+            ))
+        }
+    };
+
+    let mut rows = vec![];
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    for (index, record) in reader.records().enumerate() {
+        // The header is line 1.
+        let line = index + 2;
+        let record =
+            record.map_err(|e| StatementError(format!("строка {line}: ошибка чтения: {e}")))?;
+        let cell = |column: usize| record.get(column).unwrap_or_default().trim();
+        if record.iter().all(|cell| cell.trim().is_empty()) {
+            continue;
+        }
+        if status.is_some_and(|(column, ok)| cell(column) != ok) {
+            continue;
+        }
+
+        let date_text = cell(mapping.date);
+        let date = date_text
+            .split_whitespace()
+            .next()
+            .and_then(parse_date)
+            .ok_or_else(|| {
+                StatementError(format!("строка {line}: неверная дата \"{date_text}\""))
+            })?;
+
+        let amount_text: String = cell(mapping.amount)
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect();
+        let amount: Money = amount_text.parse().map_err(|_| {
+            StatementError(format!("строка {line}: неверная сумма \"{amount_text}\""))
+        })?;
+        let income = if unsigned_is_expense {
+            amount_text.starts_with('+')
+        } else {
+            amount > Money::ZERO
+        };
+        if amount == Money::ZERO {
+            continue;
+        }
+
+        let description = mapping.description.map(cell).unwrap_or_default();
+        let import_ref = unique_ref(
+            &mut seen,
+            format!("{date_text}|{amount_text}|{description}"),
+        );
+
+        rows.push(StatementRow {
+            date,
+            kind: if income {
+                EntryKind::Income
+            } else {
+                EntryKind::Expense
+            },
+            amount: Money(amount.0.abs()),
+            category: cell(mapping.category).to_string(),
+            description: description.to_string(),
+            import_ref,
+        });
+    }
+
+    Ok(Statement { bank, rows })
+}
+
+/// Reads an uploaded statement in OFX, QIF or CSV, telling them by content.
+pub fn read_statement(
+    bytes: &[u8],
+    mapping: Option<ColumnMapping>,
+) -> Result<Statement, StatementError> {
+    let text = decode_statement(bytes);
+    let start = text.trim_start();
+    if start.starts_with('!') {
+        parse_qif(&text)
+    } else if start.starts_with("OFXHEADER") || text.contains("<OFX>") {
+        parse_ofx(&text)
+    } else {
+        parse_statement(&text, mapping)
+    }
+}
+
+fn row(date: NaiveDate, amount: Money, description: &str, import_ref: String) -> StatementRow {
+    StatementRow {
+        date,
+        kind: if amount > Money::ZERO {
+            EntryKind::Income
+        } else {
+            EntryKind::Expense
+        },
+        amount: Money(amount.0.abs()),
+        category: String::new(),
+        description: description.to_string(),
+        import_ref,
+    }
+}
+
+/// Value of an OFX element, closed or not, as SGML 1.x files leave them.
+fn ofx_element(block: &str, name: &str) -> Option<String> {
+    let start = block.find(&format!("<{name}>"))? + name.len() + 2;
+    let value = block[start..].split(['<', '\r', '\n']).next()?.trim();
+    let value = value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&");
+    Some(value).filter(|value| !value.is_empty())
+}
+
+/// Reads the transactions of an OFX statement, SGML 1.x or XML 2.x. OFX has
+/// no categories, so they come from the payee rules.
+pub fn parse_ofx(text: &str) -> Result<Statement, StatementError> {
+    let mut rows = vec![];
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    for (index, block) in text.split("<STMTTRN>").skip(1).enumerate() {
+        let block = block.split("</STMTTRN>").next().unwrap_or_default();
+        let number = index + 1;
+        let posted = ofx_element(block, "DTPOSTED").unwrap_or_default();
+        let date = posted
+            .get(..8)
+            .and_then(|date| NaiveDate::parse_from_str(date, "%Y%m%d").ok())
+            .ok_or_else(|| {
+                StatementError(format!("операция {number}: неверная дата \"{posted}\""))
+            })?;
+        let amount_text = ofx_element(block, "TRNAMT").unwrap_or_default();
+        let amount: Money = amount_text.parse().map_err(|_| {
+            StatementError(format!(
+                "операция {number}: неверная сумма \"{amount_text}\""
+            ))
+        })?;
+        if amount == Money::ZERO {
+            continue;
+        }
+
+        let name = ofx_element(block, "NAME");
+        let memo = ofx_element(block, "MEMO");
+        let description = name.or(memo).unwrap_or_default();
+        // Banks keep FITID unique within an account.
+        let import_ref = match ofx_element(block, "FITID") {
+            Some(id) => format!("ofx:{id}"),
+            None => unique_ref(
+                &mut seen,
+                format!("ofx:{posted}|{amount_text}|{description}"),
+            ),
+        };
+        rows.push(row(date, amount, &description, import_ref));
+    }
+    Ok(Statement {
+        bank: Some("OFX"),
+        rows,
+    })
+}
+
+/// Dates of QIF files, which Quicken writes as `9/15'26` or `09/15/2026`.
+fn parse_qif_date(s: &str) -> Option<NaiveDate> {
+    let s = s.trim().replace('\'', "/").replace(' ', "");
+    // `%Y` would take a two-digit year for the year 26.
+    let format = match s.rsplit('/').next() {
+        Some(year) if year.len() == 2 => "%m/%d/%y",
+        _ => "%m/%d/%Y",
+    };
+    parse_date(&s).or_else(|| NaiveDate::parse_from_str(&s, format).ok())
+}
+
+/// Reads the transactions of the bank, cash and card sections of a QIF file,
+/// taking categories from their `L` lines.
+pub fn parse_qif(text: &str) -> Result<Statement, StatementError> {
+    const SECTIONS: [&str; 5] = ["Bank", "Cash", "CCard", "Oth A", "Oth L"];
+
+    let mut rows = vec![];
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    let mut in_section = false;
+    let mut fields: HashMap<char, &str> = HashMap::new();
+    for (index, line) in text.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.trim_end();
+        if let Some(header) = line.strip_prefix('!') {
+            in_section = header
+                .strip_prefix("Type:")
+                .is_some_and(|kind| SECTIONS.contains(&kind.trim()));
+            fields.clear();
+            continue;
+        }
+        let Some(code) = line.chars().next() else {
+            continue;
+        };
+        if code != '^' {
+            // Split lines repeat the codes of the transaction, the first wins.
+            fields.entry(code).or_insert(&line[code.len_utf8()..]);
+            continue;
+        }
+        let fields = std::mem::take(&mut fields);
+        if !in_section {
+            continue;
+        }
+
+        let date_text = fields.get(&'D').copied().unwrap_or_default();
+        let date = parse_qif_date(date_text).ok_or_else(|| {
+            StatementError(format!(
+                "строка {line_number}: неверная дата \"{date_text}\""
+            ))
+        })?;
+        let amount_text = fields.get(&'T').or(fields.get(&'U')).copied();
+        let amount_text = amount_text.unwrap_or_default().trim();
+        // Thousands are separated by commas when the fraction follows a dot.
+        let amount_text = if amount_text.contains('.') {
+            amount_text.replace(',', "")
+        } else {
+            amount_text.to_string()
+        };
+        let amount: Money = amount_text.parse().map_err(|_| {
+            StatementError(format!(
+                "строка {line_number}: неверная сумма \"{amount_text}\""
+            ))
+        })?;
+        if amount == Money::ZERO {
+            continue;
+        }
+
+        let payee = fields.get(&'P').or(fields.get(&'M')).copied();
+        let payee = payee.unwrap_or_default().trim();
+        let import_ref = unique_ref(
+            &mut seen,
+            format!("qif:{}|{amount_text}|{payee}", date_text.trim()),
+        );
+        let mut row = row(date, amount, payee, import_ref);
+        row.category = fields
+            .get(&'L')
+            .copied()
+            .unwrap_or_default()
+            .trim()
+            .to_string();
+        rows.push(row);
+    }
+
+    Ok(Statement {
+        bank: Some("QIF"),
+        rows,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_tinkoff_layout() {
+        let text = "\"Дата операции\";\"Дата платежа\";\"Номер карты\";\"Статус\";\"Сумма операции\";\"Валюта операции\";\"Категория\";\"Описание\"\n\
+            \"15.09.2026 12:30:00\";\"15.09.2026\";\"*1234\";\"OK\";\"-1 199,90\";\"RUB\";\"Рестораны\";\"Кофейня\"\n\
+            \"15.09.2026 12:30:00\";\"15.09.2026\";\"*1234\";\"FAILED\";\"-100,00\";\"RUB\";\"Рестораны\";\"Кофейня\"\n\
+            \"16.09.2026 10:00:00\";\"16.09.2026\";\"*1234\";\"OK\";\"50000,00\";\"RUB\";\"Пополнения\";\"Зарплата\"\n";
+        let statement = parse_statement(text, None).unwrap();
+        assert_eq!(statement.bank, Some("Тинькофф"));
+        assert_eq!(statement.rows.len(), 2);
+        assert_eq!(
+            statement.rows[0],
+            StatementRow {
+                date: NaiveDate::from_ymd_opt(2026, 9, 15).unwrap(),
+                kind: EntryKind::Expense,
+                amount: Money(119990),
+                category: "Рестораны".to_string(),
+                description: "Кофейня".to_string(),
+                import_ref: "15.09.2026 12:30:00|-1199,90|Кофейня".to_string(),
+            }
+        );
+        assert_eq!(statement.rows[1].kind, EntryKind::Income);
+        assert_eq!(statement.rows[1].amount, Money(5000000));
+    }
+
+    #[test]
+    fn detects_sber_layout_with_unsigned_expenses() {
+        let text = "Дата операции;Категория;Описание;Сумма в валюте счёта\n\
+            01.09.2026;Супермаркеты;Пятёрочка;350,00\n\
+            01.09.2026;Супермаркеты;Пятёрочка;350,00\n\
+            02.09.2026;Перевод;От Ивана;+1000,00\n";
+        let statement = parse_statement(text, None).unwrap();
+        assert_eq!(statement.bank, Some("Сбербанк"));
+        let kinds: Vec<_> = statement.rows.iter().map(|r| r.kind).collect();
+        assert_eq!(
+            kinds,
+            [EntryKind::Expense, EntryKind::Expense, EntryKind::Income]
+        );
+        // Identical rows of one statement are still told apart.
+        assert_ne!(statement.rows[0].import_ref, statement.rows[1].import_ref);
+    }
+
+    #[test]
+    fn uses_saved_mapping_for_unknown_layouts() {
+        let text = "date,sum,what,memo\n2026-09-01,-10.5,cafe,latte\n";
+        assert!(parse_statement(text, None).is_err());
+
+        let mapping: ColumnMapping = "1 2 3 4".parse().unwrap();
+        assert_eq!(mapping.to_string(), "1 2 3 4");
+        let statement = parse_statement(text, Some(mapping)).unwrap();
+        assert_eq!(statement.bank, None);
+        assert_eq!(statement.rows[0].amount, Money(1050));
+        assert_eq!(statement.rows[0].category, "cafe");
+        assert_eq!(statement.rows[0].description, "latte");
+    }
+
+    #[test]
+    fn reports_bad_rows_and_mappings() {
+        let mapping = Some("1 2 3".parse().unwrap());
+        let err = parse_statement("a,b,c\n2026-09-01,abc,cafe\n", mapping).unwrap_err();
+        assert_eq!(err.to_string(), "строка 2: неверная сумма \"abc\"");
+        assert!("0 1 2".parse::<ColumnMapping>().is_err());
+        assert!("1 2".parse::<ColumnMapping>().is_err());
+    }
+
+    #[test]
+    fn reads_ofx_in_both_flavours() {
+        let sgml = "OFXHEADER:100\nDATA:OFXSGML\n\n<OFX>\n<BANKMSGSRSV1><STMTTRNRS><STMTRS><BANKTRANLIST>\n\
+            <STMTTRN>\n<TRNTYPE>DEBIT\n<DTPOSTED>20260915120000[+3:MSK]\n<TRNAMT>-350.00\n<FITID>9001\n<NAME>YANDEX*TAXI\n</STMTTRN>\n\
+            <STMTTRN>\n<TRNTYPE>CREDIT\n<DTPOSTED>20260916\n<TRNAMT>50000\n<FITID>9002\n<MEMO>Salary &amp; bonus\n</STMTTRN>\n\
+            </BANKTRANLIST></STMTRS></STMTTRNRS></BANKMSGSRSV1>\n</OFX>\n";
+        let statement = read_statement(sgml.as_bytes(), None).unwrap();
+        assert_eq!(statement.bank, Some("OFX"));
+        assert_eq!(
+            statement.rows[0],
+            StatementRow {
+                date: NaiveDate::from_ymd_opt(2026, 9, 15).unwrap(),
+                kind: EntryKind::Expense,
+                amount: Money(35000),
+                category: String::new(),
+                description: "YANDEX*TAXI".to_string(),
+                import_ref: "ofx:9001".to_string(),
+            }
+        );
+        assert_eq!(statement.rows[1].kind, EntryKind::Income);
+        assert_eq!(statement.rows[1].description, "Salary & bonus");
+
+        let xml =
+            "<?xml version=\"1.0\"?><OFX><BANKTRANLIST><STMTTRN><DTPOSTED>20260915</DTPOSTED>\
+            <TRNAMT>-12.5</TRNAMT><NAME>Cafe</NAME></STMTTRN><STMTTRN><DTPOSTED>20260915</DTPOSTED>\
+            <TRNAMT>-12.5</TRNAMT><NAME>Cafe</NAME></STMTTRN></BANKTRANLIST></OFX>";
+        let statement = read_statement(xml.as_bytes(), None).unwrap();
+        assert_eq!(statement.rows.len(), 2);
+        assert_eq!(statement.rows[0].amount, Money(1250));
+        assert_ne!(statement.rows[0].import_ref, statement.rows[1].import_ref);
+
+        let err = parse_ofx("<OFX><STMTTRN><DTPOSTED>2026</STMTTRN></OFX>").unwrap_err();
+        assert_eq!(err.to_string(), "операция 1: неверная дата \"2026\"");
+    }
+
+    #[test]
+    fn reads_qif_transactions_with_categories() {
+        let text = "!Account\nNCard\nTBank\n^\n!Type:Bank\n\
+            D09/15'26\nT-1,199.90\nPКофейня\nLРестораны\n^\n\
+            D16.09.2026\nT50000\nPЗарплата\n^\n\
+            !Type:Invst\nD09/17/2026\nT-10\nPBroker\n^\n";
+        let statement = read_statement(text.as_bytes(), None).unwrap();
+        assert_eq!(statement.bank, Some("QIF"));
+        assert_eq!(statement.rows.len(), 2);
+        assert_eq!(
+            statement.rows[0],
+            StatementRow {
+                date: NaiveDate::from_ymd_opt(2026, 9, 15).unwrap(),
+                kind: EntryKind::Expense,
+                amount: Money(119990),
+                category: "Рестораны".to_string(),
+                description: "Кофейня".to_string(),
+                import_ref: "qif:09/15'26|-1199.90|Кофейня".to_string(),
+            }
+        );
+        assert_eq!(statement.rows[1].kind, EntryKind::Income);
+        assert_eq!(statement.rows[1].category, "");
+
+        let err = parse_qif("!Type:Bank\nDyesterday\nT-1\n^\n").unwrap_err();
+        assert_eq!(err.to_string(), "строка 4: неверная дата \"yesterday\"");
+    }
+
+    #[test]
+    fn longest_payee_pattern_wins() {
+        let rules = [
+            PayeeRule {
+                pattern: "yandex".to_string(),
+                category: "services".to_string(),
+            },
+            PayeeRule {
+                pattern: "yandex*taxi".to_string(),
+                category: "taxi".to_string(),
+            },
+        ];
+        assert_eq!(match_payee(&rules, "YANDEX*TAXI Moscow"), Some("taxi"));
+        assert_eq!(match_payee(&rules, "Yandex Plus"), Some("services"));
+        assert_eq!(match_payee(&rules, "Кофейня"), None);
+    }
+
+    #[test]
+    fn decodes_windows_1251() {
+        let (bytes, _, _) = encoding_rs::WINDOWS_1251.encode("Дата операции");
+        assert_eq!(decode_statement(&bytes), "Дата операции");
+        assert_eq!(decode_statement("\u{feff}Дата".as_bytes()), "Дата");
+    }
+}
@@ -0,0 +1,1503 @@
+use super::*;
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// Store shared between the handlers.
+pub type Store = Arc<dyn FinanceStore>;
+
+/// Opens the database at `DATABASE_URL`, falling back to `POSTGRESQL_URL`.
+pub async fn get_sqlx_database_client() -> Result<Store, FinanceError> {
+    let database_url = dotenv::var("DATABASE_URL")
+        .or_else(|_| dotenv::var("POSTGRESQL_URL"))
+        .expect("DATABASE_URL or POSTGRESQL_URL must be set");
+    connect(&database_url).await
+}
+
+/// Picks the backend by the URL scheme: `postgres://` or `sqlite://`.
+pub async fn connect(url: &str) -> Result<Store, FinanceError> {
+    let scheme = url.split_once(':').map_or("", |(scheme, _)| scheme);
+    match scheme {
+        "postgres" | "postgresql" => Ok(Arc::new(PgStore::connect(url).await?)),
+        #[cfg(feature = "sqlite")]
+        "sqlite" => Ok(Arc::new(SqliteStore::connect(url).await?)),
+        #[cfg(not(feature = "sqlite"))]
+        "sqlite" => Err(FinanceError::Validation(
+            "SQLite support is not compiled in, build with --features sqlite".to_string(),
+        )),
+        _ => Err(FinanceError::Validation(format!(
+            "unsupported database URL scheme \"{scheme}\""
+        ))),
+    }
+}
+
+/// Persistence of a user's accounts, categories and ledger.
+///
+/// Every method is scoped to `user_id`: rows of other users are never
+/// returned or changed, and ids of such rows are reported as
+/// [`FinanceError::NotOwner`].
+#[async_trait]
+pub trait FinanceStore: Send + Sync {
+    async fn add_account(&self, account: Accounts) -> Result<(), FinanceError>;
+
+    async fn get_accounts_page(
+        &self,
+        user_id: i64,
+        page: Option<Page>,
+    ) -> Result<Paged<Accounts>, FinanceError>;
+
+    async fn get_accounts(&self, user_id: i64) -> Result<Vec<Accounts>, FinanceError> {
+        Ok(self.get_accounts_page(user_id, None).await?.items)
+    }
+
+    async fn edit_account(
+        &self,
+        user_id: i64,
+        id: i64,
+        name: String,
+        balance: Money,
+    ) -> Result<(), FinanceError>;
+
+    async fn del_account(&self, user_id: i64, id: i64) -> Result<(), FinanceError>;
+
+    async fn add_category(&self, category: Categories) -> Result<(), FinanceError>;
+
+    async fn get_categories_page(
+        &self,
+        user_id: i64,
+        page: Option<Page>,
+    ) -> Result<Paged<Categories>, FinanceError>;
+
+    async fn get_categories(&self, user_id: i64) -> Result<Vec<Categories>, FinanceError> {
+        Ok(self.get_categories_page(user_id, None).await?.items)
+    }
+
+    async fn edit_category(
+        &self,
+        user_id: i64,
+        id: i64,
+        name: String,
+        description: String,
+    ) -> Result<(), FinanceError>;
+
+    async fn del_category(&self, user_id: i64, id: i64) -> Result<(), FinanceError>;
+
+    /// Records an expense or income with its note and tags, and changes the
+    /// account balance by its amount. Returns the id of the new row.
+    async fn add_entry(
+        &self,
+        user_id: i64,
+        income: bool,
+        entry: NewEntry,
+    ) -> Result<i64, FinanceError>;
+
+    /// Records an expense and takes its amount off the account balance.
+    /// Returns the id of the expense.
+    async fn add_expense(
+        &self,
+        user_id: i64,
+        amount: Money,
+        category: String,
+        account: String,
+        date: Option<NaiveDate>,
+    ) -> Result<i64, FinanceError> {
+        let entry = NewEntry {
+            amount,
+            category,
+            account,
+            date,
+            import_ref: None,
+            note: Note::default(),
+        };
+        self.add_entry(user_id, false, entry).await
+    }
+
+    /// Expenses of the period, only those tagged `tag` if given.
+    async fn get_expense_page(
+        &self,
+        user_id: i64,
+        period: Period,
+        tag: Option<String>,
+        page: Option<Page>,
+    ) -> Result<Paged<Expenses>, FinanceError>;
+
+    async fn get_expense(
+        &self,
+        user_id: i64,
+        period: Period,
+    ) -> Result<Vec<Expenses>, FinanceError> {
+        Ok(self
+            .get_expense_page(user_id, period, None, None)
+            .await?
+            .items)
+    }
+
+    /// Deletes an expense and returns its amount to the account.
+    async fn del_expense(&self, user_id: i64, id: i64) -> Result<(), FinanceError>;
+
+    /// Records income and adds its amount to the account balance. Returns
+    /// the id of the income.
+    async fn add_income(
+        &self,
+        user_id: i64,
+        amount: Money,
+        category: String,
+        account: String,
+        date: Option<NaiveDate>,
+    ) -> Result<i64, FinanceError> {
+        let entry = NewEntry {
+            amount,
+            category,
+            account,
+            date,
+            import_ref: None,
+            note: Note::default(),
+        };
+        self.add_entry(user_id, true, entry).await
+    }
+
+    /// Income of the period, only that tagged `tag` if given.
+    async fn get_income_page(
+        &self,
+        user_id: i64,
+        period: Period,
+        tag: Option<String>,
+        page: Option<Page>,
+    ) -> Result<Paged<Income>, FinanceError>;
+
+    async fn get_income(&self, user_id: i64, period: Period) -> Result<Vec<Income>, FinanceError> {
+        Ok(self
+            .get_income_page(user_id, period, None, None)
+            .await?
+            .items)
+    }
+
+    /// Deletes income and takes its amount back off the account.
+    async fn del_income(&self, user_id: i64, id: i64) -> Result<(), FinanceError>;
+
+    /// Books statement rows on `account` in one transaction, skipping the
+    /// rows imported before. Returns the number of booked rows.
+    async fn import_rows(
+        &self,
+        user_id: i64,
+        account: String,
+        rows: Vec<StatementRow>,
+    ) -> Result<usize, FinanceError>;
+
+    /// Fingerprints of every imported expense and income of the user.
+    async fn get_import_fingerprints(&self, user_id: i64) -> Result<HashSet<String>, FinanceError>;
+
+    /// Whether an entry with the fingerprint of `entry` was recorded after
+    /// `since`.
+    async fn has_recent_duplicate(
+        &self,
+        user_id: i64,
+        income: bool,
+        entry: &NewEntry,
+        since: DateTime<Local>,
+    ) -> Result<bool, FinanceError>;
+
+    async fn get_import_mapping(&self, user_id: i64)
+        -> Result<Option<ColumnMapping>, FinanceError>;
+
+    async fn set_import_mapping(
+        &self,
+        user_id: i64,
+        mapping: ColumnMapping,
+    ) -> Result<(), FinanceError>;
+
+    /// Aliases of the user, sorted by alias.
+    async fn get_aliases(&self, user_id: i64) -> Result<Vec<Alias>, FinanceError>;
+
+    /// Makes `alias` another name of the category called `name` or, when there
+    /// is none, of the account, replacing the alias if it was set before.
+    async fn set_alias(
+        &self,
+        user_id: i64,
+        alias: String,
+        name: String,
+    ) -> Result<AliasTarget, FinanceError>;
+
+    async fn del_alias(&self, user_id: i64, alias: String) -> Result<(), FinanceError>;
+
+    /// State of the user's unfinished dialogue, serialized by the handlers.
+    async fn get_dialogue(&self, user_id: i64) -> Result<Option<String>, FinanceError>;
+
+    async fn set_dialogue(&self, user_id: i64, state: String) -> Result<(), FinanceError>;
+
+    async fn del_dialogue(&self, user_id: i64) -> Result<(), FinanceError>;
+
+    /// Id under which a button keeps `payload` that wouldn't fit the 64 bytes
+    /// of its callback data. An equal payload saved before keeps its id.
+    async fn save_callback_payload(
+        &self,
+        user_id: i64,
+        payload: String,
+    ) -> Result<i64, FinanceError>;
+
+    async fn get_callback_payload(
+        &self,
+        user_id: i64,
+        id: i64,
+    ) -> Result<Option<String>, FinanceError>;
+
+    /// Removes the payload of a button that works once, `None` when it was
+    /// taken already.
+    async fn take_callback_payload(
+        &self,
+        user_id: i64,
+        id: i64,
+    ) -> Result<Option<String>, FinanceError>;
+
+    /// Payee rules of the user, sorted by pattern.
+    async fn get_payee_rules(&self, user_id: i64) -> Result<Vec<PayeeRule>, FinanceError>;
+
+    /// Adds a rule or moves the rule with the same pattern to another
+    /// category. Patterns are stored lowercased.
+    async fn set_payee_rule(
+        &self,
+        user_id: i64,
+        pattern: String,
+        category: String,
+    ) -> Result<(), FinanceError>;
+
+    async fn del_payee_rule(&self, user_id: i64, pattern: String) -> Result<(), FinanceError>;
+
+    /// Adds an expense or income for the scheduler to book from
+    /// `recurring.next_run` on.
+    async fn add_recurring(
+        &self,
+        user_id: i64,
+        recurring: NewRecurring,
+    ) -> Result<(), FinanceError>;
+
+    /// Recurring operations of the user, in the order they were added.
+    async fn get_recurring(&self, user_id: i64) -> Result<Vec<Recurring>, FinanceError>;
+
+    async fn del_recurring(&self, user_id: i64, id: i64) -> Result<(), FinanceError>;
+
+    /// Recurring operations of every user due by `today`, for the scheduler.
+    async fn due_recurring(&self, today: NaiveDate) -> Result<Vec<Recurring>, FinanceError>;
+
+    /// Books the run of a recurring operation due on `run` and moves the
+    /// operation on to its next day in one transaction. Returns `false`
+    /// without booking when the run is no longer due because it was booked
+    /// meanwhile, or is being booked by another instance of the bot.
+    async fn book_recurring_run(
+        &self,
+        user_id: i64,
+        id: i64,
+        run: NaiveDate,
+    ) -> Result<bool, FinanceError>;
+
+    /// Moves a recurring operation on to `next_run`, skipping a day that
+    /// could not be booked.
+    async fn set_recurring_next_run(
+        &self,
+        user_id: i64,
+        id: i64,
+        next_run: NaiveDate,
+    ) -> Result<(), FinanceError>;
+
+    /// Everything of the user, for `/backup`.
+    async fn backup(&self, user_id: i64) -> Result<Backup, FinanceError>;
+
+    /// Replaces everything of the user with `backup` in one transaction. Rows
+    /// get new ids and the references between them follow.
+    async fn restore(&self, user_id: i64, backup: Backup) -> Result<(), FinanceError>;
+
+    async fn add_transfer(
+        &self,
+        user_id: i64,
+        amount: Money,
+        from: String,
+        to: String,
+    ) -> Result<(), FinanceError>;
+
+    async fn get_transfers_page(
+        &self,
+        user_id: i64,
+        period: Period,
+        page: Option<Page>,
+    ) -> Result<Paged<Transfers>, FinanceError>;
+
+    async fn get_transfers(
+        &self,
+        user_id: i64,
+        period: Period,
+    ) -> Result<Vec<Transfers>, FinanceError> {
+        Ok(self.get_transfers_page(user_id, period, None).await?.items)
+    }
+
+    async fn del_transfer(&self, user_id: i64, id: i64) -> Result<(), FinanceError>;
+
+    async fn get_rates(&self, user_id: i64) -> Result<Vec<ExchangeRates>, FinanceError>;
+
+    /// Stores the rates, replacing previously set ones for the same currency pairs.
+    async fn set_rates(&self, user_id: i64, rates: &[ExchangeRates]) -> Result<(), FinanceError>;
+
+    async fn get_base_currency(&self, user_id: i64) -> Result<String, FinanceError>;
+
+    async fn set_base_currency(&self, user_id: i64, currency: String) -> Result<(), FinanceError>;
+
+    /// Account of the messages that name none, `None` until it is set.
+    async fn get_default_account(&self, user_id: i64) -> Result<Option<String>, FinanceError>;
+
+    async fn set_default_account(&self, user_id: i64, account: String) -> Result<(), FinanceError>;
+
+    async fn set_budget(
+        &self,
+        user_id: i64,
+        category: String,
+        limit: Money,
+        period: BudgetPeriod,
+    ) -> Result<(), FinanceError>;
+
+    /// Budgets with the amounts spent in their current week or month per
+    /// account currency, optionally limited to one category.
+    async fn get_budget_sums(
+        &self,
+        user_id: i64,
+        category: Option<String>,
+    ) -> Result<Vec<BudgetSums>, FinanceError>;
+
+    /// Budgets with the amount spent converted to the base currency of the
+    /// limits.
+    async fn get_budgets(
+        &self,
+        user_id: i64,
+        category: Option<String>,
+    ) -> Result<Vec<Budgets>, FinanceError> {
+        let sums = self.get_budget_sums(user_id, category).await?;
+        let rates = self.get_rates(user_id).await?;
+        let base = self.get_base_currency(user_id).await?;
+        Ok(sums
+            .into_iter()
+            .map(|sums| Budgets::new(sums, &base, &rates))
+            .collect())
+    }
+
+    /// Income, expenses and expense categories of the period summed per
+    /// account currency.
+    async fn get_report_sums(
+        &self,
+        user_id: i64,
+        period: Period,
+    ) -> Result<ReportSums, FinanceError>;
+
+    /// The sums of the period converted to the base currency.
+    async fn get_report(&self, user_id: i64, period: Period) -> Result<Report, FinanceError> {
+        let sums = self.get_report_sums(user_id, period).await?;
+        let rates = self.get_rates(user_id).await?;
+        let base = self.get_base_currency(user_id).await?;
+        Ok(Report::new(sums, &base, &rates))
+    }
+
+    /// Expenses, income and transfers of the period, oldest first, fetched
+    /// row by row.
+    fn ledger(
+        &self,
+        user_id: i64,
+        period: Period,
+    ) -> BoxStream<'_, Result<LedgerEntry, FinanceError>>;
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use futures::TryStreamExt;
+    use std::collections::HashMap;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    /// Connects to `POSTGRESQL_URL`, or returns `None` so the test is skipped
+    /// on machines without a database.
+    pub(crate) async fn test_store() -> Option<PgStore> {
+        dotenv::dotenv().ok();
+        let Ok(url) = dotenv::var("POSTGRESQL_URL") else {
+            eprintln!("POSTGRESQL_URL is not set, skipping database test");
+            return None;
+        };
+        Some(PgStore::connect(&url).await.unwrap())
+    }
+
+    /// A fresh SQLite database in memory, living as long as the single
+    /// connection of the pool, so a test leaves no file behind.
+    #[cfg(feature = "sqlite")]
+    async fn test_sqlite_store() -> SqliteStore {
+        SqliteStore::connect("sqlite::memory:").await.unwrap()
+    }
+
+    /// The in-memory store, SQLite when the feature is on and Postgres when a
+    /// database is available.
+    async fn stores() -> Vec<Store> {
+        let mut stores: Vec<Store> = vec![Arc::new(MemoryStore::default())];
+        #[cfg(feature = "sqlite")]
+        stores.push(Arc::new(test_sqlite_store().await));
+        if let Some(store) = test_store().await {
+            stores.push(Arc::new(store));
+        }
+        stores
+    }
+
+    /// A user id no real chat will have, so tests don't collide with each other.
+    pub(crate) fn test_user_id() -> i64 {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos() as i64;
+        -nanos.abs()
+    }
+
+    pub(crate) async fn setup_user(store: &dyn FinanceStore, balance: i64) -> i64 {
+        let user_id = test_user_id();
+        store
+            .add_account(Accounts {
+                id: None,
+                name: "card".to_string(),
+                balance: Money(balance),
+                user_id,
+                currency: DEFAULT_CURRENCY.to_string(),
+            })
+            .await
+            .unwrap();
+        store
+            .add_category(Categories {
+                id: None,
+                name: "food".to_string(),
+                user_id,
+                description: "test".to_string(),
+            })
+            .await
+            .unwrap();
+        user_id
+    }
+
+    pub(crate) async fn balance(store: &dyn FinanceStore, user_id: i64) -> i64 {
+        store.get_accounts(user_id).await.unwrap()[0].balance.0
+    }
+
+    #[tokio::test]
+    async fn failed_expense_leaves_balance_untouched() {
+        for store in stores().await {
+            let store = &*store;
+            let user_id = setup_user(store, 100).await;
+
+            let res = store
+                .add_expense(user_id, Money(40), "unknown".into(), "card".into(), None)
+                .await;
+
+            assert!(matches!(res, Err(FinanceError::UnknownCategory(_))));
+            assert_eq!(balance(store, user_id).await, 100);
+            assert!(store
+                .get_expense(user_id, Period::All)
+                .await
+                .unwrap()
+                .is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn other_users_rows_are_not_found() {
+        for store in stores().await {
+            let store = &*store;
+            let owner = setup_user(store, 100).await;
+            let stranger = setup_user(store, 0).await;
+
+            store
+                .add_expense(owner, Money(10), "food".into(), "card".into(), None)
+                .await
+                .unwrap();
+            store
+                .add_income(owner, Money(20), "food".into(), "card".into(), None)
+                .await
+                .unwrap();
+            let acc_id = store.get_accounts(owner).await.unwrap()[0].id.unwrap();
+            let cat_id = store.get_categories(owner).await.unwrap()[0].id.unwrap();
+            let exp_id = store.get_expense(owner, Period::All).await.unwrap()[0].id;
+            let inc_id = store.get_income(owner, Period::All).await.unwrap()[0].id;
+
+            let results = [
+                store.del_expense(stranger, exp_id).await,
+                store.del_income(stranger, inc_id).await,
+                store
+                    .edit_account(stranger, acc_id, "x".into(), Money(0))
+                    .await,
+                store
+                    .edit_category(stranger, cat_id, "x".into(), "x".into())
+                    .await,
+                store.del_account(stranger, acc_id).await,
+                store.del_category(stranger, cat_id).await,
+            ];
+            for res in results {
+                assert!(matches!(res, Err(FinanceError::NotOwner)));
+            }
+
+            assert_eq!(balance(store, owner).await, 110);
+            assert_eq!(
+                store.get_expense(owner, Period::All).await.unwrap().len(),
+                1
+            );
+            assert_eq!(store.get_income(owner, Period::All).await.unwrap().len(), 1);
+            assert_eq!(store.get_accounts(owner).await.unwrap()[0].name, "card");
+            assert_eq!(store.get_categories(owner).await.unwrap()[0].name, "food");
+        }
+    }
+
+    #[tokio::test]
+    async fn expenses_are_filtered_by_period() {
+        for store in stores().await {
+            let store = &*store;
+            let user_id = setup_user(store, 100).await;
+            let day = |d| NaiveDate::from_ymd_opt(2026, 9, d);
+
+            for (amount, date) in [(1, day(1)), (2, day(15)), (3, day(30)), (4, None)] {
+                store
+                    .add_expense(user_id, Money(amount), "food".into(), "card".into(), date)
+                    .await
+                    .unwrap();
+            }
+
+            let amounts =
+                |expenses: Vec<Expenses>| expenses.iter().map(|e| e.amount.0).collect::<Vec<_>>();
+            let september = "2026-09".parse().unwrap();
+            let range = "2026-09-02..2026-09-30".parse().unwrap();
+            assert_eq!(
+                amounts(store.get_expense(user_id, september).await.unwrap()),
+                [1, 2, 3]
+            );
+            assert_eq!(
+                amounts(store.get_expense(user_id, range).await.unwrap()),
+                [2, 3]
+            );
+            assert_eq!(
+                amounts(store.get_expense(user_id, Period::Today).await.unwrap()),
+                [4]
+            );
+            assert_eq!(
+                store.get_expense(user_id, Period::All).await.unwrap().len(),
+                4
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn notes_and_tags_are_stored_and_filtered() {
+        for store in stores().await {
+            let store = &*store;
+            let user_id = setup_user(store, 100).await;
+            let entry = |amount, note: &str| NewEntry {
+                amount: Money(amount),
+                category: "food".to_string(),
+                account: "card".to_string(),
+                date: None,
+                import_ref: None,
+                note: Note::parse(note),
+            };
+
+            store
+                .add_entry(user_id, false, entry(1, "ужин с клиентом #work #trip"))
+                .await
+                .unwrap();
+            store
+                .add_entry(user_id, false, entry(2, "#trip"))
+                .await
+                .unwrap();
+            store.add_entry(user_id, false, entry(3, "")).await.unwrap();
+            store
+                .add_entry(user_id, true, entry(4, "аванс #work"))
+                .await
+                .unwrap();
+
+            let expenses = |tag: Option<&'static str>| async move {
+                let page = store
+                    .get_expense_page(user_id, Period::All, tag.map(str::to_string), None)
+                    .await
+                    .unwrap();
+                page.items
+                    .iter()
+                    .map(|e| (e.amount.0, e.note.to_string()))
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(
+                expenses(None).await,
+                [
+                    (1, "ужин с клиентом #trip #work".to_string()),
+                    (2, "#trip".to_string()),
+                    (3, String::new())
+                ]
+            );
+            assert_eq!(expenses(Some("trip")).await.len(), 2);
+            assert_eq!(expenses(Some("work")).await[0].0, 1);
+            assert!(expenses(Some("отпуск")).await.is_empty());
+            let income = store
+                .get_income_page(user_id, Period::All, Some("work".to_string()), None)
+                .await
+                .unwrap();
+            assert_eq!(income.items[0].note, Note::parse("аванс #work"));
+
+            let notes: Vec<String> = store
+                .ledger(user_id, Period::All)
+                .map_ok(|entry| entry.note)
+                .try_collect()
+                .await
+                .unwrap();
+            assert!(notes.contains(&"аванс #work".to_string()), "{notes:?}");
+
+            let backup = store.backup(user_id).await.unwrap();
+            assert_eq!(backup.expenses[1].note, Note::parse("#trip"));
+            let id = store.get_expense(user_id, Period::All).await.unwrap()[0].id;
+            store.del_expense(user_id, id).await.unwrap();
+            assert_eq!(expenses(Some("work")).await, []);
+        }
+    }
+
+    #[tokio::test]
+    async fn ledger_streams_all_operations_in_order() {
+        for store in stores().await {
+            let store = &*store;
+            let user_id = setup_user(store, 100).await;
+            store
+                .add_account(Accounts {
+                    id: None,
+                    name: "cash".to_string(),
+                    balance: Money(0),
+                    user_id,
+                    currency: DEFAULT_CURRENCY.to_string(),
+                })
+                .await
+                .unwrap();
+            let day = |d| NaiveDate::from_ymd_opt(2026, 9, d);
+            store
+                .add_expense(user_id, Money(1), "food".into(), "card".into(), day(15))
+                .await
+                .unwrap();
+            store
+                .add_income(user_id, Money(2), "food".into(), "card".into(), day(1))
+                .await
+                .unwrap();
+            store
+                .add_transfer(user_id, Money(3), "card".into(), "cash".into())
+                .await
+                .unwrap();
+
+            let entries = |period| async move {
+                store
+                    .ledger(user_id, period)
+                    .map_ok(|e| (e.kind, e.amount.0, e.account, e.category, e.currency))
+                    .try_collect::<Vec<_>>()
+                    .await
+                    .unwrap()
+            };
+            let row = |kind, amount, category: &str| {
+                let (account, currency) = ("card".to_string(), "RUB".to_string());
+                (kind, amount, account, category.to_string(), currency)
+            };
+            assert_eq!(
+                entries("2026-09".parse().unwrap()).await,
+                [
+                    row(EntryKind::Income, 2, "food"),
+                    row(EntryKind::Expense, 1, "food")
+                ]
+            );
+            assert_eq!(
+                entries(Period::Today).await,
+                [row(EntryKind::Transfer, 3, "cash")]
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn imported_rows_are_booked_once() {
+        for store in stores().await {
+            let store = &*store;
+            let user_id = setup_user(store, 100).await;
+            let row = |kind, amount, category: &str, import_ref: &str| StatementRow {
+                date: NaiveDate::from_ymd_opt(2026, 9, 1).unwrap(),
+                kind,
+                amount: Money(amount),
+                category: category.to_string(),
+                description: String::new(),
+                import_ref: import_ref.to_string(),
+            };
+
+            let rows = vec![
+                row(EntryKind::Expense, 30, "food", "a"),
+                row(EntryKind::Income, 50, "food", "b"),
+            ];
+            let added = store.import_rows(user_id, "card".into(), rows.clone());
+            assert_eq!(added.await.unwrap(), 2);
+            assert_eq!(balance(store, user_id).await, 120);
+
+            let added = store.import_rows(user_id, "card".into(), rows);
+            assert_eq!(added.await.unwrap(), 0);
+            assert_eq!(balance(store, user_id).await, 120);
+            let fingerprints = store.get_import_fingerprints(user_id).await.unwrap();
+            assert_eq!(fingerprints.len(), 2);
+            assert!(fingerprints
+                .iter()
+                .all(|f| f.ends_with("|2026-09-01|a") || f.ends_with("|2026-09-01|b")));
+
+            let rows = vec![
+                row(EntryKind::Expense, 10, "food", "c"),
+                row(EntryKind::Expense, 10, "cafe", "d"),
+            ];
+            let res = store.import_rows(user_id, "card".into(), rows).await;
+            assert!(matches!(res, Err(FinanceError::UnknownCategory(_))));
+            assert_eq!(balance(store, user_id).await, 120);
+
+            assert_eq!(store.get_import_mapping(user_id).await.unwrap(), None);
+            let mapping: ColumnMapping = "1 4 2".parse().unwrap();
+            store.set_import_mapping(user_id, mapping).await.unwrap();
+            store.set_import_mapping(user_id, mapping).await.unwrap();
+            assert_eq!(
+                store.get_import_mapping(user_id).await.unwrap(),
+                Some(mapping)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn payee_rules_follow_their_category() {
+        for store in stores().await {
+            let store = &*store;
+            let user_id = setup_user(store, 100).await;
+            let rule = |pattern: &str, category: &str| PayeeRule {
+                pattern: pattern.to_string(),
+                category: category.to_string(),
+            };
+
+            let err = store
+                .set_payee_rule(user_id, "Taxi".into(), "transport".into())
+                .await
+                .unwrap_err();
+            assert!(matches!(err, FinanceError::UnknownCategory(name) if name == "transport"));
+            for pattern in ["Yandex Taxi", "cafe", "YANDEX TAXI"] {
+                store
+                    .set_payee_rule(user_id, pattern.into(), "food".into())
+                    .await
+                    .unwrap();
+            }
+            assert_eq!(
+                store.get_payee_rules(user_id).await.unwrap(),
+                [rule("cafe", "food"), rule("yandex taxi", "food")]
+            );
+
+            store.del_payee_rule(user_id, "Cafe".into()).await.unwrap();
+            assert!(matches!(
+                store.del_payee_rule(user_id, "cafe".into()).await,
+                Err(FinanceError::NotFound)
+            ));
+            assert!(store
+                .get_payee_rules(test_user_id())
+                .await
+                .unwrap()
+                .is_empty());
+
+            // Deleting the category takes its rules along.
+            let food = store.get_categories(user_id).await.unwrap()[0].id.unwrap();
+            store.del_category(user_id, food).await.unwrap();
+            assert!(store.get_payee_rules(user_id).await.unwrap().is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn names_match_in_any_case_and_by_alias() {
+        for store in stores().await {
+            let store = &*store;
+            let user_id = setup_user(store, 1000).await;
+
+            store
+                .add_expense(user_id, Money(10), "FOOD".into(), "Card".into(), None)
+                .await
+                .unwrap();
+            assert_eq!(
+                store
+                    .set_alias(user_id, "Еда".into(), "food".into())
+                    .await
+                    .unwrap(),
+                AliasTarget::Category("food".to_string())
+            );
+            assert_eq!(
+                store
+                    .set_alias(user_id, "карта".into(), "CARD".into())
+                    .await
+                    .unwrap(),
+                AliasTarget::Account("card".to_string())
+            );
+            assert!(matches!(
+                store.set_alias(user_id, "x".into(), "unknown".into()).await,
+                Err(FinanceError::UnknownCategory(_))
+            ));
+            store
+                .add_expense(user_id, Money(20), "еда".into(), "Карта".into(), None)
+                .await
+                .unwrap();
+            assert_eq!(balance(store, user_id).await, 1000 - 30);
+
+            store
+                .set_budget(user_id, "ЕДА".into(), Money(100), BudgetPeriod::Month)
+                .await
+                .unwrap();
+            let budgets = store
+                .get_budgets(user_id, Some("еда".into()))
+                .await
+                .unwrap();
+            assert_eq!(budgets[0].category, "food");
+            assert_eq!(budgets[0].spent, Money(30));
+
+            let aliases: Vec<_> = store
+                .get_aliases(user_id)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|alias| alias.alias)
+                .collect();
+            assert_eq!(aliases, ["еда", "карта"]);
+            store.del_alias(user_id, "ЕДА".into()).await.unwrap();
+            assert!(matches!(
+                store.del_alias(user_id, "еда".into()).await,
+                Err(FinanceError::NotFound)
+            ));
+            assert!(matches!(
+                store
+                    .add_expense(user_id, Money(20), "еда".into(), "card".into(), None)
+                    .await,
+                Err(FinanceError::UnknownCategory(_))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn recurring_runs_are_booked_once() {
+        let day = |d: u32| NaiveDate::from_ymd_opt(2026, 10, d).unwrap();
+        for store in stores().await {
+            let store = &*store;
+            let user_id = setup_user(store, 100).await;
+            let rent = NewRecurring {
+                income: false,
+                amount: Money(30),
+                category: "food".to_string(),
+                account: "card".to_string(),
+                schedule: Schedule::Monthly(5),
+                next_run: day(5),
+            };
+            store.add_recurring(user_id, rent).await.unwrap();
+            let id = store.get_recurring(user_id).await.unwrap()[0].id;
+
+            assert!(store.book_recurring_run(user_id, id, day(5)).await.unwrap());
+            assert!(!store.book_recurring_run(user_id, id, day(5)).await.unwrap());
+            assert!(!store
+                .book_recurring_run(
+                    test_user_id(),
+                    id,
+                    NaiveDate::from_ymd_opt(2026, 11, 5).unwrap()
+                )
+                .await
+                .unwrap());
+
+            assert_eq!(balance(store, user_id).await, 70);
+            let expenses = store.get_expense(user_id, Period::All).await.unwrap();
+            assert_eq!(expenses.len(), 1);
+            assert_eq!(expenses[0].occurred_at.date_naive(), day(5));
+            let recurring = store.get_recurring(user_id).await.unwrap();
+            assert_eq!(
+                recurring[0].next_run,
+                NaiveDate::from_ymd_opt(2026, 11, 5).unwrap()
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn recurring_operations_come_due_and_move_on() {
+        let day = |d: u32| NaiveDate::from_ymd_opt(2026, 10, d).unwrap();
+        for store in stores().await {
+            let store = &*store;
+            let user_id = setup_user(store, 100).await;
+            let rent = |account: &str| NewRecurring {
+                income: false,
+                amount: Money(3000000),
+                category: "food".to_string(),
+                account: account.to_string(),
+                schedule: Schedule::Monthly(5),
+                next_run: day(5),
+            };
+
+            let err = store
+                .add_recurring(user_id, rent("cash"))
+                .await
+                .unwrap_err();
+            assert!(matches!(err, FinanceError::UnknownAccount(name) if name == "cash"));
+            store.add_recurring(user_id, rent("card")).await.unwrap();
+            let recurring = store.get_recurring(user_id).await.unwrap();
+            assert_eq!(recurring.len(), 1);
+            assert_eq!(recurring[0].account, "card");
+            assert_eq!(recurring[0].schedule, Schedule::Monthly(5));
+            let id = recurring[0].id;
+
+            let due = |today| async move {
+                store
+                    .due_recurring(today)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .filter(|recurring| recurring.user_id == user_id)
+                    .count()
+            };
+            assert_eq!(due(day(4)).await, 0);
+            assert_eq!(due(day(5)).await, 1);
+            store
+                .set_recurring_next_run(user_id, id, day(30))
+                .await
+                .unwrap();
+            assert_eq!(due(day(29)).await, 0);
+            assert!(matches!(
+                store
+                    .set_recurring_next_run(test_user_id(), id, day(5))
+                    .await,
+                Err(FinanceError::NotOwner)
+            ));
+
+            store.del_recurring(user_id, id).await.unwrap();
+            assert!(matches!(
+                store.del_recurring(user_id, id).await,
+                Err(FinanceError::NotFound)
+            ));
+
+            // Deleting the account takes its recurring operations along.
+            store.add_recurring(user_id, rent("card")).await.unwrap();
+            let card = store.get_accounts(user_id).await.unwrap()[0].id.unwrap();
+            store.del_account(user_id, card).await.unwrap();
+            assert!(store.get_recurring(user_id).await.unwrap().is_empty());
+        }
+    }
+
+    /// Ids of `backup` replaced by positions, to compare backups of instances.
+    fn renumbered(mut backup: Backup) -> Backup {
+        let accounts: HashMap<i64, i64> = backup
+            .accounts
+            .iter_mut()
+            .enumerate()
+            .map(|(index, acc)| (std::mem::replace(&mut acc.id, index as i64), index as i64))
+            .collect();
+        let categories: HashMap<i64, i64> = backup
+            .categories
+            .iter_mut()
+            .enumerate()
+            .map(|(index, cat)| (std::mem::replace(&mut cat.id, index as i64), index as i64))
+            .collect();
+        for entry in backup.expenses.iter_mut().chain(&mut backup.income) {
+            entry.account_id = accounts[&entry.account_id];
+            entry.category_id = categories[&entry.category_id];
+        }
+        for transfer in &mut backup.transfers {
+            transfer.from_account_id = accounts[&transfer.from_account_id];
+            transfer.to_account_id = accounts[&transfer.to_account_id];
+        }
+        for recurring in &mut backup.recurring {
+            recurring.account_id = accounts[&recurring.account_id];
+            recurring.category_id = categories[&recurring.category_id];
+        }
+        for rule in &mut backup.settings.payee_rules {
+            rule.category_id = categories[&rule.category_id];
+        }
+        backup.settings.default_account_id =
+            backup.settings.default_account_id.map(|id| accounts[&id]);
+        for alias in &mut backup.settings.aliases {
+            alias.category_id = alias.category_id.map(|id| categories[&id]);
+            alias.account_id = alias.account_id.map(|id| accounts[&id]);
+        }
+        backup.created_at = DateTime::<Local>::default();
+        backup
+    }
+
+    #[tokio::test]
+    async fn restore_replaces_user_data_with_new_ids() {
+        let sample = crate::handlers::logic::backup::tests::sample_backup();
+        for store in stores().await {
+            let store = &*store;
+            let user_id = setup_user(store, 500).await;
+            store
+                .add_expense(user_id, Money(100), "food".into(), "card".into(), None)
+                .await
+                .unwrap();
+
+            let mut broken = sample.clone();
+            broken.transfers[0].to_account_id = 1;
+            let err = store.restore(user_id, broken).await.unwrap_err();
+            assert!(matches!(err, FinanceError::Validation(_)));
+            assert_eq!(balance(store, user_id).await, 400);
+
+            store.restore(user_id, sample.clone()).await.unwrap();
+            let accounts = store.get_accounts(user_id).await.unwrap();
+            let names: Vec<_> = accounts.iter().map(|acc| acc.name.as_str()).collect();
+            assert_eq!(names, ["card", "cash"]);
+            assert_ne!(accounts[0].id, Some(7));
+            assert_eq!(
+                store.get_expense(user_id, Period::All).await.unwrap().len(),
+                1
+            );
+            assert_eq!(
+                store.get_import_fingerprints(user_id).await.unwrap().len(),
+                1
+            );
+            assert_eq!(store.get_base_currency(user_id).await.unwrap(), "USD");
+
+            let backup = store.backup(user_id).await.unwrap();
+            assert_eq!(renumbered(backup), renumbered(sample.clone()));
+        }
+    }
+
+    #[tokio::test]
+    async fn recent_duplicates_are_found_by_fingerprint() {
+        for store in stores().await {
+            let store = &*store;
+            let user_id = setup_user(store, 100).await;
+            let entry = |amount, account: &str| NewEntry {
+                amount: Money(amount),
+                category: "food".to_string(),
+                account: account.to_string(),
+                date: None,
+                import_ref: None,
+                note: Note::default(),
+            };
+            let minute_ago = Local::now() - chrono::Duration::minutes(1);
+
+            assert!(!store
+                .has_recent_duplicate(user_id, false, &entry(10, "card"), minute_ago)
+                .await
+                .unwrap());
+            store
+                .add_expense(user_id, Money(10), "food".into(), "card".into(), None)
+                .await
+                .unwrap();
+            assert!(store
+                .has_recent_duplicate(user_id, false, &entry(10, "card"), minute_ago)
+                .await
+                .unwrap());
+            assert!(!store
+                .has_recent_duplicate(user_id, true, &entry(10, "card"), minute_ago)
+                .await
+                .unwrap());
+            assert!(!store
+                .has_recent_duplicate(user_id, false, &entry(11, "card"), minute_ago)
+                .await
+                .unwrap());
+            assert!(!store
+                .has_recent_duplicate(user_id, false, &entry(10, "bank"), minute_ago)
+                .await
+                .unwrap());
+            let later = Local::now() + chrono::Duration::minutes(1);
+            assert!(!store
+                .has_recent_duplicate(user_id, false, &entry(10, "card"), later)
+                .await
+                .unwrap());
+        }
+    }
+
+    #[tokio::test]
+    async fn transfer_moves_money_and_delete_reverts_it() {
+        for store in stores().await {
+            let store = &*store;
+            let user_id = setup_user(store, 100).await;
+            store
+                .add_account(Accounts {
+                    id: None,
+                    name: "cash".to_string(),
+                    balance: Money(0),
+                    user_id,
+                    currency: DEFAULT_CURRENCY.to_string(),
+                })
+                .await
+                .unwrap();
+            let balances = || async move {
+                let mut accounts = store.get_accounts(user_id).await.unwrap();
+                accounts.sort_by(|a, b| a.name.cmp(&b.name));
+                accounts.iter().map(|a| a.balance.0).collect::<Vec<_>>()
+            };
+
+            store
+                .add_transfer(user_id, Money(30), "card".into(), "cash".into())
+                .await
+                .unwrap();
+            assert_eq!(balances().await, [70, 30]);
+            assert!(store
+                .add_transfer(user_id, Money(30), "card".into(), "card".into())
+                .await
+                .is_err_and(|e| matches!(e, FinanceError::Validation(_))));
+            assert!(store
+                .add_transfer(user_id, Money(30), "card".into(), "bank".into())
+                .await
+                .is_err_and(|e| matches!(e, FinanceError::UnknownAccount(name) if name == "bank")));
+            assert_eq!(balances().await, [70, 30]);
+
+            store
+                .add_account(Accounts {
+                    id: None,
+                    name: "usd".to_string(),
+                    balance: Money(0),
+                    user_id,
+                    currency: "USD".to_string(),
+                })
+                .await
+                .unwrap();
+            assert!(store
+                .add_transfer(user_id, Money(30), "card".into(), "usd".into())
+                .await
+                .is_err_and(|e| matches!(e, FinanceError::Validation(_))));
+            assert_eq!(balances().await, [70, 30, 0]);
+
+            let transfers = store.get_transfers(user_id, Period::All).await.unwrap();
+            assert_eq!(transfers.len(), 1);
+            assert_eq!(transfers[0].from_account, "card");
+            assert_eq!(transfers[0].to_account, "cash");
+
+            let stranger = setup_user(store, 0).await;
+            let res = store.del_transfer(stranger, transfers[0].id).await;
+            assert!(matches!(res, Err(FinanceError::NotOwner)));
+
+            store.del_transfer(user_id, transfers[0].id).await.unwrap();
+            assert_eq!(balances().await, [100, 0, 0]);
+        }
+    }
+
+    #[tokio::test]
+    async fn rates_and_base_currency_are_stored_per_user() {
+        for store in stores().await {
+            let store = &*store;
+            let user_id = test_user_id();
+
+            assert_eq!(store.get_base_currency(user_id).await.unwrap(), "RUB");
+            store
+                .set_base_currency(user_id, "USD".into())
+                .await
+                .unwrap();
+            assert_eq!(store.get_base_currency(user_id).await.unwrap(), "USD");
+
+            let rates = parse_rates_csv("USD,RUB,90 EUR,RUB,100").unwrap();
+            store.set_rates(user_id, &rates).await.unwrap();
+            let rates = parse_rates_csv("USD,RUB,92.5").unwrap();
+            store.set_rates(user_id, &rates).await.unwrap();
+
+            let stored = store.get_rates(user_id).await.unwrap();
+            let stored: Vec<_> = stored
+                .iter()
+                .map(|r| (r.from.as_str(), r.to.as_str(), r.rate))
+                .collect();
+            assert_eq!(stored, [("EUR", "RUB", 100.0), ("USD", "RUB", 92.5)]);
+            assert!(store.get_rates(test_user_id()).await.unwrap().is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn default_account_is_cleared_with_its_account() {
+        for store in stores().await {
+            let store = &*store;
+            let user_id = setup_user(store, 0).await;
+
+            assert_eq!(store.get_default_account(user_id).await.unwrap(), None);
+            let res = store.set_default_account(user_id, "cash".into()).await;
+            assert!(matches!(res, Err(FinanceError::UnknownAccount(_))));
+
+            store
+                .set_default_account(user_id, "card".into())
+                .await
+                .unwrap();
+            assert_eq!(
+                store.get_default_account(user_id).await.unwrap().as_deref(),
+                Some("card")
+            );
+
+            let id = store.get_accounts(user_id).await.unwrap()[0].id.unwrap();
+            store.del_account(user_id, id).await.unwrap();
+            assert_eq!(store.get_default_account(user_id).await.unwrap(), None);
+        }
+    }
+
+    #[tokio::test]
+    async fn dialogues_are_replaced_and_deleted() {
+        for store in stores().await {
+            let store = &*store;
+            let user_id = test_user_id();
+
+            assert_eq!(store.get_dialogue(user_id).await.unwrap(), None);
+            store.set_dialogue(user_id, "a".into()).await.unwrap();
+            store.set_dialogue(user_id, "b".into()).await.unwrap();
+            assert_eq!(
+                store.get_dialogue(user_id).await.unwrap().as_deref(),
+                Some("b")
+            );
+
+            store.del_dialogue(user_id).await.unwrap();
+            store.del_dialogue(user_id).await.unwrap();
+            assert_eq!(store.get_dialogue(user_id).await.unwrap(), None);
+        }
+    }
+
+    #[tokio::test]
+    async fn callback_payloads_are_kept_per_user() {
+        for store in stores().await {
+            let store = &*store;
+            let user_id = test_user_id();
+
+            let id = store
+                .save_callback_payload(user_id, "a".into())
+                .await
+                .unwrap();
+            assert_eq!(
+                store
+                    .save_callback_payload(user_id, "a".into())
+                    .await
+                    .unwrap(),
+                id
+            );
+            let other = store
+                .save_callback_payload(user_id, "b".into())
+                .await
+                .unwrap();
+            assert_ne!(other, id);
+
+            let stranger = test_user_id();
+            assert_eq!(
+                store.get_callback_payload(stranger, id).await.unwrap(),
+                None
+            );
+            assert_eq!(
+                store.take_callback_payload(stranger, id).await.unwrap(),
+                None
+            );
+            assert_eq!(
+                store
+                    .get_callback_payload(user_id, id)
+                    .await
+                    .unwrap()
+                    .as_deref(),
+                Some("a")
+            );
+
+            assert_eq!(
+                store
+                    .take_callback_payload(user_id, id)
+                    .await
+                    .unwrap()
+                    .as_deref(),
+                Some("a")
+            );
+            assert_eq!(
+                store.take_callback_payload(user_id, id).await.unwrap(),
+                None
+            );
+            assert_eq!(store.get_callback_payload(user_id, id).await.unwrap(), None);
+        }
+    }
+
+    #[tokio::test]
+    async fn budgets_count_current_period_expenses() {
+        for store in stores().await {
+            let store = &*store;
+            let user_id = setup_user(store, 100000).await;
+
+            let res = store
+                .set_budget(user_id, "unknown".into(), Money(100), BudgetPeriod::Month)
+                .await;
+            assert!(matches!(res, Err(FinanceError::UnknownCategory(_))));
+            store
+                .set_budget(user_id, "food".into(), Money(1000), BudgetPeriod::Month)
+                .await
+                .unwrap();
+
+            let last_year = NaiveDate::from_ymd_opt(2000, 1, 1);
+            store
+                .add_expense(user_id, Money(500), "food".into(), "card".into(), last_year)
+                .await
+                .unwrap();
+            store
+                .add_expense(user_id, Money(850), "food".into(), "card".into(), None)
+                .await
+                .unwrap();
+
+            let budgets = store
+                .get_budgets(user_id, Some("food".into()))
+                .await
+                .unwrap();
+            assert_eq!(budgets.len(), 1);
+            assert_eq!(budgets[0].spent, Money(850));
+            assert_eq!(budgets[0].percent(), 85);
+            assert_eq!(budgets[0].crossed_threshold(Money(850)), Some(80));
+
+            store
+                .add_account(Accounts {
+                    id: None,
+                    name: "usd".to_string(),
+                    balance: Money(100),
+                    user_id,
+                    currency: "USD".to_string(),
+                })
+                .await
+                .unwrap();
+            store
+                .add_expense(user_id, Money(1), "food".into(), "usd".into(), None)
+                .await
+                .unwrap();
+            let spent = || async {
+                let budgets = store.get_budgets(user_id, None).await.unwrap();
+                budgets[0].spent
+            };
+            // Dollars count once there is a rate to the base currency.
+            assert_eq!(spent().await, Money(850));
+            let rate = ExchangeRates {
+                from: "USD".to_string(),
+                to: "RUB".to_string(),
+                rate: 90.0,
+            };
+            store.set_rates(user_id, &[rate]).await.unwrap();
+            assert_eq!(spent().await, Money(940));
+        }
+    }
+
+    #[tokio::test]
+    async fn report_aggregates_period() {
+        for store in stores().await {
+            let store = &*store;
+            let user_id = setup_user(store, 0).await;
+            for name in ["cafe", "taxi"] {
+                store
+                    .add_category(Categories {
+                        id: None,
+                        name: name.to_string(),
+                        user_id,
+                        description: "test".to_string(),
+                    })
+                    .await
+                    .unwrap();
+            }
+
+            let day = |d| NaiveDate::from_ymd_opt(2026, 9, d);
+            let expenses = [(300, "food"), (100, "cafe"), (250, "cafe"), (50, "taxi")];
+            for (amount, category) in expenses {
+                store
+                    .add_expense(
+                        user_id,
+                        Money(amount),
+                        category.into(),
+                        "card".into(),
+                        day(10),
+                    )
+                    .await
+                    .unwrap();
+            }
+            store
+                .add_income(user_id, Money(1000), "food".into(), "card".into(), day(1))
+                .await
+                .unwrap();
+            store
+                .add_expense(
+                    user_id,
+                    Money(999),
+                    "taxi".into(),
+                    "card".into(),
+                    NaiveDate::from_ymd_opt(2026, 8, 31),
+                )
+                .await
+                .unwrap();
+
+            let report = store
+                .get_report(user_id, "2026-09".parse().unwrap())
+                .await
+                .unwrap();
+            assert_eq!(report.income, Money(1000));
+            assert_eq!(report.expenses, Money(700));
+            assert_eq!(report.net(), Money(300));
+            assert_eq!(
+                report.top_categories,
+                [
+                    ("cafe".to_string(), Money(350)),
+                    ("food".to_string(), Money(300)),
+                    ("taxi".to_string(), Money(50))
+                ]
+            );
+            assert_eq!(report.share(Money(350)), 50);
+
+            let previous = store
+                .get_report(user_id, "2026-08".parse().unwrap())
+                .await
+                .unwrap();
+            assert_eq!(previous.expenses, Money(999));
+        }
+    }
+
+    #[tokio::test]
+    async fn report_converts_currencies_to_the_base() {
+        for store in stores().await {
+            let store = &*store;
+            let user_id = setup_user(store, 0).await;
+            for currency in ["USD", "EUR"] {
+                store
+                    .add_account(Accounts {
+                        id: None,
+                        name: currency.to_lowercase(),
+                        balance: Money(0),
+                        user_id,
+                        currency: currency.to_string(),
+                    })
+                    .await
+                    .unwrap();
+            }
+            let rate = ExchangeRates {
+                from: "USD".to_string(),
+                to: "RUB".to_string(),
+                rate: 90.0,
+            };
+            store.set_rates(user_id, &[rate]).await.unwrap();
+
+            let day = NaiveDate::from_ymd_opt(2026, 9, 10);
+            let expenses = [(300, "card"), (10, "usd"), (7, "eur")];
+            for (amount, account) in expenses {
+                store
+                    .add_expense(user_id, Money(amount), "food".into(), account.into(), day)
+                    .await
+                    .unwrap();
+            }
+            store
+                .add_income(user_id, Money(20), "food".into(), "usd".into(), day)
+                .await
+                .unwrap();
+
+            let report = store
+                .get_report(user_id, "2026-09".parse().unwrap())
+                .await
+                .unwrap();
+            assert_eq!(report.income, Money(1800));
+            assert_eq!(report.expenses, Money(1200));
+            assert_eq!(report.top_categories, [("food".to_string(), Money(1200))]);
+            assert_eq!(report.missing_rates, ["EUR"]);
+        }
+    }
+
+    #[tokio::test]
+    async fn listings_are_paged() {
+        for store in stores().await {
+            let store = &*store;
+            let user_id = setup_user(store, 0).await;
+            for amount in 1..=5 {
+                store
+                    .add_expense(user_id, Money(amount), "food".into(), "card".into(), None)
+                    .await
+                    .unwrap();
+            }
+
+            let page = |number| Some(Page { number, size: 2 });
+            let amounts = |paged: Paged<Expenses>| {
+                let amounts: Vec<_> = paged.items.iter().map(|e| e.amount.0).collect();
+                (amounts, paged.total)
+            };
+            let get = |number| store.get_expense_page(user_id, Period::All, None, page(number));
+            assert_eq!(amounts(get(0).await.unwrap()), (vec![1, 2], 5));
+            assert_eq!(amounts(get(2).await.unwrap()), (vec![5], 5));
+            assert_eq!(amounts(get(3).await.unwrap()), (vec![], 0));
+
+            let accounts = store.get_accounts_page(user_id, page(0)).await.unwrap();
+            assert_eq!((accounts.items.len(), accounts.total), (1, 1));
+        }
+    }
+}
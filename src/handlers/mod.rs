@@ -1,264 +1,1365 @@
+pub mod backup;
+pub mod dialogue;
+pub mod duplicate;
+pub mod import;
 pub mod logic;
+pub mod names;
+pub mod pages;
+pub mod quick;
+pub mod recurring;
 
+use backup::*;
+use chrono::{Duration, Local};
+use duplicate::*;
+use futures::TryStreamExt;
+use import::*;
 use logic::*;
-use sqlx::postgres::PgPool;
+use names::*;
+use pages::*;
+use quick::*;
+use recurring::*;
 
-use teloxide::{prelude::*, utils::command::BotCommands};
+use teloxide::{
+    prelude::*,
+    types::{InlineKeyboardMarkup, InputFile, Me, ParseMode},
+    utils::command::{BotCommands, ParseError},
+};
 
-pub async fn help_handler(bot: Bot, msg: Message) -> ResponseResult<()> {
-    bot.send_message(msg.chat.id, Command::descriptions().to_string())
-        .await?;
-    Ok(())
+/// Message sent back to the user.
+pub struct Reply {
+    pub text: String,
+    /// Whether `text` is HTML rather than plain text.
+    pub html: bool,
+    pub keyboard: Option<InlineKeyboardMarkup>,
+    /// File sent with `text` as its caption.
+    pub document: Option<Document>,
+    /// PNG image sent with `text` as its caption.
+    pub photo: Option<Vec<u8>>,
 }
 
-pub const BOT_INFO: &str = "Привет. \nЯ бот, который поможет тебе управлять финансами. \nЧтобы узнать мои команды отправь /help";
+pub struct Document {
+    pub file_name: String,
+    pub bytes: Vec<u8>,
+}
 
-pub async fn start_handler(bot: Bot, msg: Message) -> ResponseResult<()> {
-    bot.send_message(msg.chat.id, BOT_INFO).await?;
-    Ok(())
+impl Reply {
+    pub fn document(text: String, file_name: &str, bytes: Vec<u8>) -> Self {
+        Reply {
+            document: Some(Document {
+                file_name: file_name.to_string(),
+                bytes,
+            }),
+            ..text.into()
+        }
+    }
+
+    pub fn photo(text: String, png: Vec<u8>) -> Self {
+        Reply {
+            photo: Some(png),
+            ..text.into()
+        }
+    }
 }
 
-pub async fn accounts_handler(bot: Bot, msg: Message, pool: PgPool) -> ResponseResult<()> {
-    let accounts = get_accounts(pool, msg.chat.id.0).await.unwrap();
-    for acc in accounts {
-        let text = format!(
-            "id: {id} name: {name} balance: {balance}",
-            id = acc.id.unwrap(),
-            name = acc.name,
-            balance = acc.balance
-        );
-        bot.send_message(msg.chat.id, text).await?;
+impl From<String> for Reply {
+    fn from(text: String) -> Self {
+        Reply {
+            text,
+            html: false,
+            keyboard: None,
+            document: None,
+            photo: None,
+        }
     }
+}
 
+impl From<&str> for Reply {
+    fn from(text: &str) -> Self {
+        text.to_string().into()
+    }
+}
+
+pub async fn send_reply(bot: &Bot, chat_id: ChatId, reply: Reply) -> ResponseResult<()> {
+    if let Some(document) = reply.document {
+        let file = InputFile::memory(document.bytes).file_name(document.file_name);
+        bot.send_document(chat_id, file).caption(reply.text).await?;
+        return Ok(());
+    }
+    if let Some(png) = reply.photo {
+        let file = InputFile::memory(png).file_name("chart.png");
+        bot.send_photo(chat_id, file).caption(reply.text).await?;
+        return Ok(());
+    }
+
+    let mut message = bot.send_message(chat_id, reply.text);
+    if reply.html {
+        message = message.parse_mode(ParseMode::Html);
+    }
+    if let Some(keyboard) = reply.keyboard {
+        message = message.reply_markup(keyboard);
+    }
+    message.await?;
     Ok(())
 }
 
-pub async fn categories_handler(bot: Bot, msg: Message, pool: PgPool) -> ResponseResult<()> {
-    let categories = get_categories(pool, msg.chat.id.0).await.unwrap();
-    for cat in categories {
-        let text = format!(
-            "id: {id} name: {name} description: {description}",
-            id = cat.id.unwrap(),
-            name = cat.name,
-            description = cat.description
-        );
-        bot.send_message(msg.chat.id, text).await?;
+/// Runs a command of the user and returns the messages to answer with.
+pub async fn handle(store: &dyn FinanceStore, user_id: i64, cmd: Command) -> Vec<Reply> {
+    let reply = match cmd {
+        Command::Help => help_handler(),
+
+        Command::Start => start_handler(),
+
+        Command::Accounts => accounts_handler(store, user_id).await,
+
+        Command::AddAccount {
+            name,
+            balance,
+            currency,
+        } => add_account_handler(store, user_id, name, balance, currency).await,
+
+        Command::Total => total_handler(store, user_id).await,
+
+        Command::AddCategory { name, description } => {
+            add_category_handler(store, user_id, name, description).await
+        }
+
+        Command::Categories => categories_handler(store, user_id).await,
+
+        Command::DelAccount(id) => del_account_handler(store, user_id, id).await,
+
+        Command::DelCategory(id) => del_category_handler(store, user_id, id).await,
+
+        Command::EditAccount { id, name, balance } => {
+            edit_account_handler(store, user_id, id, name, balance).await
+        }
+
+        Command::EditCategory {
+            id,
+            name,
+            description,
+        } => edit_category_handler(store, user_id, id, name, description).await,
+
+        Command::Expenses { period, tag } => expense_handler(store, user_id, period, tag).await,
+
+        Command::Income { period, tag } => income_handler(store, user_id, period, tag).await,
+
+        Command::AddExpense {
+            amount,
+            category,
+            account,
+            date,
+            note,
+        } => {
+            let entry = NewEntry {
+                amount,
+                category,
+                account,
+                date,
+                import_ref: None,
+                note,
+            };
+            return add_expense_handler(store, user_id, entry).await;
+        }
+        Command::AddIncome {
+            amount,
+            category,
+            account,
+            date,
+            note,
+        } => {
+            let entry = NewEntry {
+                amount,
+                category,
+                account,
+                date,
+                import_ref: None,
+                note,
+            };
+            add_income_handler(store, user_id, entry).await
+        }
+
+        Command::DelExp(id) => del_expense_handler(store, user_id, id).await,
+        Command::DelInc(id) => del_income_handler(store, user_id, id).await,
+
+        Command::Transfer { amount, from, to } => {
+            transfer_handler(store, user_id, amount, from, to).await
+        }
+        Command::Transfers(period) => transfers_handler(store, user_id, period).await,
+        Command::DelTransfer(id) => del_transfer_handler(store, user_id, id).await,
+
+        Command::SetRate { from, to, rate } => {
+            set_rate_handler(store, user_id, from, to, rate).await
+        }
+        Command::ImportRates(csv) => import_rates_handler(store, user_id, csv).await,
+        Command::Rates => rates_handler(store, user_id).await,
+        Command::BaseCurrency(currency) => base_currency_handler(store, user_id, currency).await,
+        Command::DefaultAccount(account) => default_account_handler(store, user_id, account).await,
+        Command::Alias(args) => alias_handler(store, user_id, args).await,
+        Command::DelAlias(alias) => del_alias_handler(store, user_id, alias).await,
+
+        Command::SetBudget {
+            category,
+            limit,
+            period,
+        } => set_budget_handler(store, user_id, category, limit, period).await,
+        Command::Budgets => budgets_handler(store, user_id).await,
+
+        Command::Report(period) => report_handler(store, user_id, period).await,
+
+        Command::Export { format, period } => export_handler(store, user_id, format, period).await,
+        Command::Chart { kind, period } => chart_handler(store, user_id, kind, period).await,
+        Command::ImportMapping(columns) => import_mapping_handler(store, user_id, columns).await,
+        Command::PayeeRule(args) => payee_rule_handler(store, user_id, args).await,
+        Command::DelPayeeRule(pattern) => del_payee_rule_handler(store, user_id, pattern).await,
+
+        Command::AddRecurring {
+            income,
+            amount,
+            category,
+            account,
+            schedule,
+        } => {
+            add_recurring_handler(store, user_id, income, amount, category, account, schedule).await
+        }
+        Command::Recurring => recurring_handler(store, user_id).await,
+        Command::DelRecurring(id) => del_recurring_handler(store, user_id, id).await,
+        Command::Backup => backup_handler(store, user_id).await,
+        Command::Restore => RESTORE_HELP.into(),
+    };
+
+    vec![reply]
+}
+
+/// Friendly text for an error returned by `handlers::logic`.
+pub fn error_message(e: FinanceError) -> String {
+    match e {
+        FinanceError::UnknownAccount(name) => {
+            format!("Аккаунт \"{name}\" не найден. Список аккаунтов: /accounts")
+        }
+        FinanceError::UnknownCategory(name) => {
+            format!("Категория \"{name}\" не найдена. Список категорий: /categories")
+        }
+        // Another user's row is reported as missing, so ids can't be probed.
+        FinanceError::NotFound | FinanceError::NotOwner => {
+            "Запись с таким id не найдена".to_string()
+        }
+        FinanceError::Validation(message) => format!("Неверные данные: {message}"),
+        FinanceError::Database(e) => {
+            log::error!("Database error: {e}");
+            "Не удалось обратиться к базе данных, попробуйте позже".to_string()
+        }
     }
+}
 
+/// Answer to a command that doesn't parse: what is wrong with it and how it
+/// is used. `None` for a command addressed to another bot.
+pub fn command_error_message(text: &str, e: ParseError) -> Option<String> {
+    let problem = match e {
+        ParseError::WrongBotName(_) => return None,
+        ParseError::UnknownCommand(command) => {
+            return Some(format!(
+                "Неизвестная команда {command}. Список команд: /help"
+            ))
+        }
+        ParseError::TooFewArguments { message, .. }
+        | ParseError::TooManyArguments { message, .. } => message,
+        ParseError::IncorrectFormat(e) | ParseError::Custom(e) => e.to_string(),
+    };
+    let command = text.split_whitespace().next().unwrap_or_default();
+    let command = command.split('@').next().unwrap_or_default().to_lowercase();
+    // The description of the command with its example, up to the next one.
+    let descriptions = Command::descriptions().to_string();
+    let usage: Vec<&str> = descriptions
+        .lines()
+        .skip_while(|line| line.split_whitespace().next() != Some(command.as_str()))
+        .enumerate()
+        .take_while(|(i, line)| *i == 0 || !line.starts_with('/'))
+        .map(|(_, line)| line)
+        .collect();
+    Some(format!(
+        "Команда не разобрана: {problem}\n{}",
+        usage.join("\n")
+    ))
+}
+
+/// Answers a command that `filter_command` dropped as unparsable.
+pub async fn command_error_handler(bot: Bot, msg: Message, me: Me) -> ResponseResult<()> {
+    let text = msg.text().unwrap_or_default();
+    if let Err(e) = Command::parse(text, me.username()) {
+        if let Some(text) = command_error_message(text, e) {
+            send_reply(&bot, msg.chat.id, text.into()).await?;
+        }
+    }
     Ok(())
 }
 
+pub fn help_handler() -> Reply {
+    Command::descriptions().to_string().into()
+}
+
+pub const BOT_INFO: &str = "Привет. \nЯ бот, который поможет тебе управлять финансами. \nЧтобы узнать мои команды отправь /help";
+
+pub fn start_handler() -> Reply {
+    BOT_INFO.into()
+}
+
+pub async fn accounts_handler(store: &dyn FinanceStore, user_id: i64) -> Reply {
+    listing_reply(store, user_id, Listing::Accounts, Period::All, None).await
+}
+
+pub async fn categories_handler(store: &dyn FinanceStore, user_id: i64) -> Reply {
+    listing_reply(store, user_id, Listing::Categories, Period::All, None).await
+}
+
 pub async fn add_account_handler(
-    bot: Bot,
-    msg: Message,
-    pool: PgPool,
+    store: &dyn FinanceStore,
+    user_id: i64,
     name: String,
-    balance: i64,
-) -> ResponseResult<()> {
-    let new_acc = Accounts {
-        id: None,
-        name: name,
-        balance: balance,
-        user_id: msg.chat.id.0,
+    balance: Money,
+    currency: Option<String>,
+) -> Reply {
+    let currency = match currency {
+        Some(currency) => Ok(currency),
+        None => store.get_base_currency(user_id).await,
     };
 
-    let text = match new_acc.add(pool).await {
-        Ok(()) => "Аккаунт успешно добавлен".to_string(),
-        Err(e) => format!("Произошла ошибка {e}"),
+    let text = match currency {
+        Ok(currency) => {
+            let new_acc = Accounts {
+                id: None,
+                name,
+                balance,
+                user_id,
+                currency,
+            };
+            match store.add_account(new_acc).await {
+                Ok(()) => "Аккаунт успешно добавлен".to_string(),
+                Err(e) => error_message(e),
+            }
+        }
+        Err(e) => error_message(e),
     };
-    bot.send_message(msg.chat.id, text).await?;
-    Ok(())
+    text.into()
 }
 
 pub async fn add_category_handler(
-    bot: Bot,
-    msg: Message,
-    pool: PgPool,
+    store: &dyn FinanceStore,
+    user_id: i64,
     name: String,
     description: String,
-) -> ResponseResult<()> {
+) -> Reply {
     let new_cat = Categories {
         id: None,
-        name: name,
-        user_id: msg.chat.id.0,
-        description: description,
+        name,
+        user_id,
+        description,
     };
 
-    let text = match new_cat.add(pool).await {
+    let text = match store.add_category(new_cat).await {
         Ok(()) => "Категория успешно добавлена".to_string(),
-        Err(e) => format!("Произошла ошибка {e}"),
+        Err(e) => error_message(e),
     };
-    bot.send_message(msg.chat.id, text).await?;
-    Ok(())
+    text.into()
+}
+
+pub async fn total_handler(store: &dyn FinanceStore, user_id: i64) -> Reply {
+    let text = match total_text(store, user_id).await {
+        Ok(text) => text,
+        Err(e) => error_message(e),
+    };
+    text.into()
 }
 
-pub async fn total_handler(bot: Bot, msg: Message, pool: PgPool) -> ResponseResult<()> {
-    let accounts = get_accounts(pool, msg.chat.id.0).await.unwrap();
-    let mut sum: i64 = 0;
-    for acc in accounts {
-        sum += acc.balance;
+async fn total_text(store: &dyn FinanceStore, user_id: i64) -> Result<String, FinanceError> {
+    let accounts = store.get_accounts(user_id).await?;
+    let rates = store.get_rates(user_id).await?;
+    let base = store.get_base_currency(user_id).await?;
+
+    let totals = totals(
+        accounts.into_iter().map(|acc| (acc.currency, acc.balance)),
+        &base,
+        &rates,
+    );
+    let mut text = String::new();
+    for (currency, sum) in &totals.by_currency {
+        text += &format!("{currency}: {sum}\n");
+    }
+    text += &format!(
+        "Общий баланс составляет {sum} {base}",
+        sum = totals.converted
+    );
+    if !totals.missing_rates.is_empty() {
+        text += &format!(
+            "\nНе учтены валюты без курса к {base}: {missing}. Задайте курс командой /setrate",
+            missing = totals.missing_rates.join(", ")
+        );
     }
-    let text = format!("Общий баланс составляет {sum}");
-    bot.send_message(msg.chat.id, text).await?;
 
-    Ok(())
+    Ok(text)
 }
 
-pub async fn del_account_handler(
-    bot: Bot,
-    msg: Message,
-    pool: PgPool,
-    id: i64,
-) -> ResponseResult<()> {
-    let text = match del_account(pool, id).await {
+pub async fn del_account_handler(store: &dyn FinanceStore, user_id: i64, id: i64) -> Reply {
+    let text = match store.del_account(user_id, id).await {
         Ok(()) => "Аккаунт успешно удален".to_string(),
-        Err(e) => format!("Произошла ошибка {e}"),
+        Err(FinanceError::NotFound) => "Аккаунт с таким id не найден".to_string(),
+        Err(e) => error_message(e),
     };
-    bot.send_message(msg.chat.id, text).await?;
-
-    Ok(())
+    text.into()
 }
 
-pub async fn del_category_handler(
-    bot: Bot,
-    msg: Message,
-    pool: PgPool,
-    id: i64,
-) -> ResponseResult<()> {
-    let text = match del_category(pool, id).await {
+pub async fn del_category_handler(store: &dyn FinanceStore, user_id: i64, id: i64) -> Reply {
+    let text = match store.del_category(user_id, id).await {
         Ok(()) => "Категория успешно удалена".to_string(),
-        Err(e) => format!("Произошла ошибка {e}"),
+        Err(FinanceError::NotFound) => "Категория с таким id не найдена".to_string(),
+        Err(e) => error_message(e),
     };
-    bot.send_message(msg.chat.id, text).await?;
-
-    Ok(())
+    text.into()
 }
 
 pub async fn edit_category_handler(
-    bot: Bot,
-    msg: Message,
-    pool: PgPool,
+    store: &dyn FinanceStore,
+    user_id: i64,
     id: i64,
     name: String,
     description: String,
-) -> ResponseResult<()> {
-    let text = match edit_category(pool, id, name, description).await {
+) -> Reply {
+    let text = match store.edit_category(user_id, id, name, description).await {
         Ok(()) => "Категория успешно изменена".to_string(),
-        Err(e) => format!("Произошла ошибка {e}"),
+        Err(FinanceError::NotFound) => "Категория с таким id не найдена".to_string(),
+        Err(e) => error_message(e),
     };
-    bot.send_message(msg.chat.id, text).await?;
-
-    Ok(())
+    text.into()
 }
 
 pub async fn edit_account_handler(
-    bot: Bot,
-    msg: Message,
-    pool: PgPool,
+    store: &dyn FinanceStore,
+    user_id: i64,
     id: i64,
     name: String,
-    balance: i64,
-) -> ResponseResult<()> {
-    let text = match edit_account(pool, id, name, balance).await {
+    balance: Money,
+) -> Reply {
+    let text = match store.edit_account(user_id, id, name, balance).await {
         Ok(()) => "Аккаунт успешно изменен".to_string(),
-        Err(e) => format!("Произошла ошибка {e}"),
+        Err(FinanceError::NotFound) => "Аккаунт с таким id не найден".to_string(),
+        Err(e) => error_message(e),
     };
-    bot.send_message(msg.chat.id, text).await?;
+    text.into()
+}
 
-    Ok(())
+pub async fn income_handler(
+    store: &dyn FinanceStore,
+    user_id: i64,
+    period: Period,
+    tag: Option<String>,
+) -> Reply {
+    listing_reply(store, user_id, Listing::Income, period, tag).await
 }
 
-pub async fn income_handler(bot: Bot, msg: Message, pool: PgPool) -> ResponseResult<()> {
-    let income = get_income(pool, msg.chat.id.0).await.unwrap();
-    for inc in income {
-        let text = format!(
-            "id: {id} account: {account} category: {category} amount: {amount} \n",
-            id = inc.id,
-            account = inc.account,
-            category = inc.category,
-            amount = inc.amount
-        );
-        bot.send_message(msg.chat.id, text).await?;
+pub async fn expense_handler(
+    store: &dyn FinanceStore,
+    user_id: i64,
+    period: Period,
+    tag: Option<String>,
+) -> Reply {
+    listing_reply(store, user_id, Listing::Expenses, period, tag).await
+}
+
+pub async fn add_expense_handler(
+    store: &dyn FinanceStore,
+    user_id: i64,
+    entry: NewEntry,
+) -> Vec<Reply> {
+    let since = Local::now() - Duration::minutes(DUPLICATE_WINDOW_MINUTES);
+    match store
+        .has_recent_duplicate(user_id, false, &entry, since)
+        .await
+    {
+        Ok(true) => duplicate_prompt(store, user_id, entry).await,
+        Ok(false) => book_expense(store, user_id, entry).await,
+        Err(e) => vec![error_message(e).into()],
     }
+}
 
-    Ok(())
+/// Adds the expense and warns when it pushes its category past a budget
+/// threshold.
+pub async fn book_expense(store: &dyn FinanceStore, user_id: i64, entry: NewEntry) -> Vec<Reply> {
+    if let Err(e) = store.add_entry(user_id, false, entry.clone()).await {
+        return vec![entry_error(store, user_id, false, &entry, e).await];
+    }
+
+    let mut replies = vec!["Расход успешно добавлен".into()];
+    if let Some(warning) = budget_warning(store, user_id, &entry).await {
+        replies.push(warning.into());
+    }
+    replies
 }
 
-pub async fn expense_handler(bot: Bot, msg: Message, pool: PgPool) -> ResponseResult<()> {
-    let expenses = get_expense(pool, msg.chat.id.0).await.unwrap();
-    for exp in expenses {
-        let text = format!(
-            "id: {id} account: {account} category: {category} amount: {amount} \n",
-            id = exp.id,
-            account = exp.account,
-            category = exp.category,
-            amount = exp.amount
-        );
-        bot.send_message(msg.chat.id, text).await?;
+/// Warning for an expense that pushed its category past a budget threshold.
+async fn budget_warning(
+    store: &dyn FinanceStore,
+    user_id: i64,
+    entry: &NewEntry,
+) -> Option<String> {
+    let budgets = match store
+        .get_budgets(user_id, Some(entry.category.clone()))
+        .await
+    {
+        Ok(budgets) => budgets,
+        Err(e) => {
+            log::error!("Failed to load budget: {e}");
+            return None;
+        }
+    };
+    let budget = budgets.into_iter().next()?;
+
+    // Expenses dated outside the current budget period don't count towards it.
+    if let Some(date) = entry.date {
+        let (from, to) = budget.period.period().bounds(Local::now().date_naive())?;
+        if date < from || date >= to {
+            return None;
+        }
     }
 
-    Ok(())
+    // The budget counts spending in the base currency, and leaves out an
+    // expense in a currency without a rate to it.
+    let amount = match base_amount(store, user_id, entry).await {
+        Ok(amount) => amount?,
+        Err(e) => {
+            log::error!("Failed to convert expense: {e}");
+            return None;
+        }
+    };
+    let threshold = budget.crossed_threshold(amount)?;
+    let text = if threshold >= 100 {
+        format!(
+            "⚠️ Бюджет категории {category} превышен: потрачено {spent} из {limit} ({percent}%)",
+            category = budget.category,
+            spent = budget.spent,
+            limit = budget.limit,
+            percent = budget.percent()
+        )
+    } else {
+        format!(
+            "⚠️ Бюджет категории {category} израсходован на {percent}%: потрачено {spent} из {limit}",
+            category = budget.category,
+            spent = budget.spent,
+            limit = budget.limit,
+            percent = budget.percent()
+        )
+    };
+    Some(text)
 }
 
-pub async fn add_expense_handler(
-    bot: Bot,
-    msg: Message,
-    pool: PgPool,
-    amount: i64,
-    category: String,
-    account: String,
-) -> ResponseResult<()> {
-    let text = match add_expense(pool, msg.chat.id.0, amount, category, account).await {
-        Ok(()) => "Расход успешно добавлен".to_string(),
-        Err(e) => format!("Произошла ошибка {e}"),
+/// Amount of the entry in the base currency, `None` without a rate to it.
+async fn base_amount(
+    store: &dyn FinanceStore,
+    user_id: i64,
+    entry: &NewEntry,
+) -> Result<Option<Money>, FinanceError> {
+    let (_, accounts) = named_rows(store, user_id).await?;
+    let account_id = resolve_name(&accounts, &entry.account);
+    let Some(account) = store
+        .get_accounts(user_id)
+        .await?
+        .into_iter()
+        .find(|acc| acc.id.is_some() && acc.id == account_id)
+    else {
+        return Ok(None);
     };
-    bot.send_message(msg.chat.id, text).await?;
-    Ok(())
+    let rates = store.get_rates(user_id).await?;
+    let base = store.get_base_currency(user_id).await?;
+    Ok(convert(entry.amount, &account.currency, &base, &rates))
+}
+
+pub async fn add_income_handler(store: &dyn FinanceStore, user_id: i64, entry: NewEntry) -> Reply {
+    match store.add_entry(user_id, true, entry.clone()).await {
+        Ok(_) => "Доход успешно добавлен".into(),
+        Err(e) => entry_error(store, user_id, true, &entry, e).await,
+    }
+}
+
+pub async fn del_income_handler(store: &dyn FinanceStore, user_id: i64, id: i64) -> Reply {
+    let text = match store.del_income(user_id, id).await {
+        Ok(()) => "Доход успешно удален".to_string(),
+        Err(FinanceError::NotFound) => "Доход с таким id не найден".to_string(),
+        Err(e) => error_message(e),
+    };
+    text.into()
+}
+
+pub async fn del_expense_handler(store: &dyn FinanceStore, user_id: i64, id: i64) -> Reply {
+    let text = match store.del_expense(user_id, id).await {
+        Ok(()) => "Расход успешно удален".to_string(),
+        Err(FinanceError::NotFound) => "Расход с таким id не найден".to_string(),
+        Err(e) => error_message(e),
+    };
+    text.into()
+}
+
+pub async fn transfers_handler(store: &dyn FinanceStore, user_id: i64, period: Period) -> Reply {
+    listing_reply(store, user_id, Listing::Transfers, period, None).await
+}
+
+pub async fn transfer_handler(
+    store: &dyn FinanceStore,
+    user_id: i64,
+    amount: Money,
+    from: String,
+    to: String,
+) -> Reply {
+    let text = match store.add_transfer(user_id, amount, from, to).await {
+        Ok(()) => "Перевод успешно выполнен".to_string(),
+        Err(e) => error_message(e),
+    };
+    text.into()
+}
+
+pub async fn del_transfer_handler(store: &dyn FinanceStore, user_id: i64, id: i64) -> Reply {
+    let text = match store.del_transfer(user_id, id).await {
+        Ok(()) => "Перевод успешно удален".to_string(),
+        Err(FinanceError::NotFound) => "Перевод с таким id не найден".to_string(),
+        Err(e) => error_message(e),
+    };
+    text.into()
+}
+
+pub async fn set_rate_handler(
+    store: &dyn FinanceStore,
+    user_id: i64,
+    from: String,
+    to: String,
+    rate: f64,
+) -> Reply {
+    let rates = [ExchangeRates { from, to, rate }];
+    let text = match store.set_rates(user_id, &rates).await {
+        Ok(()) => "Курс успешно сохранен".to_string(),
+        Err(e) => error_message(e),
+    };
+    text.into()
 }
 
-pub async fn add_income_handler(
-    bot: Bot,
-    msg: Message,
-    pool: PgPool,
-    amount: i64,
+pub async fn import_rates_handler(store: &dyn FinanceStore, user_id: i64, csv: String) -> Reply {
+    let text = match parse_rates_csv(&csv) {
+        Ok(rates) if rates.is_empty() => "Нет курсов для импорта".to_string(),
+        Ok(rates) => match store.set_rates(user_id, &rates).await {
+            Ok(()) => format!("Импортировано курсов: {}", rates.len()),
+            Err(e) => error_message(e),
+        },
+        Err(e) => error_message(e.into()),
+    };
+    text.into()
+}
+
+pub async fn rates_handler(store: &dyn FinanceStore, user_id: i64) -> Reply {
+    match store.get_rates(user_id).await {
+        Ok(rates) if rates.is_empty() => "Курсы не заданы, добавьте /setrate USD RUB 92.5".into(),
+        Ok(rates) => {
+            let mut text = String::new();
+            for rate in rates {
+                text += &format!("1 {} = {} {}\n", rate.from, rate.rate, rate.to);
+            }
+            text.into()
+        }
+        Err(e) => error_message(e).into(),
+    }
+}
+
+pub async fn base_currency_handler(
+    store: &dyn FinanceStore,
+    user_id: i64,
+    currency: String,
+) -> Reply {
+    let text = match parse_currency(&currency) {
+        Ok(currency) => match store.set_base_currency(user_id, currency).await {
+            Ok(()) => "Базовая валюта успешно изменена".to_string(),
+            Err(e) => error_message(e),
+        },
+        Err(e) => error_message(e.into()),
+    };
+    text.into()
+}
+
+pub async fn set_budget_handler(
+    store: &dyn FinanceStore,
+    user_id: i64,
     category: String,
-    account: String,
-) -> ResponseResult<()> {
-    let text = match add_income(pool, msg.chat.id.0, amount, category, account).await {
-        Ok(()) => "Доход успешно добавлен".to_string(),
-        Err(e) => format!("Произошла ошибка {e}"),
+    limit: Money,
+    period: BudgetPeriod,
+) -> Reply {
+    let text = match store.set_budget(user_id, category, limit, period).await {
+        Ok(()) => "Бюджет успешно установлен".to_string(),
+        Err(e) => error_message(e),
     };
-    bot.send_message(msg.chat.id, text).await?;
-    Ok(())
+    text.into()
 }
 
+pub async fn budgets_handler(store: &dyn FinanceStore, user_id: i64) -> Reply {
+    match store.get_budgets(user_id, None).await {
+        Ok(budgets) if budgets.is_empty() => {
+            "Бюджеты не заданы. Установить: /setbudget <категория> <сумма> [month|week]".into()
+        }
+        Ok(budgets) => {
+            let mut text = String::new();
+            for budget in budgets {
+                text += &format!(
+                    "{category} ({period}): {spent} из {limit} ({percent}%)\n",
+                    category = budget.category,
+                    period = budget.period.as_str(),
+                    spent = budget.spent,
+                    limit = budget.limit,
+                    percent = budget.percent()
+                );
+            }
+            text.into()
+        }
+        Err(e) => error_message(e).into(),
+    }
+}
 
-pub async fn del_income_handler(
-    bot: Bot,
-    msg: Message,
-    pool: PgPool,
-    id: i64,
-) -> ResponseResult<()> {
-    let text = match del_income(pool, id).await {
-        Ok(()) => "Доход успешно удален".to_string(),
-        Err(e) => format!("Произошла ошибка {e}"),
+pub async fn report_handler(store: &dyn FinanceStore, user_id: i64, period: String) -> Reply {
+    let period = match period.trim() {
+        "" => Ok(Period::Month),
+        period => period.parse::<Period>(),
     };
-    bot.send_message(msg.chat.id, text).await?;
+    let text = match period {
+        Ok(period) => report_text(store, user_id, period)
+            .await
+            .unwrap_or_else(error_message),
+        Err(e) => error_message(e.into()),
+    };
+    text.into()
+}
 
-    Ok(())
+pub async fn export_handler(
+    store: &dyn FinanceStore,
+    user_id: i64,
+    format: ExportFormat,
+    period: Period,
+) -> Reply {
+    let file = match format {
+        ExportFormat::Csv => write_csv(store.ledger(user_id, period)).await,
+        ExportFormat::Ledger | ExportFormat::Beancount => match store.get_accounts(user_id).await {
+            Ok(accounts) => {
+                let entries = store.ledger(user_id, Period::All);
+                write_journal(format, &accounts, period, entries).await
+            }
+            Err(e) => Err(e),
+        },
+    };
+    match file {
+        Ok(bytes) => Reply::document(
+            format!("Операции за период {period}"),
+            format.file_name(),
+            bytes,
+        ),
+        Err(e) => error_message(e).into(),
+    }
 }
 
-pub async fn del_expense_handler(
-    bot: Bot,
-    msg: Message,
-    pool: PgPool,
-    id: i64,
-) -> ResponseResult<()> {
-    let text = match del_expense(pool, id).await {
-        Ok(()) => "Расход успешно удален".to_string(),
-        Err(e) => format!("Произошла ошибка {e}"),
+pub async fn chart_handler(
+    store: &dyn FinanceStore,
+    user_id: i64,
+    kind: ChartKind,
+    period: Period,
+) -> Reply {
+    let chart = async {
+        let rates = store.get_rates(user_id).await?;
+        let base = store.get_base_currency(user_id).await?;
+        let (title, png) = match kind {
+            ChartKind::Pie => {
+                let entries: Vec<LedgerEntry> = store.ledger(user_id, period).try_collect().await?;
+                let slices = spending_by_category(&entries, &base, &rates);
+                if slices.is_empty() {
+                    return Ok(None);
+                }
+                let title = format!("Расходы по категориям за период {period}, {base}");
+                let png = render_pie(&title, &slices);
+                (title, png)
+            }
+            ChartKind::Line => {
+                let accounts = store.get_accounts(user_id).await?;
+                let balances = accounts.into_iter().map(|acc| (acc.currency, acc.balance));
+                let balance = totals(balances, &base, &rates).converted;
+                // Balances are counted back from today through all later operations.
+                let entries: Vec<LedgerEntry> =
+                    store.ledger(user_id, Period::All).try_collect().await?;
+                let today = Local::now().date_naive();
+                let days = daily_series(&entries, balance, period, today, &base, &rates);
+                if days.is_empty() {
+                    return Ok(None);
+                }
+                let title = format!("Баланс и расходы за период {period}, {base}");
+                let png = render_line(&title, &days);
+                (title, png)
+            }
+        };
+        Ok::<_, FinanceError>(Some((title, png)))
     };
-    bot.send_message(msg.chat.id, text).await?;
+    match chart.await {
+        Ok(Some((title, Ok(png)))) => Reply::photo(title, png),
+        Ok(Some((_, Err(e)))) => {
+            log::error!("Failed to draw a chart: {e}");
+            "Не удалось построить график".into()
+        }
+        Ok(None) => format!("Нет данных за период {period}").into(),
+        Err(e) => error_message(e).into(),
+    }
+}
 
-    Ok(())
-}
\ No newline at end of file
+async fn report_text(
+    store: &dyn FinanceStore,
+    user_id: i64,
+    period: Period,
+) -> Result<String, FinanceError> {
+    let report = store.get_report(user_id, period).await?;
+
+    let mut text = format!(
+        "Доходы: {income}\nРасходы: {expenses}\nСбережения: {net}\n",
+        income = report.income,
+        expenses = report.expenses,
+        net = report.net()
+    );
+    if !report.top_categories.is_empty() {
+        text += "\nТоп категорий расходов:\n";
+        for (category, amount) in &report.top_categories {
+            text += &format!(
+                "{category}: {amount} ({share}%)\n",
+                share = report.share(*amount)
+            );
+        }
+    }
+    if !report.missing_rates.is_empty() {
+        let base = store.get_base_currency(user_id).await?;
+        text += &format!(
+            "\nНе учтены валюты без курса к {base}: {missing}. Задайте курс командой /setrate\n",
+            missing = report.missing_rates.join(", ")
+        );
+    }
+
+    if let Some(previous) = period.previous(Local::now().date_naive()) {
+        let prev = store.get_report(user_id, previous).await?;
+        let change = |current, previous| match change_percent(current, previous) {
+            Some(change) => format!("{previous} ({change:+}%)"),
+            None => format!("{previous}"),
+        };
+        text += &format!(
+            "\nПредыдущий период:\nДоходы: {income}\nРасходы: {expenses}\nСбережения: {net}",
+            income = change(report.income, prev.income),
+            expenses = change(report.expenses, prev.expenses),
+            net = change(report.net(), prev.net())
+        );
+    }
+
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use logic::store::tests::test_user_id;
+
+    /// Parses `text` as Telegram delivers it and runs the command against `store`.
+    async fn run(store: &MemoryStore, user_id: i64, text: &str) -> Vec<String> {
+        let cmd = Command::parse(text, "finance_bot").unwrap();
+        handle(store, user_id, cmd)
+            .await
+            .into_iter()
+            .map(|reply| reply.text)
+            .collect()
+    }
+
+    /// Callback data of the `column`th button in the first row under `reply`.
+    fn callback_data(reply: &Reply, column: usize) -> String {
+        let keyboard = reply.keyboard.as_ref().expect("buttons");
+        match &keyboard.inline_keyboard[0][column].kind {
+            teloxide::types::InlineKeyboardButtonKind::CallbackData(data) => data.clone(),
+            kind => panic!("unexpected button {kind:?}"),
+        }
+    }
+
+    async fn run_one(store: &MemoryStore, user_id: i64, text: &str) -> String {
+        let mut replies = run(store, user_id, text).await;
+        assert_eq!(replies.len(), 1, "{text}: {replies:?}");
+        replies.remove(0)
+    }
+
+    /// A user with a `card` account holding 100.00 and a `food` category.
+    async fn setup(store: &MemoryStore) -> i64 {
+        let user_id = test_user_id();
+        run_one(store, user_id, "/addaccount card 100").await;
+        run_one(store, user_id, "/addcategory food еда").await;
+        user_id
+    }
+
+    async fn balance(store: &MemoryStore, user_id: i64, name: &str) -> Money {
+        let accounts = store.get_accounts(user_id).await.unwrap();
+        accounts
+            .iter()
+            .find(|acc| acc.name == name)
+            .unwrap()
+            .balance
+    }
+
+    #[tokio::test]
+    async fn start_and_help() {
+        let store = MemoryStore::default();
+        assert_eq!(run_one(&store, 1, "/start").await, BOT_INFO);
+        assert!(run_one(&store, 1, "/help").await.contains("/addexpense"));
+    }
+
+    #[tokio::test]
+    async fn account_commands() {
+        let store = MemoryStore::default();
+        let user_id = test_user_id();
+
+        assert_eq!(
+            run_one(&store, user_id, "/addaccount card 100.50").await,
+            "Аккаунт успешно добавлен"
+        );
+        run_one(&store, user_id, "/addaccount wallet 20 usd").await;
+        let accounts = run_one(&store, user_id, "/accounts").await;
+        assert!(accounts.contains("card   | 100.50  | RUB"), "{accounts}");
+        assert!(accounts.contains("wallet | 20.00   | USD"), "{accounts}");
+
+        let id = store.get_accounts(user_id).await.unwrap()[0].id.unwrap();
+        assert_eq!(
+            run_one(&store, user_id, &format!("/editaccount {id} cash 50")).await,
+            "Аккаунт успешно изменен"
+        );
+        assert_eq!(balance(&store, user_id, "cash").await, Money(5000));
+        assert_eq!(
+            run_one(&store, test_user_id(), &format!("/delaccount {id}")).await,
+            "Запись с таким id не найдена"
+        );
+        assert_eq!(
+            run_one(&store, user_id, &format!("/delaccount {id}")).await,
+            "Аккаунт успешно удален"
+        );
+        assert_eq!(
+            run_one(&store, user_id, &format!("/delaccount {id}")).await,
+            "Аккаунт с таким id не найден"
+        );
+    }
+
+    #[tokio::test]
+    async fn category_commands() {
+        let store = MemoryStore::default();
+        let user_id = test_user_id();
+
+        assert_eq!(
+            run_one(&store, user_id, "/addcategory food еда").await,
+            "Категория успешно добавлена"
+        );
+        assert!(run_one(&store, user_id, "/categories")
+            .await
+            .contains("food | еда"));
+
+        let id = store.get_categories(user_id).await.unwrap()[0].id.unwrap();
+        assert_eq!(
+            run_one(&store, user_id, &format!("/editcategory {id} cafe кафе")).await,
+            "Категория успешно изменена"
+        );
+        assert!(run_one(&store, user_id, "/categories")
+            .await
+            .contains("cafe | кафе"));
+        assert_eq!(
+            run_one(&store, user_id, &format!("/delcategory {id}")).await,
+            "Категория успешно удалена"
+        );
+        assert_eq!(run_one(&store, user_id, "/categories").await, "Список пуст");
+        assert_eq!(
+            run_one(&store, user_id, &format!("/delcategory {id}")).await,
+            "Категория с таким id не найдена"
+        );
+    }
+
+    #[tokio::test]
+    async fn expense_and_income_commands() {
+        let store = MemoryStore::default();
+        let user_id = setup(&store).await;
+
+        assert_eq!(
+            run(&store, user_id, "/addexpense 40,50 food card").await,
+            ["Расход успешно добавлен"]
+        );
+        assert_eq!(
+            run_one(&store, user_id, "/addincome 10 food card 2026-09-01").await,
+            "Доход успешно добавлен"
+        );
+        assert_eq!(balance(&store, user_id, "card").await, Money(6950));
+
+        assert!(run_one(&store, user_id, "/expenses")
+            .await
+            .contains("card    | food     | 40.50"));
+        assert!(run_one(&store, user_id, "/income 2026-09")
+            .await
+            .contains("2026-09-01 | card    | food     | 10.00"));
+        assert_eq!(
+            run_one(&store, user_id, "/income 2026-08").await,
+            "Список пуст"
+        );
+
+        assert_eq!(
+            run(&store, user_id, "/addexpense 5 taxi card").await,
+            ["Категория \"taxi\" не найдена. Список категорий: /categories"]
+        );
+        assert_eq!(
+            run_one(&store, user_id, "/addincome 5 food bank").await,
+            "Аккаунт \"bank\" не найден. Список аккаунтов: /accounts"
+        );
+        assert!(run_one(&store, user_id, "/delaccount 1")
+            .await
+            .starts_with("Неверные данные: по аккаунту есть операции"));
+
+        let exp_id = store.get_expense(user_id, Period::All).await.unwrap()[0].id;
+        let inc_id = store.get_income(user_id, Period::All).await.unwrap()[0].id;
+        assert_eq!(
+            run_one(&store, user_id, &format!("/delexp {exp_id}")).await,
+            "Расход успешно удален"
+        );
+        assert_eq!(
+            run_one(&store, user_id, &format!("/delinc {inc_id}")).await,
+            "Доход успешно удален"
+        );
+        assert_eq!(
+            run_one(&store, user_id, &format!("/delinc {inc_id}")).await,
+            "Доход с таким id не найден"
+        );
+        assert_eq!(balance(&store, user_id, "card").await, Money(10000));
+    }
+
+    #[tokio::test]
+    async fn notes_and_tags_of_expenses() {
+        let store = MemoryStore::default();
+        let user_id = setup(&store).await;
+
+        assert_eq!(
+            run(
+                &store,
+                user_id,
+                "/addexpense 25 food card 2026-09-01 ужин с клиентом #Work"
+            )
+            .await,
+            ["Расход успешно добавлен"]
+        );
+        run(&store, user_id, "/addexpense 10 food card такси #trip").await;
+        // The confirm button books the repeated expense with its note.
+        let cmd = Command::parse("/addexpense 10 food card такси #trip", "finance_bot").unwrap();
+        let prompt = handle(&store, user_id, cmd).await.remove(0);
+        assert!(prompt
+            .text
+            .starts_with("Расход 10.00 food с card уже записан"));
+        let data = callback_data(&prompt, 0);
+        assert_eq!(
+            duplicate_answer(&store, user_id, &data).await[0].text,
+            "Расход успешно добавлен"
+        );
+        let trip = run_one(&store, user_id, "/expenses #trip").await;
+        assert_eq!(trip.matches("такси #trip").count(), 2, "{trip}");
+        run_one(&store, user_id, "/addincome 5 food card 2026-09-02 #work").await;
+
+        let expenses = run_one(&store, user_id, "/expenses").await;
+        assert!(
+            expenses.contains("| 25.00  | ужин с клиентом #work"),
+            "{expenses}"
+        );
+        let work = run_one(&store, user_id, "/expenses #work").await;
+        assert!(work.contains("ужин с клиентом #work"), "{work}");
+        assert!(!work.contains("такси"), "{work}");
+        assert!(run_one(&store, user_id, "/expenses 2026-09 #trip")
+            .await
+            .contains("Список пуст"));
+        assert!(run_one(&store, user_id, "/income #work")
+            .await
+            .contains("| 5.00   | #work"));
+        assert!(Command::parse("/expenses #a.b", "finance_bot").is_err());
+    }
+
+    #[tokio::test]
+    async fn repeated_expense_asks_for_confirmation() {
+        let store = MemoryStore::default();
+        let user_id = setup(&store).await;
+        run(&store, user_id, "/addexpense 10 food card").await;
+
+        let cmd = Command::parse("/addexpense 10 food card", "finance_bot").unwrap();
+        let prompt = handle(&store, user_id, cmd).await.remove(0);
+        assert!(prompt
+            .text
+            .starts_with("Расход 10.00 food с card уже записан"));
+        let (data, cancel) = (callback_data(&prompt, 0), callback_data(&prompt, 1));
+        assert_eq!(balance(&store, user_id, "card").await, Money(9000));
+
+        let replies = duplicate_answer(&store, user_id, &data).await;
+        assert_eq!(replies[0].text, "Расход успешно добавлен");
+        assert_eq!(balance(&store, user_id, "card").await, Money(8000));
+        // A second press of either button books nothing more.
+        for data in [&data, &cancel] {
+            let replies = duplicate_answer(&store, user_id, data).await;
+            assert_eq!(replies[0].text, "Расход не записан");
+        }
+        assert_eq!(balance(&store, user_id, "card").await, Money(8000));
+
+        // Another amount or an earlier day is not a duplicate.
+        assert_eq!(
+            run(&store, user_id, "/addexpense 11 food card").await,
+            ["Расход успешно добавлен"]
+        );
+        assert_eq!(
+            run(&store, user_id, "/addexpense 10 food card 2026-09-01").await,
+            ["Расход успешно добавлен"]
+        );
+    }
+
+    #[tokio::test]
+    async fn transfer_commands() {
+        let store = MemoryStore::default();
+        let user_id = setup(&store).await;
+        run_one(&store, user_id, "/addaccount cash 0").await;
+
+        assert_eq!(
+            run_one(&store, user_id, "/transfer 30 card cash").await,
+            "Перевод успешно выполнен"
+        );
+        assert_eq!(
+            run_one(&store, user_id, "/transfer 30 card card").await,
+            "Неверные данные: нельзя перевести деньги на тот же аккаунт"
+        );
+        assert!(run_one(&store, user_id, "/transfers week")
+            .await
+            .contains("card | cash | 30.00"));
+
+        let id = store.get_transfers(user_id, Period::All).await.unwrap()[0].id;
+        assert_eq!(
+            run_one(&store, user_id, &format!("/deltransfer {id}")).await,
+            "Перевод успешно удален"
+        );
+        assert_eq!(
+            run_one(&store, user_id, &format!("/deltransfer {id}")).await,
+            "Перевод с таким id не найден"
+        );
+        assert_eq!(balance(&store, user_id, "card").await, Money(10000));
+        assert_eq!(balance(&store, user_id, "cash").await, Money::ZERO);
+    }
+
+    #[tokio::test]
+    async fn quoted_names_may_contain_spaces() {
+        let store = MemoryStore::default();
+        let user_id = setup(&store).await;
+
+        run_one(&store, user_id, r#"/addaccount "сбер вклад" 0"#).await;
+        assert_eq!(
+            run_one(
+                &store,
+                user_id,
+                r#"/addcategory «кафе у дома» обеды и "кофе" с собой"#
+            )
+            .await,
+            "Категория успешно добавлена"
+        );
+        assert!(run_one(&store, user_id, "/categories")
+            .await
+            .contains(r#"кафе у дома | обеды и "кофе" с собой"#));
+        assert_eq!(
+            run(&store, user_id, r#"/addexpense 10 "кафе у дома" card"#).await,
+            ["Расход успешно добавлен"]
+        );
+        assert_eq!(
+            run_one(&store, user_id, r#"/transfer 30 card "сбер вклад""#).await,
+            "Перевод успешно выполнен"
+        );
+        assert_eq!(balance(&store, user_id, "сбер вклад").await, Money(3000));
+
+        let id = store.get_accounts(user_id).await.unwrap()[1].id.unwrap();
+        let edit = format!(r#"/editaccount {id} "вклад 2" 30"#);
+        assert_eq!(
+            run_one(&store, user_id, &edit).await,
+            "Аккаунт успешно изменен"
+        );
+        assert_eq!(balance(&store, user_id, "вклад 2").await, Money(3000));
+
+        // A mistyped date isn't taken for the note.
+        let text = "/addexpense 100 food card 2026-13-01";
+        let e = Command::parse(text, "finance_bot").err().unwrap();
+        assert!(command_error_message(text, e)
+            .unwrap()
+            .starts_with("Команда не разобрана: неверная дата \"2026-13-01\""));
+
+        // Without a closing quote or a description the command doesn't parse.
+        assert!(Command::parse(r#"/addexpense 10 "кафе у дома card"#, "finance_bot").is_err());
+        assert!(Command::parse("/addcategory кафе", "finance_bot").is_err());
+    }
+
+    #[test]
+    fn unparsable_commands_get_the_error_and_usage() {
+        let error = |text: &str| {
+            let e = Command::parse(text, "finance_bot").err().unwrap();
+            command_error_message(text, e)
+        };
+
+        let text = error(r#"/transfer 10 card "сбер вклад"#).unwrap();
+        assert!(
+            text.starts_with("Команда не разобрана: не закрыта кавычка"),
+            "{text}"
+        );
+        assert!(
+            text.ends_with("example: /transfer 1000 tinkoff \"сбер вклад\""),
+            "{text}"
+        );
+        assert!(!text.contains("/transfers"), "{text}");
+
+        let text = error("/setrate@finance_bot USD RUB").unwrap();
+        assert!(text.contains("\n/setrate"), "{text}");
+        assert_eq!(
+            error("/spend 10").unwrap(),
+            "Неизвестная команда /spend. Список команд: /help"
+        );
+        assert_eq!(error("/total@other_bot"), None);
+    }
+
+    #[tokio::test]
+    async fn currency_commands() {
+        let store = MemoryStore::default();
+        let user_id = setup(&store).await;
+        run_one(&store, user_id, "/addaccount wallet 10 USD").await;
+
+        assert_eq!(
+            run_one(&store, user_id, "/rates").await,
+            "Курсы не заданы, добавьте /setrate USD RUB 92.5"
+        );
+        assert_eq!(
+            run_one(&store, user_id, "/setrate usd rub 90").await,
+            "Курс успешно сохранен"
+        );
+        assert_eq!(
+            run_one(&store, user_id, "/importrates EUR,RUB,100 GBP,RUB,110").await,
+            "Импортировано курсов: 2"
+        );
+        assert_eq!(
+            run_one(&store, user_id, "/rates").await,
+            "1 EUR = 100 RUB\n1 GBP = 110 RUB\n1 USD = 90 RUB\n"
+        );
+        assert_eq!(
+            run_one(&store, user_id, "/total").await,
+            "RUB: 100.00\nUSD: 10.00\nОбщий баланс составляет 1000.00 RUB"
+        );
+
+        assert_eq!(
+            run_one(&store, user_id, "/basecurrency usd").await,
+            "Базовая валюта успешно изменена"
+        );
+        assert!(run_one(&store, user_id, "/basecurrency рубль")
+            .await
+            .starts_with("Неверные данные"));
+        assert!(run_one(&store, user_id, "/total")
+            .await
+            .ends_with("Общий баланс составляет 11.11 USD"));
+    }
+
+    #[tokio::test]
+    async fn budget_commands() {
+        let store = MemoryStore::default();
+        let user_id = setup(&store).await;
+
+        assert_eq!(
+            run_one(&store, user_id, "/budgets").await,
+            "Бюджеты не заданы. Установить: /setbudget <категория> <сумма> [month|week]"
+        );
+        assert_eq!(
+            run_one(&store, user_id, "/setbudget food 50").await,
+            "Бюджет успешно установлен"
+        );
+        assert_eq!(
+            run_one(&store, user_id, "/setbudget taxi 50").await,
+            "Категория \"taxi\" не найдена. Список категорий: /categories"
+        );
+        let replies = run(&store, user_id, "/addexpense 45 food card").await;
+        assert_eq!(replies.len(), 2);
+        assert!(replies[1].contains("израсходован на 90%"), "{}", replies[1]);
+        assert_eq!(
+            run_one(&store, user_id, "/budgets").await,
+            "food (month): 45.00 из 50.00 (90%)\n"
+        );
+
+        // The limit is in the base currency, so is spending from a dollar account.
+        run_one(&store, user_id, "/addaccount wallet 10 usd").await;
+        run_one(&store, user_id, "/setrate usd rub 10").await;
+        let replies = run(&store, user_id, "/addexpense 0.5 food wallet").await;
+        assert_eq!(replies.len(), 2);
+        assert!(replies[1].contains("превышен"), "{}", replies[1]);
+        assert_eq!(
+            run_one(&store, user_id, "/budgets").await,
+            "food (month): 50.00 из 50.00 (100%)\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn report_command() {
+        let store = MemoryStore::default();
+        let user_id = setup(&store).await;
+        run(&store, user_id, "/addexpense 30 food card").await;
+        run_one(&store, user_id, "/addincome 50 food card").await;
+
+        let report = run_one(&store, user_id, "/report").await;
+        assert!(
+            report.starts_with("Доходы: 50.00\nРасходы: 30.00\nСбережения: 20.00\n"),
+            "{report}"
+        );
+        assert!(report.contains("food: 30.00 (100%)"), "{report}");
+        assert!(run_one(&store, user_id, "/report никогда")
+            .await
+            .starts_with("Неверные данные"));
+    }
+
+    #[tokio::test]
+    async fn export_command() {
+        let store = MemoryStore::default();
+        let user_id = setup(&store).await;
+        run_one(&store, user_id, "/addaccount cash 0").await;
+        run(&store, user_id, "/addexpense 30 food card 2026-09-15").await;
+        run_one(&store, user_id, "/addincome 50 food card 2026-09-10").await;
+        run_one(&store, user_id, "/addexpense 5 food card 2026-08-01").await;
+        run_one(&store, user_id, "/transfer 10 card cash").await;
+
+        let cmd = Command::parse("/export csv 2026-09", "finance_bot").unwrap();
+        let mut replies = handle(&store, user_id, cmd).await;
+        let document = replies.remove(0).document.unwrap();
+        assert_eq!(document.file_name, "finance.csv");
+        let csv = String::from_utf8(document.bytes).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 3, "{csv}");
+        assert!(lines[1].contains(",income,card,food,50.00,"), "{csv}");
+        assert!(lines[2].contains(",expense,card,food,30.00,"), "{csv}");
+
+        let cmd = Command::parse("/export csv", "finance_bot").unwrap();
+        let csv = handle(&store, user_id, cmd)
+            .await
+            .remove(0)
+            .document
+            .unwrap();
+        let csv = String::from_utf8(csv.bytes).unwrap();
+        assert_eq!(csv.lines().count(), 5, "{csv}");
+        assert!(csv.contains(",transfer,card,cash,10.00,"), "{csv}");
+
+        let cmd = Command::parse("/export ledger 2026-09", "finance_bot").unwrap();
+        let journal = handle(&store, user_id, cmd)
+            .await
+            .remove(0)
+            .document
+            .unwrap();
+        assert_eq!(journal.file_name, "finance.journal");
+        let journal = String::from_utf8(journal.bytes).unwrap();
+        // The August expense is in the opening balance, today's transfer is
+        // past the period.
+        assert!(
+            journal.contains("2026-09-01 Начальные остатки\n    Assets:card  95.00 RUB\n"),
+            "{journal}"
+        );
+        assert!(
+            journal.contains("2026-09-15 food\n    Expenses:food  30.00 RUB\n    Assets:card\n"),
+            "{journal}"
+        );
+        assert!(!journal.contains("Assets:cash  "), "{journal}");
+
+        assert!(Command::parse("/export xls", "finance_bot").is_err());
+    }
+}
@@ -0,0 +1,443 @@
+use super::logic::*;
+use super::{book_expense, error_message, send_reply, Reply};
+use teloxide::{
+    prelude::*,
+    types::{InlineKeyboardButton, InlineKeyboardMarkup},
+};
+
+/// Categories and accounts of the user with their aliases, to resolve typed
+/// names the way the store does.
+pub async fn named_rows(
+    store: &dyn FinanceStore,
+    user_id: i64,
+) -> Result<(Vec<NamedRow>, Vec<NamedRow>), FinanceError> {
+    let mut categories: Vec<NamedRow> = store
+        .get_categories(user_id)
+        .await?
+        .into_iter()
+        .filter_map(|cat| {
+            Some(NamedRow {
+                id: cat.id?,
+                name: cat.name,
+                alias: false,
+            })
+        })
+        .collect();
+    let mut accounts: Vec<NamedRow> = store
+        .get_accounts(user_id)
+        .await?
+        .into_iter()
+        .filter_map(|acc| {
+            Some(NamedRow {
+                id: acc.id?,
+                name: acc.name,
+                alias: false,
+            })
+        })
+        .collect();
+
+    for alias in store.get_aliases(user_id).await? {
+        let (rows, target) = match &alias.target {
+            AliasTarget::Category(name) => (&mut categories, name),
+            AliasTarget::Account(name) => (&mut accounts, name),
+        };
+        if let Some(id) = rows
+            .iter()
+            .find(|row| row.name == *target)
+            .map(|row| row.id)
+        {
+            rows.push(NamedRow {
+                id,
+                name: alias.alias,
+                alias: true,
+            });
+        }
+    }
+    Ok((categories, accounts))
+}
+
+/// Button of a "did you mean" reply: the names to retype an expense or
+/// income with. The entry waits in the store under `entry_id`, as its note
+/// wouldn't fit the 64 bytes of callback data.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SuggestedEntry {
+    pub income: bool,
+    pub entry_id: i64,
+    pub category_id: i64,
+    pub account_id: i64,
+}
+
+impl SuggestedEntry {
+    pub fn to_callback_data(self) -> String {
+        let kind = if self.income { "i" } else { "e" };
+        format!(
+            "fix:{kind}:{}:{}:{}",
+            self.entry_id, self.category_id, self.account_id
+        )
+    }
+
+    pub fn from_callback_data(data: &str) -> Option<Self> {
+        let mut parts = data.strip_prefix("fix:")?.splitn(4, ':');
+        let income = match parts.next()? {
+            "i" => true,
+            "e" => false,
+            _ => return None,
+        };
+        Some(SuggestedEntry {
+            income,
+            entry_id: parts.next()?.parse().ok()?,
+            category_id: parts.next()?.parse().ok()?,
+            account_id: parts.next()?.parse().ok()?,
+        })
+    }
+}
+
+/// Rows `name` may refer to with their edit distances: the one it names, or
+/// the closest ones when it names none.
+fn candidates<'a>(rows: &'a [NamedRow], name: &str) -> Vec<(&'a NamedRow, usize)> {
+    match resolve_name(rows, name) {
+        Some(id) => rows
+            .iter()
+            .filter(|row| row.id == id && !row.alias)
+            .map(|row| (row, 0))
+            .take(1)
+            .collect(),
+        None => closest_names(rows, name),
+    }
+}
+
+/// "Did you mean" reply for an expense or income whose category or account
+/// is a near miss, `None` when no name is close.
+pub async fn suggest_names(
+    store: &dyn FinanceStore,
+    user_id: i64,
+    income: bool,
+    entry: &NewEntry,
+) -> Result<Option<Reply>, FinanceError> {
+    let (categories, accounts) = named_rows(store, user_id).await?;
+    let category_known = resolve_name(&categories, &entry.category).is_some();
+    let account_known = resolve_name(&accounts, &entry.account).is_some();
+
+    let mut pairs = vec![];
+    for (category, category_distance) in candidates(&categories, &entry.category) {
+        for (account, account_distance) in candidates(&accounts, &entry.account) {
+            pairs.push((category, account, category_distance + account_distance));
+        }
+    }
+    pairs.sort_by_key(|(_, _, distance)| *distance);
+    pairs.truncate(SUGGESTIONS);
+    if pairs.is_empty() {
+        return Ok(None);
+    }
+    let payload = serde_json::to_string(entry).expect("entry serializes");
+    let entry_id = store.save_callback_payload(user_id, payload).await?;
+
+    let rows = pairs.into_iter().map(|(category, account, _)| {
+        let label = match (category_known, account_known) {
+            (false, false) => format!("{}, {}", category.name, account.name),
+            (false, true) => category.name.clone(),
+            _ => account.name.clone(),
+        };
+        let suggested = SuggestedEntry {
+            income,
+            entry_id,
+            category_id: category.id,
+            account_id: account.id,
+        };
+        [InlineKeyboardButton::callback(
+            label,
+            suggested.to_callback_data(),
+        )]
+    });
+
+    let mut text = String::new();
+    if !category_known {
+        text += &format!("Категория \"{}\" не найдена. ", entry.category);
+    }
+    if !account_known {
+        text += &format!("Аккаунт \"{}\" не найден. ", entry.account);
+    }
+    text += "Возможно, вы имели в виду:";
+    Ok(Some(Reply {
+        keyboard: Some(InlineKeyboardMarkup::new(rows)),
+        ..text.into()
+    }))
+}
+
+/// Answers an expense or income the store rejected, suggesting names when
+/// its category or account is a near miss.
+pub async fn entry_error(
+    store: &dyn FinanceStore,
+    user_id: i64,
+    income: bool,
+    entry: &NewEntry,
+    e: FinanceError,
+) -> Reply {
+    if !matches!(
+        e,
+        FinanceError::UnknownCategory(_) | FinanceError::UnknownAccount(_)
+    ) {
+        return error_message(e).into();
+    }
+    match suggest_names(store, user_id, income, entry).await {
+        Ok(Some(reply)) => reply,
+        Ok(None) => error_message(e).into(),
+        Err(suggest_error) => {
+            log::error!("Failed to suggest names: {suggest_error}");
+            error_message(e).into()
+        }
+    }
+}
+
+/// Answers a suggestion button by booking the entry with the suggested names.
+/// The entry is taken once, so only the first button pressed books it.
+pub async fn suggestion_answer(store: &dyn FinanceStore, user_id: i64, data: &str) -> Vec<Reply> {
+    let not_added = || vec!["Запись не добавлена".into()];
+    let Some(suggested) = SuggestedEntry::from_callback_data(data) else {
+        return not_added();
+    };
+
+    let names = async {
+        let accounts = store.get_accounts(user_id).await?;
+        let categories = store.get_categories(user_id).await?;
+        let account = accounts
+            .into_iter()
+            .find(|acc| acc.id == Some(suggested.account_id))
+            .ok_or(FinanceError::NotFound)?;
+        let category = categories
+            .into_iter()
+            .find(|cat| cat.id == Some(suggested.category_id))
+            .ok_or(FinanceError::NotFound)?;
+        Ok::<_, FinanceError>((account.name, category.name))
+    };
+    let (account, category) = match names.await {
+        Ok(names) => names,
+        Err(e) => return vec![error_message(e).into()],
+    };
+    let payload = match store
+        .take_callback_payload(user_id, suggested.entry_id)
+        .await
+    {
+        Ok(Some(payload)) => payload,
+        Ok(None) => return not_added(),
+        Err(e) => return vec![error_message(e).into()],
+    };
+    let entry = match serde_json::from_str::<NewEntry>(&payload) {
+        Ok(entry) => NewEntry {
+            category,
+            account,
+            ..entry
+        },
+        Err(e) => {
+            log::warn!("Dropping unreadable suggested entry of {user_id}: {e}");
+            return not_added();
+        }
+    };
+    if !suggested.income {
+        return book_expense(store, user_id, entry).await;
+    }
+    let text = match store.add_entry(user_id, true, entry).await {
+        Ok(_) => "Доход успешно добавлен".to_string(),
+        Err(e) => error_message(e),
+    };
+    vec![text.into()]
+}
+
+/// Handles the buttons under a "did you mean" reply by replacing it with the
+/// outcome.
+pub async fn suggestion_callback_handler(
+    bot: Bot,
+    q: CallbackQuery,
+    store: Store,
+) -> ResponseResult<()> {
+    if let (Some(data), Some(message)) = (q.data.as_deref(), &q.message) {
+        let mut replies = suggestion_answer(&*store, message.chat.id.0, data)
+            .await
+            .into_iter();
+        if let Some(first) = replies.next() {
+            bot.edit_message_text(message.chat.id, message.id, first.text)
+                .await?;
+        }
+        for reply in replies {
+            send_reply(&bot, message.chat.id, reply).await?;
+        }
+    }
+    bot.answer_callback_query(q.id).await?;
+
+    Ok(())
+}
+
+/// Lists the aliases without arguments, or sets `<alias> <name>`.
+pub async fn alias_handler(store: &dyn FinanceStore, user_id: i64, args: String) -> Reply {
+    let args = args.trim();
+    if args.is_empty() {
+        return match store.get_aliases(user_id).await {
+            Ok(aliases) if aliases.is_empty() => {
+                "Псевдонимов нет. Добавить: /alias кофе кафе".into()
+            }
+            Ok(aliases) => {
+                let mut text = "Псевдонимы:\n".to_string();
+                for alias in aliases {
+                    text += &format!("{} → {}\n", alias.alias, alias.target);
+                }
+                text.into()
+            }
+            Err(e) => error_message(e).into(),
+        };
+    }
+
+    let mut args = Args::new(args);
+    let alias = args.next_arg().ok().flatten().unwrap_or_default();
+    let name = args.rest();
+    if alias.is_empty() || name.is_empty() {
+        return "Укажите псевдоним и категорию или аккаунт, например: /alias кофе кафе".into();
+    }
+    match store.set_alias(user_id, alias.clone(), name).await {
+        Ok(target) => format!("Псевдоним {} → {target}", alias.to_lowercase()).into(),
+        Err(FinanceError::UnknownCategory(name)) => {
+            format!("Нет категории или аккаунта \"{name}\"").into()
+        }
+        Err(e) => error_message(e).into(),
+    }
+}
+
+pub async fn del_alias_handler(store: &dyn FinanceStore, user_id: i64, alias: String) -> Reply {
+    match store.del_alias(user_id, Args::new(&alias).rest()).await {
+        Ok(()) => "Псевдоним удалён".into(),
+        Err(FinanceError::NotFound) => "Псевдоним не найден".into(),
+        Err(e) => error_message(e).into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::logic::store::tests::{balance, setup_user};
+
+    fn buttons(reply: &Reply) -> Vec<(String, String)> {
+        let keyboard = reply.keyboard.as_ref().expect("buttons");
+        keyboard
+            .inline_keyboard
+            .iter()
+            .flatten()
+            .map(|button| match &button.kind {
+                teloxide::types::InlineKeyboardButtonKind::CallbackData(data) => {
+                    (button.text.clone(), data.clone())
+                }
+                kind => panic!("unexpected button {kind:?}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn suggested_entry_round_trips() {
+        let entry = SuggestedEntry {
+            income: true,
+            entry_id: 31,
+            category_id: 12,
+            account_id: 7,
+        };
+        let data = entry.to_callback_data();
+        assert_eq!(data, "fix:i:31:12:7");
+        assert_eq!(SuggestedEntry::from_callback_data(&data), Some(entry));
+        assert_eq!(SuggestedEntry::from_callback_data("fix:x:1:2:3"), None);
+        // Buttons sent before the entry was kept in the store.
+        assert_eq!(
+            SuggestedEntry::from_callback_data("fix:i:19990:12:7:2026-09-15"),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn near_misses_suggest_the_closest_names() {
+        let store = MemoryStore::default();
+        let user_id = setup_user(&store, 100000).await;
+
+        let entry = NewEntry {
+            amount: Money(20000),
+            category: "fod".to_string(),
+            account: "card".to_string(),
+            date: None,
+            import_ref: None,
+            note: Note::default(),
+        };
+        let error = FinanceError::UnknownCategory("fod".to_string());
+        let reply = entry_error(&store, user_id, false, &entry, error).await;
+        assert_eq!(
+            reply.text,
+            "Категория \"fod\" не найдена. Возможно, вы имели в виду:"
+        );
+        let buttons = buttons(&reply);
+        assert_eq!(buttons.len(), 1);
+        assert_eq!(buttons[0].0, "food");
+
+        let replies = suggestion_answer(&store, user_id, &buttons[0].1).await;
+        assert_eq!(replies[0].text, "Расход успешно добавлен");
+        assert_eq!(balance(&store, user_id).await, 100000 - 20000);
+        // Each reply books its entry once.
+        let replies = suggestion_answer(&store, user_id, &buttons[0].1).await;
+        assert_eq!(replies[0].text, "Запись не добавлена");
+        assert_eq!(balance(&store, user_id).await, 100000 - 20000);
+
+        let entry = NewEntry {
+            category: "зарплата".to_string(),
+            ..entry
+        };
+        let error = FinanceError::UnknownCategory("зарплата".to_string());
+        let reply = entry_error(&store, user_id, false, &entry, error).await;
+        assert!(reply.text.ends_with("Список категорий: /categories"));
+        assert!(reply.keyboard.is_none());
+    }
+
+    #[tokio::test]
+    async fn accepted_suggestions_keep_the_note() {
+        let store = MemoryStore::default();
+        let user_id = setup_user(&store, 100000).await;
+        let note = Note::parse("ужин #trip");
+
+        for income in [false, true] {
+            let entry = NewEntry {
+                amount: Money(1000),
+                category: "fod".to_string(),
+                account: "crd".to_string(),
+                date: None,
+                import_ref: None,
+                note: note.clone(),
+            };
+            let error = FinanceError::UnknownCategory("fod".to_string());
+            let reply = entry_error(&store, user_id, income, &entry, error).await;
+            let buttons = buttons(&reply);
+            assert_eq!(buttons[0].0, "food, card");
+            suggestion_answer(&store, user_id, &buttons[0].1).await;
+        }
+
+        let expenses = store.get_expense(user_id, Period::All).await.unwrap();
+        assert_eq!(expenses[0].note, note);
+        let income = store.get_income(user_id, Period::All).await.unwrap();
+        assert_eq!(income[0].note, note);
+        assert_eq!(income[0].category, "food");
+    }
+
+    #[tokio::test]
+    async fn aliases_are_listed_and_deleted() {
+        let store = MemoryStore::default();
+        let user_id = setup_user(&store, 0).await;
+
+        let reply = alias_handler(&store, user_id, "Еда food".to_string()).await;
+        assert_eq!(reply.text, "Псевдоним еда → категория food");
+        let reply = alias_handler(&store, user_id, "к card".to_string()).await;
+        assert_eq!(reply.text, "Псевдоним к → аккаунт card");
+        let reply = alias_handler(&store, user_id, "x нет".to_string()).await;
+        assert_eq!(reply.text, "Нет категории или аккаунта \"нет\"");
+
+        let reply = alias_handler(&store, user_id, String::new()).await;
+        assert_eq!(
+            reply.text,
+            "Псевдонимы:\nеда → категория food\nк → аккаунт card\n"
+        );
+        let reply = del_alias_handler(&store, user_id, "еда".to_string()).await;
+        assert_eq!(reply.text, "Псевдоним удалён");
+        let reply = del_alias_handler(&store, user_id, "еда".to_string()).await;
+        assert_eq!(reply.text, "Псевдоним не найден");
+    }
+}
@@ -0,0 +1,486 @@
+use super::logic::*;
+use super::{error_message, Reply};
+use std::sync::OnceLock;
+use teloxide::{
+    prelude::*,
+    types::{InlineKeyboardButton, InlineKeyboardMarkup, ParseMode},
+    utils::html,
+};
+
+pub const DEFAULT_PAGE_SIZE: i64 = 10;
+/// With cells cut to `MAX_CELL_CHARS`, keeps a page below Telegram's 4096
+/// characters message limit.
+pub const MAX_PAGE_SIZE: i64 = 20;
+/// Longest cell of a table, longer notes and descriptions are cut.
+pub const MAX_CELL_CHARS: usize = 32;
+
+/// Rows per listing page, taken from `PAGE_SIZE`.
+pub fn page_size() -> i64 {
+    static PAGE_SIZE: OnceLock<i64> = OnceLock::new();
+    *PAGE_SIZE.get_or_init(|| {
+        dotenv::var("PAGE_SIZE")
+            .ok()
+            .and_then(|size| size.parse::<i64>().ok())
+            .map_or(DEFAULT_PAGE_SIZE, |size| size.clamp(1, MAX_PAGE_SIZE))
+    })
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Listing {
+    Accounts,
+    Categories,
+    Expenses,
+    Income,
+    Transfers,
+}
+
+impl Listing {
+    fn code(&self) -> &'static str {
+        match self {
+            Listing::Accounts => "acc",
+            Listing::Categories => "cat",
+            Listing::Expenses => "exp",
+            Listing::Income => "inc",
+            Listing::Transfers => "tr",
+        }
+    }
+
+    fn from_code(code: &str) -> Option<Self> {
+        match code {
+            "acc" => Some(Listing::Accounts),
+            "cat" => Some(Listing::Categories),
+            "exp" => Some(Listing::Expenses),
+            "inc" => Some(Listing::Income),
+            "tr" => Some(Listing::Transfers),
+            _ => None,
+        }
+    }
+}
+
+/// Page of a listing, encoded into the callback data of the page buttons.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PageRequest {
+    pub listing: Listing,
+    pub period: Period,
+    /// Only the expenses or income with the tag saved under this id, as a
+    /// long tag wouldn't fit the callback data.
+    pub tag_id: Option<i64>,
+    pub number: i64,
+}
+
+impl PageRequest {
+    pub fn to_callback_data(self) -> String {
+        let mut data = format!(
+            "page:{listing}:{number}:{period}",
+            listing = self.listing.code(),
+            number = self.number,
+            period = self.period
+        );
+        if let Some(tag_id) = self.tag_id {
+            data += &format!(":{tag_id}");
+        }
+        data
+    }
+
+    pub fn from_callback_data(data: &str) -> Option<Self> {
+        let mut parts = data.strip_prefix("page:")?.splitn(4, ':');
+        let listing = Listing::from_code(parts.next()?)?;
+        let number = parts.next()?.parse().ok().filter(|n: &i64| *n >= 0)?;
+        let period = parts.next()?.parse().ok()?;
+        let tag_id = match parts.next() {
+            Some(tag_id) => Some(tag_id.parse().ok()?),
+            None => None,
+        };
+        Some(PageRequest {
+            listing,
+            period,
+            tag_id,
+            number,
+        })
+    }
+}
+
+/// Tag saved for the page buttons of a listing filtered by it.
+async fn saved_tag(
+    store: &dyn FinanceStore,
+    user_id: i64,
+    id: i64,
+) -> Result<String, FinanceError> {
+    let payload = store.get_callback_payload(user_id, id).await?;
+    payload
+        .as_deref()
+        .and_then(parse_tag)
+        .ok_or(FinanceError::NotFound)
+}
+
+/// `cell` cut to `MAX_CELL_CHARS` with an ellipsis.
+fn fit_cell(cell: &str) -> String {
+    if cell.chars().count() <= MAX_CELL_CHARS {
+        return cell.to_string();
+    }
+    let mut cut: String = cell.chars().take(MAX_CELL_CHARS - 1).collect();
+    cut.push('…');
+    cut
+}
+
+/// Lays rows out as a plain-text table with padded columns, cutting long
+/// cells.
+pub fn render_table(headers: &[&str], rows: &[Vec<String>]) -> String {
+    let rows: Vec<Vec<String>> = rows
+        .iter()
+        .map(|row| row.iter().map(|cell| fit_cell(cell)).collect())
+        .collect();
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.chars().count()).collect();
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let line = |cells: Vec<&str>| {
+        let padded: Vec<String> = cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{cell:<width$}"))
+            .collect();
+        padded.join(" | ").trim_end().to_string()
+    };
+
+    let mut lines = vec![line(headers.to_vec())];
+    lines.push(
+        widths
+            .iter()
+            .map(|width| "-".repeat(*width))
+            .collect::<Vec<_>>()
+            .join("-+-"),
+    );
+    for row in &rows {
+        lines.push(line(row.iter().map(String::as_str).collect()));
+    }
+    lines.join("\n")
+}
+
+async fn load_rows(
+    store: &dyn FinanceStore,
+    user_id: i64,
+    request: PageRequest,
+) -> Result<(&'static [&'static str], Vec<Vec<String>>, i64), FinanceError> {
+    let page = Some(Page {
+        number: request.number,
+        size: page_size(),
+    });
+    let period = request.period;
+    let tag = match request.tag_id {
+        Some(id) => Some(saved_tag(store, user_id, id).await?),
+        None => None,
+    };
+    match request.listing {
+        Listing::Accounts => store.get_accounts_page(user_id, page).await.map(|p| {
+            let rows = p.items.into_iter().map(|acc| {
+                vec![
+                    acc.id.unwrap_or_default().to_string(),
+                    acc.name,
+                    acc.balance.to_string(),
+                    acc.currency,
+                ]
+            });
+            (
+                &["id", "name", "balance", "cur"][..],
+                rows.collect(),
+                p.total,
+            )
+        }),
+        Listing::Categories => store.get_categories_page(user_id, page).await.map(|p| {
+            let rows = p.items.into_iter().map(|cat| {
+                vec![
+                    cat.id.unwrap_or_default().to_string(),
+                    cat.name,
+                    cat.description,
+                ]
+            });
+            (&["id", "name", "description"][..], rows.collect(), p.total)
+        }),
+        Listing::Expenses => store
+            .get_expense_page(user_id, period, tag, page)
+            .await
+            .map(|p| {
+                let rows = p.items.into_iter().map(|exp| {
+                    vec![
+                        exp.id.to_string(),
+                        exp.occurred_at.format("%Y-%m-%d").to_string(),
+                        exp.account,
+                        exp.category,
+                        exp.amount.to_string(),
+                        exp.note.to_string(),
+                    ]
+                });
+                (
+                    &["id", "date", "account", "category", "amount", "note"][..],
+                    rows.collect(),
+                    p.total,
+                )
+            }),
+        Listing::Income => store
+            .get_income_page(user_id, period, tag, page)
+            .await
+            .map(|p| {
+                let rows = p.items.into_iter().map(|inc| {
+                    vec![
+                        inc.id.to_string(),
+                        inc.occurred_at.format("%Y-%m-%d").to_string(),
+                        inc.account,
+                        inc.category,
+                        inc.amount.to_string(),
+                        inc.note.to_string(),
+                    ]
+                });
+                (
+                    &["id", "date", "account", "category", "amount", "note"][..],
+                    rows.collect(),
+                    p.total,
+                )
+            }),
+        Listing::Transfers => store
+            .get_transfers_page(user_id, period, page)
+            .await
+            .map(|p| {
+                let rows = p.items.into_iter().map(|tr| {
+                    vec![
+                        tr.id.to_string(),
+                        tr.occurred_at.format("%Y-%m-%d").to_string(),
+                        tr.from_account,
+                        tr.to_account,
+                        tr.amount.to_string(),
+                    ]
+                });
+                (
+                    &["id", "date", "from", "to", "amount"][..],
+                    rows.collect(),
+                    p.total,
+                )
+            }),
+    }
+}
+
+/// Text and page buttons of one listing page.
+pub async fn render_page(
+    store: &dyn FinanceStore,
+    user_id: i64,
+    mut request: PageRequest,
+) -> Result<(String, Option<InlineKeyboardMarkup>), FinanceError> {
+    let (headers, mut rows, mut total) = load_rows(store, user_id, request).await?;
+    // Rows may have been deleted since the page buttons were sent.
+    if rows.is_empty() && request.number > 0 {
+        request.number = 0;
+        (_, rows, total) = load_rows(store, user_id, request).await?;
+    }
+    if rows.is_empty() {
+        return Ok(("Список пуст".to_string(), None));
+    }
+
+    let pages = (total + page_size() - 1) / page_size();
+    let text = format!(
+        "<pre>{table}</pre>\nСтраница {page}/{pages}",
+        table = html::escape(&render_table(headers, &rows)),
+        page = request.number + 1
+    );
+
+    let mut buttons = vec![];
+    if request.number > 0 {
+        let previous = PageRequest {
+            number: request.number - 1,
+            ..request
+        };
+        buttons.push(InlineKeyboardButton::callback(
+            "◀️ Назад",
+            previous.to_callback_data(),
+        ));
+    }
+    if request.number + 1 < pages {
+        let next = PageRequest {
+            number: request.number + 1,
+            ..request
+        };
+        buttons.push(InlineKeyboardButton::callback(
+            "Вперед ▶️",
+            next.to_callback_data(),
+        ));
+    }
+    let keyboard = (!buttons.is_empty()).then(|| InlineKeyboardMarkup::new([buttons]));
+
+    Ok((text, keyboard))
+}
+
+/// First page of a listing.
+pub async fn listing_reply(
+    store: &dyn FinanceStore,
+    user_id: i64,
+    listing: Listing,
+    period: Period,
+    tag: Option<String>,
+) -> Reply {
+    let tag_id = match tag {
+        Some(tag) => match store
+            .save_callback_payload(user_id, format!("#{tag}"))
+            .await
+        {
+            Ok(id) => Some(id),
+            Err(e) => return error_message(e).into(),
+        },
+        None => None,
+    };
+    let request = PageRequest {
+        listing,
+        period,
+        tag_id,
+        number: 0,
+    };
+    match render_page(store, user_id, request).await {
+        Ok((text, keyboard)) => Reply {
+            text,
+            html: true,
+            keyboard,
+            document: None,
+            photo: None,
+        },
+        Err(e) => error_message(e).into(),
+    }
+}
+
+/// Handles the page buttons under listings by editing the listing in place.
+pub async fn page_callback_handler(bot: Bot, q: CallbackQuery, store: Store) -> ResponseResult<()> {
+    let request = q.data.as_deref().and_then(PageRequest::from_callback_data);
+    if let (Some(request), Some(message)) = (request, &q.message) {
+        match render_page(&*store, message.chat.id.0, request).await {
+            Ok((text, keyboard)) => {
+                let mut edit = bot
+                    .edit_message_text(message.chat.id, message.id, text)
+                    .parse_mode(ParseMode::Html);
+                if let Some(keyboard) = keyboard {
+                    edit = edit.reply_markup(keyboard);
+                }
+                edit.await?;
+            }
+            Err(e) => {
+                bot.send_message(message.chat.id, error_message(e)).await?;
+            }
+        }
+    }
+    bot.answer_callback_query(q.id).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::logic::store::tests::setup_user;
+
+    #[test]
+    fn callback_data_round_trips() {
+        let request = PageRequest {
+            listing: Listing::Expenses,
+            period: "2026-09-01..2026-09-15".parse().unwrap(),
+            tag_id: None,
+            number: 3,
+        };
+        let data = request.to_callback_data();
+        assert_eq!(data, "page:exp:3:2026-09-01..2026-09-15");
+        assert_eq!(PageRequest::from_callback_data(&data), Some(request));
+
+        let request = PageRequest {
+            listing: Listing::Income,
+            period: "2026-09-01..2026-09-15".parse().unwrap(),
+            tag_id: Some(i64::MAX),
+            number: 999,
+        };
+        let data = request.to_callback_data();
+        assert!(data.len() <= 64, "{data}");
+        assert_eq!(PageRequest::from_callback_data(&data), Some(request));
+    }
+
+    #[tokio::test]
+    async fn page_buttons_keep_a_long_tag() {
+        let store = MemoryStore::default();
+        let user_id = setup_user(&store, 0).await;
+        let tag = "командировка_в_новосибирск";
+        for amount in 1..=page_size() + 1 {
+            let entry = NewEntry {
+                amount: Money(amount),
+                category: "food".to_string(),
+                account: "card".to_string(),
+                date: None,
+                import_ref: None,
+                note: Note::parse(&format!("#{tag}")),
+            };
+            store.add_entry(user_id, false, entry).await.unwrap();
+        }
+        store
+            .add_expense(user_id, Money(1000), "food".into(), "card".into(), None)
+            .await
+            .unwrap();
+
+        let reply = listing_reply(
+            &store,
+            user_id,
+            Listing::Expenses,
+            Period::All,
+            Some(tag.to_string()),
+        )
+        .await;
+        assert!(reply.text.contains("Страница 1/2"), "{}", reply.text);
+        let keyboard = reply.keyboard.expect("page buttons");
+        let data = match &keyboard.inline_keyboard[0][0].kind {
+            teloxide::types::InlineKeyboardButtonKind::CallbackData(data) => data.clone(),
+            kind => panic!("unexpected button {kind:?}"),
+        };
+        let request = PageRequest::from_callback_data(&data).unwrap();
+        let (text, _) = render_page(&store, user_id, request).await.unwrap();
+        assert!(text.contains("Страница 2/2"), "{text}");
+        assert!(!text.contains("10.00"), "{text}");
+    }
+
+    #[test]
+    fn rejects_foreign_callback_data() {
+        assert_eq!(PageRequest::from_callback_data("undo:5"), None);
+        assert_eq!(PageRequest::from_callback_data("page:xyz:0:all"), None);
+        assert_eq!(PageRequest::from_callback_data("page:exp:-1:all"), None);
+        assert_eq!(PageRequest::from_callback_data("page:exp:1:never"), None);
+        assert_eq!(PageRequest::from_callback_data("page:exp:1:all:work"), None);
+    }
+
+    #[test]
+    fn renders_aligned_table() {
+        let rows = vec![
+            vec!["1".to_string(), "кафе".to_string(), "199.90".to_string()],
+            vec!["12".to_string(), "такси".to_string(), "5.00".to_string()],
+        ];
+        let table = render_table(&["id", "category", "amount"], &rows);
+        assert_eq!(
+            table,
+            "id | category | amount\n\
+             ---+----------+-------\n\
+             1  | кафе     | 199.90\n\
+             12 | такси    | 5.00"
+        );
+    }
+
+    #[test]
+    fn longest_page_fits_a_message() {
+        let cell = "я".repeat(MAX_CELL_CHARS * 10);
+        let row = vec![
+            i64::MAX.to_string(),
+            "2026-09-15".to_string(),
+            cell.clone(),
+            cell.clone(),
+            Money(i64::MAX).to_string(),
+            cell,
+        ];
+        let rows = vec![row; MAX_PAGE_SIZE as usize];
+        let headers = ["id", "date", "account", "category", "amount", "note"];
+        let table = render_table(&headers, &rows);
+        assert!(table.contains(&format!("{}…", "я".repeat(MAX_CELL_CHARS - 1))));
+        let text = format!("{table}\nСтраница 999/999");
+        assert!(text.chars().count() <= 4096, "{}", text.chars().count());
+    }
+}
@@ -0,0 +1,222 @@
+use super::logic::*;
+use super::names::entry_error;
+use super::{budget_warning, error_message, send_reply, Reply};
+use teloxide::{
+    prelude::*,
+    types::{InlineKeyboardButton, InlineKeyboardMarkup},
+};
+
+const QUICK_ENTRY_HELP: &str = "Не понял сообщение. Напишите расход как «кафе 200» или «200 такси с tinkoff», доход как «+50000 зарплата», или отправьте /help";
+
+/// Account of a quick entry naming none: the default one, or the only one
+/// the user has.
+async fn default_account(store: &dyn FinanceStore, user_id: i64) -> Result<String, String> {
+    match store.get_default_account(user_id).await {
+        Ok(Some(account)) => return Ok(account),
+        Ok(None) => {}
+        Err(e) => return Err(error_message(e)),
+    }
+    let mut accounts = store.get_accounts(user_id).await.map_err(error_message)?;
+    if accounts.len() == 1 {
+        return Ok(accounts.remove(0).name);
+    }
+    Err("Укажите аккаунт: «кафе 200 с <аккаунт>», или выберите аккаунт по умолчанию командой /defaultaccount <аккаунт>".to_string())
+}
+
+pub async fn default_account_handler(
+    store: &dyn FinanceStore,
+    user_id: i64,
+    account: String,
+) -> Reply {
+    let account = Args::new(&account).rest();
+    if account.is_empty() {
+        let text = match store.get_default_account(user_id).await {
+            Ok(Some(account)) => format!("Аккаунт по умолчанию: {account}"),
+            Ok(None) => {
+                "Аккаунт по умолчанию не выбран. Выбрать: /defaultaccount <аккаунт>".to_string()
+            }
+            Err(e) => error_message(e),
+        };
+        return text.into();
+    }
+
+    let text = match store
+        .set_default_account(user_id, account.to_string())
+        .await
+    {
+        Ok(()) => format!("Аккаунт по умолчанию: {account}"),
+        Err(e) => error_message(e),
+    };
+    text.into()
+}
+
+/// Records an expense or income typed as a plain message, with a button to
+/// undo it.
+pub async fn quick_entry_handler(store: &dyn FinanceStore, user_id: i64, text: &str) -> Vec<Reply> {
+    let Some(entry) = parse_quick_entry(text) else {
+        return vec![QUICK_ENTRY_HELP.into()];
+    };
+    let account = match entry.account {
+        Some(account) => account,
+        None => match default_account(store, user_id).await {
+            Ok(account) => account,
+            Err(text) => return vec![text.into()],
+        },
+    };
+
+    let QuickEntry {
+        income,
+        amount,
+        category,
+        ..
+    } = entry;
+    let entry = NewEntry {
+        amount,
+        category,
+        account,
+        date: None,
+        import_ref: None,
+        note: Note::default(),
+    };
+    let (kind, undo) = if income {
+        ("Доход", "undo:income")
+    } else {
+        ("Расход", "undo:expense")
+    };
+    let id = match store.add_entry(user_id, income, entry.clone()).await {
+        Ok(id) => id,
+        Err(e) => return vec![entry_error(store, user_id, income, &entry, e).await],
+    };
+
+    let keyboard = InlineKeyboardMarkup::new([[InlineKeyboardButton::callback(
+        "↩️ Отменить",
+        format!("{undo}:{id}"),
+    )]]);
+    let mut replies = vec![Reply {
+        keyboard: Some(keyboard),
+        ..format!(
+            "{kind} {amount} {category} ({account}) записан",
+            category = entry.category,
+            account = entry.account
+        )
+        .into()
+    }];
+    if !income {
+        if let Some(warning) = budget_warning(store, user_id, &entry).await {
+            replies.push(warning.into());
+        }
+    }
+    replies
+}
+
+/// Answers the undo button under a quick entry by deleting the entry.
+pub async fn undo_answer(store: &dyn FinanceStore, user_id: i64, data: &str) -> Reply {
+    let undo = data.strip_prefix("undo:").and_then(|rest| {
+        let (kind, id) = rest.split_once(':')?;
+        Some((kind, id.parse::<i64>().ok()?))
+    });
+    let (deleted, text) = match undo {
+        Some(("expense", id)) => (store.del_expense(user_id, id).await, "Расход отменён"),
+        Some(("income", id)) => (store.del_income(user_id, id).await, "Доход отменён"),
+        _ => return "Не удалось отменить запись".into(),
+    };
+    match deleted {
+        Ok(()) => text.into(),
+        Err(FinanceError::NotFound) => "Запись уже удалена".into(),
+        Err(e) => error_message(e).into(),
+    }
+}
+
+pub async fn quick_entry_message_handler(
+    bot: Bot,
+    msg: Message,
+    store: Store,
+) -> ResponseResult<()> {
+    let text = msg.text().unwrap_or_default();
+    for reply in quick_entry_handler(&*store, msg.chat.id.0, text).await {
+        send_reply(&bot, msg.chat.id, reply).await?;
+    }
+
+    Ok(())
+}
+
+/// Handles the undo button by replacing the confirmation with the outcome.
+pub async fn undo_callback_handler(bot: Bot, q: CallbackQuery, store: Store) -> ResponseResult<()> {
+    if let (Some(data), Some(message)) = (q.data.as_deref(), &q.message) {
+        let reply = undo_answer(&*store, message.chat.id.0, data).await;
+        bot.edit_message_text(message.chat.id, message.id, reply.text)
+            .await?;
+    }
+    bot.answer_callback_query(q.id).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::logic::store::tests::{balance, setup_user};
+
+    fn undo_data(reply: &Reply) -> String {
+        let keyboard = reply.keyboard.as_ref().expect("undo button");
+        match &keyboard.inline_keyboard[0][0].kind {
+            teloxide::types::InlineKeyboardButtonKind::CallbackData(data) => data.clone(),
+            kind => panic!("unexpected button {kind:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn quick_entries_are_recorded_and_undone() {
+        let store = MemoryStore::default();
+        let user_id = setup_user(&store, 1000000).await;
+
+        let replies = quick_entry_handler(&store, user_id, "food 200").await;
+        assert_eq!(replies[0].text, "Расход 200.00 food (card) записан");
+        assert_eq!(balance(&store, user_id).await, 1000000 - 20000);
+
+        let replies = quick_entry_handler(&store, user_id, "+500 food с card").await;
+        assert_eq!(replies[0].text, "Доход 500.00 food (card) записан");
+        assert_eq!(balance(&store, user_id).await, 1000000 + 30000);
+
+        let data = undo_data(&replies[0]);
+        assert_eq!(
+            undo_answer(&store, user_id, &data).await.text,
+            "Доход отменён"
+        );
+        assert_eq!(balance(&store, user_id).await, 1000000 - 20000);
+        assert_eq!(
+            undo_answer(&store, user_id, &data).await.text,
+            "Запись уже удалена"
+        );
+    }
+
+    #[tokio::test]
+    async fn quick_entries_use_the_default_account() {
+        let store = MemoryStore::default();
+        let user_id = setup_user(&store, 1000000).await;
+        store
+            .add_account(Accounts {
+                id: None,
+                name: "cash".to_string(),
+                balance: Money(0),
+                user_id,
+                currency: DEFAULT_CURRENCY.to_string(),
+            })
+            .await
+            .unwrap();
+
+        let replies = quick_entry_handler(&store, user_id, "food 200").await;
+        assert!(replies[0].text.starts_with("Укажите аккаунт"));
+        assert!(replies[0].keyboard.is_none());
+
+        store
+            .set_default_account(user_id, "cash".to_string())
+            .await
+            .unwrap();
+        let replies = quick_entry_handler(&store, user_id, "200 food").await;
+        assert_eq!(replies[0].text, "Расход 200.00 food (cash) записан");
+
+        let replies = quick_entry_handler(&store, user_id, "привет").await;
+        assert_eq!(replies[0].text, QUICK_ENTRY_HELP);
+    }
+}
@@ -0,0 +1,212 @@
+use super::logic::*;
+use super::{budget_warning, error_message, Reply};
+use chrono::{Local, NaiveDate};
+use std::time::Duration;
+use teloxide::prelude::*;
+
+/// How often the scheduler looks for due operations.
+const SCHEDULER_PERIOD: Duration = Duration::from_secs(60 * 60);
+
+fn kind_name(income: bool) -> &'static str {
+    if income {
+        "доход"
+    } else {
+        "расход"
+    }
+}
+
+pub async fn add_recurring_handler(
+    store: &dyn FinanceStore,
+    user_id: i64,
+    income: bool,
+    amount: Money,
+    category: String,
+    account: String,
+    schedule: Schedule,
+) -> Reply {
+    let next_run = schedule.next_on_or_after(Local::now().date_naive());
+    let recurring = NewRecurring {
+        income,
+        amount,
+        category,
+        account,
+        schedule,
+        next_run,
+    };
+    match store.add_recurring(user_id, recurring).await {
+        Ok(()) => format!(
+            "Регулярный {} добавлен, {schedule}. Первый раз он будет записан {next_run}",
+            kind_name(income)
+        )
+        .into(),
+        Err(e) => error_message(e).into(),
+    }
+}
+
+pub async fn recurring_handler(store: &dyn FinanceStore, user_id: i64) -> Reply {
+    match store.get_recurring(user_id).await {
+        Ok(recurring) if recurring.is_empty() => {
+            "Регулярных операций нет. Добавить: /addrecurring".into()
+        }
+        Ok(recurring) => {
+            let mut text = "Регулярные операции:\n".to_string();
+            for r in recurring {
+                text += &format!(
+                    "{}. {} {} {} ({}), {}, следующий раз {}\n",
+                    r.id,
+                    kind_name(r.income),
+                    r.amount,
+                    r.category,
+                    r.account,
+                    r.schedule,
+                    r.next_run
+                );
+            }
+            text.into()
+        }
+        Err(e) => error_message(e).into(),
+    }
+}
+
+pub async fn del_recurring_handler(store: &dyn FinanceStore, user_id: i64, id: i64) -> Reply {
+    let text = match store.del_recurring(user_id, id).await {
+        Ok(()) => "Регулярная операция удалена".to_string(),
+        Err(FinanceError::NotFound) => "Регулярная операция с таким id не найдена".to_string(),
+        Err(e) => error_message(e),
+    };
+    text.into()
+}
+
+/// Books every recurring operation due by `today`, one for each day of its
+/// schedule missed since, and returns the messages for their users.
+pub async fn book_due_recurring(
+    store: &dyn FinanceStore,
+    today: NaiveDate,
+) -> Result<Vec<(i64, String)>, FinanceError> {
+    let mut messages = vec![];
+    for recurring in store.due_recurring(today).await? {
+        let Recurring {
+            id,
+            user_id,
+            income,
+            amount,
+            ..
+        } = recurring;
+        let kind = kind_name(income);
+        let mut run = recurring.next_run;
+        while run <= today {
+            match store.book_recurring_run(user_id, id, run).await {
+                Ok(true) => {
+                    messages.push((
+                        user_id,
+                        format!(
+                            "Записан регулярный {kind} {amount} {} ({}) за {run}",
+                            recurring.category, recurring.account
+                        ),
+                    ));
+                    if !income {
+                        let entry = recurring.entry(run);
+                        if let Some(warning) = budget_warning(store, user_id, &entry).await {
+                            messages.push((user_id, warning));
+                        }
+                    }
+                }
+                // Booked meanwhile by another instance of the bot.
+                Ok(false) => break,
+                // The day stays due and is booked on the next round.
+                Err(FinanceError::Database(e)) => {
+                    log::error!("Failed to book recurring operation {id}: {e}");
+                    break;
+                }
+                // The day is skipped, so the operation isn't retried forever.
+                Err(e) => {
+                    messages.push((
+                        user_id,
+                        format!(
+                            "Не удалось записать регулярный {kind} {id} за {run}. {}",
+                            error_message(e)
+                        ),
+                    ));
+                    let next_run = recurring.schedule.next_after(run);
+                    if let Err(e) = store.set_recurring_next_run(user_id, id, next_run).await {
+                        log::error!("Failed to move recurring operation {id} on: {e}");
+                        break;
+                    }
+                }
+            }
+            run = recurring.schedule.next_after(run);
+        }
+    }
+    Ok(messages)
+}
+
+/// Books due recurring operations right away, catching up on the days missed
+/// while the bot was down, and then every hour.
+pub async fn recurring_scheduler(bot: Bot, store: Store) {
+    let mut interval = tokio::time::interval(SCHEDULER_PERIOD);
+    loop {
+        interval.tick().await;
+        let messages = match book_due_recurring(&*store, Local::now().date_naive()).await {
+            Ok(messages) => messages,
+            Err(e) => {
+                log::error!("Failed to book recurring operations: {e}");
+                continue;
+            }
+        };
+        for (user_id, text) in messages {
+            if let Err(e) = bot.send_message(ChatId(user_id), text).await {
+                log::warn!("Failed to notify {user_id} of a recurring operation: {e}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::logic::store::tests::{balance, setup_user};
+
+    #[tokio::test]
+    async fn missed_runs_are_caught_up() {
+        let store = MemoryStore::default();
+        let user_id = setup_user(&store, 1000000).await;
+        let day = |d: u32| NaiveDate::from_ymd_opt(2026, 10, d).unwrap();
+        let recurring = NewRecurring {
+            income: false,
+            amount: Money(100000),
+            category: "food".to_string(),
+            account: "card".to_string(),
+            schedule: Schedule::Weekly(chrono::Weekday::Mon),
+            next_run: day(5),
+        };
+        store.add_recurring(user_id, recurring).await.unwrap();
+
+        // Mondays 5th, 12th and 19th passed while the bot was down.
+        let messages = book_due_recurring(&store, day(20)).await.unwrap();
+        let texts: Vec<&str> = messages.iter().map(|(_, text)| text.as_str()).collect();
+        assert_eq!(
+            texts,
+            [
+                "Записан регулярный расход 1000.00 food (card) за 2026-10-05",
+                "Записан регулярный расход 1000.00 food (card) за 2026-10-12",
+                "Записан регулярный расход 1000.00 food (card) за 2026-10-19",
+            ]
+        );
+        assert!(messages.iter().all(|(user, _)| *user == user_id));
+        assert_eq!(balance(&store, user_id).await, 1000000 - 3 * 100000);
+        let recurring = store.get_recurring(user_id).await.unwrap();
+        assert_eq!(recurring[0].next_run, day(26));
+
+        assert!(book_due_recurring(&store, day(20))
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            recurring_handler(&store, user_id).await.text,
+            format!(
+                "Регулярные операции:\n{}. расход 1000.00 food (card), еженедельно, понедельник, следующий раз 2026-10-26\n",
+                recurring[0].id
+            )
+        );
+    }
+}
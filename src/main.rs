@@ -1,11 +1,17 @@
 pub mod handlers;
 
 use dotenv::dotenv;
+use handlers::backup::*;
+use handlers::dialogue::*;
+use handlers::duplicate::*;
+use handlers::import::*;
 use handlers::logic::*;
+use handlers::names::*;
+use handlers::pages::*;
+use handlers::quick::*;
+use handlers::recurring::*;
 use handlers::*;
-use teloxide::prelude::*;
-
-use sqlx::postgres::PgPool;
+use teloxide::{dispatching::dialogue::ErasedStorage, prelude::*};
 
 #[tokio::main]
 async fn main() {
@@ -13,10 +19,10 @@ async fn main() {
     dotenv().ok();
     log::info!("Starting throw dice bot...");
 
-    let pool = match get_sqlx_database_client().await {
-        Ok(pool) => {
+    let store = match get_sqlx_database_client().await {
+        Ok(store) => {
             println!("✅Connection to the database is successful!");
-            pool
+            store
         }
         Err(err) => {
             println!("🔥 Failed to connect to the database: {:?}", err);
@@ -26,65 +32,107 @@ async fn main() {
 
     let bot = Bot::from_env();
     println!("🚀 Bot started successfully");
-    // Command::repl(bot, move |bot, msg, cmd| answer(bot, msg, cmd, Database{pool: pool.clone()})).await;
-    Command::repl(bot, move |bot, msg, cmd| {
-        answer(bot, msg, cmd, pool.clone())
-    })
-    .await;
-}
-
-async fn answer(bot: Bot, msg: Message, cmd: Command, pool: PgPool) -> ResponseResult<()> {
-    match cmd {
-        Command::Help => help_handler(bot, msg).await?,
-
-        Command::Start => start_handler(bot, msg).await?,
-
-        Command::Accounts => accounts_handler(bot, msg, pool).await?,
-
-        Command::AddAccount { name, balance } => {
-            add_account_handler(bot, msg, pool, name, balance).await?
-        }
-
-        Command::Total => total_handler(bot, msg, pool).await?,
-
-        Command::AddCategory { name, description } => {
-            add_category_handler(bot, msg, pool, name, description).await?
-        }
-
-        Command::Categories => categories_handler(bot, msg, pool).await?,
-
-        Command::DelAccount(id) => del_account_handler(bot, msg, pool, id).await?,
 
-        Command::DelCategory(id) => del_category_handler(bot, msg, pool, id).await?,
-
-        Command::EditAccount { id, name, balance } => {
-            edit_account_handler(bot, msg, pool, id, name, balance).await?
-        }
-
-        Command::EditCategory {
-            id,
-            name,
-            description,
-        } => edit_category_handler(bot, msg, pool, id, name, description).await?,
-
-        Command::Expenses => expense_handler(bot, msg, pool).await?,
-
-        Command::Income  => income_handler(bot, msg, pool).await?,
-       
-        Command::AddExpense {
-            amount,
-            category,
-            account,
-        } => add_expense_handler(bot, msg, pool, amount, category, account).await?,
-        Command::AddIncome {
-            amount,
-            category,
-            account,
-        } => add_income_handler(bot, msg, pool, amount, category, account).await?,
+    tokio::spawn(recurring_scheduler(bot.clone(), store.clone()));
+
+    let handler = dptree::entry()
+        .branch(
+            Update::filter_message()
+                .filter_command::<Command>()
+                .endpoint(answer),
+        )
+        .branch(
+            Update::filter_message()
+                .enter_dialogue::<Message, ErasedStorage<AddExpenseState>, AddExpenseState>()
+                .branch(
+                    dptree::filter(|msg: Message| msg.text().is_some_and(is_add_expense))
+                        .endpoint(add_expense_dialogue_start),
+                )
+                .branch(
+                    dptree::filter(|msg: Message, state: AddExpenseState| {
+                        state != AddExpenseState::Idle
+                            && msg.text().is_some_and(|t| !t.starts_with('/'))
+                    })
+                    .endpoint(add_expense_dialogue_message),
+                ),
+        )
+        .branch(
+            Update::filter_message()
+                .filter(|msg: Message| msg.text().is_some_and(|t| t.starts_with('/')))
+                .endpoint(command_error_handler),
+        )
+        .branch(
+            Update::filter_message()
+                .filter(|msg: Message| msg.document().is_some_and(is_backup_document))
+                .endpoint(restore_document_handler),
+        )
+        .branch(
+            Update::filter_message()
+                .filter(|msg: Message| msg.document().is_some())
+                .endpoint(document_handler),
+        )
+        .branch(
+            Update::filter_message()
+                .filter(|msg: Message| msg.text().is_some_and(|t| !t.starts_with('/')))
+                .endpoint(quick_entry_message_handler),
+        )
+        .branch(
+            Update::filter_callback_query()
+                .filter(|q: CallbackQuery| {
+                    q.data.as_deref().is_some_and(|d| d.starts_with("import:"))
+                })
+                .endpoint(import_callback_handler),
+        )
+        .branch(
+            Update::filter_callback_query()
+                .filter(|q: CallbackQuery| {
+                    q.data.as_deref().is_some_and(|d| d.starts_with("restore:"))
+                })
+                .endpoint(restore_callback_handler),
+        )
+        .branch(
+            Update::filter_callback_query()
+                .filter(|q: CallbackQuery| q.data.as_deref().is_some_and(|d| d.starts_with("dup:")))
+                .endpoint(duplicate_callback_handler),
+        )
+        .branch(
+            Update::filter_callback_query()
+                .filter(|q: CallbackQuery| {
+                    q.data.as_deref().is_some_and(|d| d.starts_with("addexp:"))
+                })
+                .enter_dialogue::<CallbackQuery, ErasedStorage<AddExpenseState>, AddExpenseState>()
+                .endpoint(add_expense_dialogue_callback),
+        )
+        .branch(
+            Update::filter_callback_query()
+                .filter(|q: CallbackQuery| q.data.as_deref().is_some_and(|d| d.starts_with("fix:")))
+                .endpoint(suggestion_callback_handler),
+        )
+        .branch(
+            Update::filter_callback_query()
+                .filter(|q: CallbackQuery| {
+                    q.data.as_deref().is_some_and(|d| d.starts_with("undo:"))
+                })
+                .endpoint(undo_callback_handler),
+        )
+        .branch(Update::filter_callback_query().endpoint(page_callback_handler));
+
+    Dispatcher::builder(bot, handler)
+        .dependencies(dptree::deps![
+            DialogueStore::erased(store.clone()),
+            store,
+            ImportDrafts::default(),
+            RestoreDrafts::default()
+        ])
+        .enable_ctrlc_handler()
+        .build()
+        .dispatch()
+        .await;
+}
 
-         //--------------------------------------
-        Command::DelExp(id) => del_expense_handler(bot, msg, pool, id).await?,
-        Command::DelInc(id) => del_income_handler(bot, msg, pool, id).await?,
+async fn answer(bot: Bot, msg: Message, cmd: Command, store: Store) -> ResponseResult<()> {
+    for reply in handle(&*store, msg.chat.id.0, cmd).await {
+        send_reply(&bot, msg.chat.id, reply).await?;
     }
 
     Ok(())